@@ -1,4 +1,4 @@
-import init, { Config, App } from "./reaction-diffusion.js";
+import init, { Config, App, Colormap } from "./reaction-diffusion.js";
 
 const inputs = {
   diffusionA: document.getElementById("diffusion-a"),
@@ -54,15 +54,18 @@ function initUI(updater, canvas) {
 
   // set event listeners
   updateCallbacks = {
-    diffusionA: updater.setDiffusionA.bind(updater),
-    diffusionB: updater.setDiffusionB.bind(updater),
+    diffusionA: (value) => updater.setDiffusion(0, value),
+    diffusionB: (value) => updater.setDiffusion(1, value),
     feed: updater.setFeed.bind(updater),
     kill: updater.setKill.bind(updater),
     stepsPerFrame: updater.setStepsPerFrame.bind(updater),
     timestep: updater.setTimestep.bind(updater),
   };
 
-  for (const key of configKeys) {
+  // one slider per parameter of the reactions
+  const parameterKeys = initParameters(updater);
+
+  for (const key of [...configKeys, ...parameterKeys]) {
     // set initial value
     setValue(key, state[key]);
     inputs[key].addEventListener("input", (event) => {
@@ -76,6 +79,83 @@ function initUI(updater, canvas) {
   startButton.addEventListener("click", updater.start.bind(updater));
   const pauseButton = document.getElementById("pause");
   pauseButton.addEventListener("click", updater.pause.bind(updater));
+  const stepButton = document.getElementById("step");
+  const stepCount = document.getElementById("step-count");
+  stepButton.addEventListener("click", () => {
+    updater.step(Math.max(1, stepCount.valueAsNumber || 1));
+  });
+  const colormapSelect = document.getElementById("colormap");
+  colormapSelect.addEventListener("change", (event) => {
+    updater.setColormap(Colormap[event.target.value]);
+  });
+  const hudButton = document.getElementById("hud");
+  hudButton.addEventListener("click", updater.toggleHud.bind(updater));
+  const maxStepsInput = document.getElementById("max-steps");
+  maxStepsInput.addEventListener("change", (event) => {
+    const value = event.target.valueAsNumber;
+    updater.setMaxSteps(Number.isNaN(value) ? undefined : BigInt(value));
+  });
+  updater.onTargetReached((stats) => {
+    console.log(`Target reached at step ${stats.step_number}`);
+    stats.free();
+  });
+  updater.onDivergence((divergence) => {
+    console.warn(
+      `Simulation diverged at step ${divergence.step_number}` +
+        (divergence.rolled_back ? ", rolled back" : ""),
+    );
+    divergence.free();
+  });
+
+  // poll the stats of the simulation
+  const statsElement = document.getElementById("stats");
+  setInterval(() => showStats(statsElement, updater.stats()), 500);
+}
+
+// add a slider for each parameter of the reactions, ranging from 0 to twice its initial value
+function initParameters(updater) {
+  const container = document.getElementById("parameters");
+  const keys = [];
+  for (const name of updater.parameters()) {
+    const key = `parameter-${name}`;
+    const value = updater.parameterValue(name);
+
+    const slider = document.createElement("div");
+    slider.className = "settings-slider";
+    const label = document.createElement("label");
+    label.htmlFor = key;
+    label.textContent = name;
+    const input = document.createElement("input");
+    input.id = key;
+    input.type = "range";
+    input.min = "0";
+    input.max = (2 * Math.abs(value) || 1).toString();
+    input.step = "any";
+    const output = document.createElement("span");
+    slider.append(label, input, output);
+    container.appendChild(slider);
+
+    inputs[key] = input;
+    outputs[key] = output;
+    state[key] = value;
+    updateCallbacks[key] = (value) => updater.setParameter(name, value);
+    keys.push(key);
+  }
+  return keys;
+}
+
+function showStats(element, stats) {
+  element.textContent = [
+    `FPS: ${stats.fps.toFixed(1)}`,
+    `Steps/s: ${stats.steps_per_second.toFixed(0)}`,
+    `Step: ${stats.step_number}`,
+    `Time: ${stats.time.toFixed(1)}`,
+    `Grid: ${stats.width}x${stats.height}`,
+    `Seed: ${stats.seed}`,
+    `Stable timestep: ${stats.stable_timestep.toFixed(3)}`,
+    stats.paused ? "Paused" : "Running",
+  ].join("\n");
+  stats.free();
 }
 
 function isWebGPUAvailable() {
@@ -0,0 +1,136 @@
+/// Alternating direction implicit solver, treating the diffusion with a Peaceman-Rachford step
+/// and the reaction explicitly.
+///
+/// Each step runs the reaction into the stage buffer, then a half step implicit along the rows
+/// and another one implicit along the columns. Each half step builds and solves a tridiagonal
+/// system per line, by cyclic reduction in a workgroup, with the walls given by `Boundary`.
+pub(crate) struct Adi {
+    reaction_pipeline: wgpu::ComputePipeline,
+    rows_pipeline: wgpu::ComputePipeline,
+    stage_pipeline: wgpu::ComputePipeline,
+    columns_pipeline: wgpu::ComputePipeline,
+    store_pipeline: wgpu::ComputePipeline,
+    bind_group: wgpu::BindGroup,
+    width: u32,
+    height: u32,
+}
+
+impl Adi {
+    pub(crate) const SHADER: &'static str = include_str!("adi.wgsl");
+
+    /// Create the solver. `shader` is the source of the diffusion shader with `SHADER` appended,
+    /// and the layouts are those of the groups 0 and 1 of the diffusion pipelines.
+    pub(crate) fn new(
+        device: &wgpu::Device,
+        shader: String,
+        diffusion_layout: &wgpu::BindGroupLayout,
+        aux_layout: &wgpu::BindGroupLayout,
+        width: u32,
+        height: u32,
+    ) -> Self {
+        let size = (width * height) as wgpu::BufferAddress;
+        let create_buffer = |label, cell_size: usize| {
+            device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some(label),
+                size: size * cell_size as u64,
+                usage: wgpu::BufferUsages::STORAGE,
+                mapped_at_creation: false,
+            })
+        };
+        let stage = create_buffer("ADI stage", std::mem::size_of::<[f32; 2]>());
+        // lower, diagonal, upper and right hand side of each species
+        let system = create_buffer("ADI system", std::mem::size_of::<[[f32; 2]; 4]>());
+
+        let storage_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only: false },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("ADI BindGroupLayout"),
+            entries: &[
+                // stage
+                storage_entry(0),
+                // system
+                storage_entry(1),
+            ],
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("ADI BindGroup"),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: stage.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: system.as_entire_binding(),
+                },
+            ],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("ADI PipelineLayout"),
+            bind_group_layouts: &[diffusion_layout, aux_layout, &bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("ADI Shader"),
+            source: wgpu::ShaderSource::Wgsl(shader.into()),
+        });
+        let create_compute_pipeline = |label, entry_point| {
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some(label),
+                layout: Some(&pipeline_layout),
+                module: &shader,
+                entry_point: Some(entry_point),
+                compilation_options: Default::default(),
+                cache: None,
+            })
+        };
+
+        Self {
+            reaction_pipeline: create_compute_pipeline(
+                "ADI reaction ComputePipeline",
+                "adi_reaction",
+            ),
+            rows_pipeline: create_compute_pipeline("ADI rows ComputePipeline", "adi_rows"),
+            stage_pipeline: create_compute_pipeline("ADI stage ComputePipeline", "adi_stage"),
+            columns_pipeline: create_compute_pipeline("ADI columns ComputePipeline", "adi_columns"),
+            store_pipeline: create_compute_pipeline("ADI store ComputePipeline", "adi_store"),
+            bind_group,
+            width,
+            height,
+        }
+    }
+
+    /// Record one step, reading the field bound in `field_bind_group` and writing its other copy.
+    /// The bind group 1 of the diffusion pipelines must already be set.
+    pub(crate) fn step(
+        &self,
+        compute_pass: &mut wgpu::ComputePass,
+        field_bind_group: &wgpu::BindGroup,
+    ) {
+        let workgroups = (self.width * self.height).div_ceil(64);
+        compute_pass.set_bind_group(0, field_bind_group, &[]);
+        compute_pass.set_bind_group(2, &self.bind_group, &[]);
+
+        compute_pass.set_pipeline(&self.reaction_pipeline);
+        compute_pass.dispatch_workgroups(workgroups, 1, 1);
+        // one workgroup per line
+        compute_pass.set_pipeline(&self.rows_pipeline);
+        compute_pass.dispatch_workgroups(self.height, 1, 1);
+        compute_pass.set_pipeline(&self.stage_pipeline);
+        compute_pass.dispatch_workgroups(workgroups, 1, 1);
+        compute_pass.set_pipeline(&self.columns_pipeline);
+        compute_pass.dispatch_workgroups(self.width, 1, 1);
+        compute_pass.set_pipeline(&self.store_pipeline);
+        compute_pass.dispatch_workgroups(workgroups, 1, 1);
+    }
+}
@@ -0,0 +1,222 @@
+// Alternating direction implicit solver: the reaction is integrated explicitly, then the
+// diffusion with a Peaceman-Rachford step, implicit along the rows and explicit along the columns
+// for the first half of the timestep, and the other way around for the second half. This file is
+// appended to `diffusion.wgsl`, whose helpers it uses.
+//
+// Each implicit half step solves a tridiagonal system per line, by cyclic reduction within a
+// workgroup.
+
+// Equation of a cell in the tridiagonal system of its line, one per species
+struct Row {
+    lower: vec2<f32>,
+    diagonal: vec2<f32>,
+    upper: vec2<f32>,
+    // right hand side, replaced by the solution
+    rhs: vec2<f32>,
+};
+
+// Concentrations at the start of each half step
+@group(2) @binding(0) var<storage, read_write> stage: array<vec2<f32>>;
+@group(2) @binding(1) var<storage, read_write> system: array<Row>;
+
+const ADI_WORKGROUP: u32 = 64;
+
+// Boundary conditions, see `Boundary` in `config.rs`
+const NEUMANN: u32 = 0;
+const DIRICHLET: u32 = 1;
+// Concentrations outside the walls with Dirichlet boundaries, the background of the species
+fn dirichlet_value() -> vec2<f32> {
+    return config.boundary_values.xy;
+}
+
+// Concentration of a neighbour outside the walls, next to a cell of concentration `ab`
+fn outside(ab: vec2<f32>) -> vec2<f32> {
+    return select(ab, dirichlet_value(), config.boundary == DIRICHLET);
+}
+
+// Diffusion of a half step along an axis of weight `weight`, for each species
+fn diffusion_number(weight: f32) -> vec2<f32> {
+    return 0.5 * config.timestep * weight * config.diffusion.xy;
+}
+
+// Concentrations after the reaction and the stochastic noise
+@compute @workgroup_size(64)
+fn adi_reaction(@builtin(global_invocation_id) global_invocation_id: vec3<u32>) {
+    let i = global_invocation_id.x;
+    if i >= config.size {
+        return;
+    }
+    let ab = load(i % config.width, i / config.width);
+    stage[i] = update(ab, vec2<f32>(0.0)) + noise(ab, i, step_counter);
+}
+
+// Solve the system of the line of `n` cells at `start`, with `stride` between consecutive cells.
+//
+// The forward reduction eliminates the odd equations level after level, until a single one is
+// left, then the back substitution solves the equations in the reverse order, each one using the
+// solutions of the level above.
+fn cyclic_reduction(local_index: u32, start: u32, stride: u32, n: u32) {
+    var s = 1u;
+    loop {
+        if 2 * s > n {
+            break;
+        }
+        for (var k = local_index; 2 * s * k + 2 * s - 1 < n; k += ADI_WORKGROUP) {
+            let i = 2 * s * k + 2 * s - 1;
+            let row = system[start + i * stride];
+            let previous = system[start + (i - s) * stride];
+            let alpha = -row.lower / previous.diagonal;
+            var result = Row(
+                alpha * previous.lower,
+                row.diagonal + alpha * previous.upper,
+                vec2<f32>(0.0),
+                row.rhs + alpha * previous.rhs,
+            );
+            if i + s < n {
+                let next = system[start + (i + s) * stride];
+                let gamma = -row.upper / next.diagonal;
+                result.diagonal += gamma * next.lower;
+                result.upper = gamma * next.upper;
+                result.rhs += gamma * next.rhs;
+            }
+            system[start + i * stride] = result;
+        }
+        storageBarrier();
+        s *= 2;
+    }
+
+    loop {
+        for (var k = local_index; 2 * s * k + s - 1 < n; k += ADI_WORKGROUP) {
+            let i = 2 * s * k + s - 1;
+            let row = system[start + i * stride];
+            var rhs = row.rhs;
+            if i >= s {
+                rhs -= row.lower * system[start + (i - s) * stride].rhs;
+            }
+            if i + s < n {
+                rhs -= row.upper * system[start + (i + s) * stride].rhs;
+            }
+            system[start + i * stride].rhs = rhs / row.diagonal;
+        }
+        storageBarrier();
+        if s == 1 {
+            break;
+        }
+        s /= 2;
+    }
+}
+
+// Half step along the line `line`, implicit along the rows if `rows` and along the columns
+// otherwise, reading `stage` and leaving the result in the right hand sides of `system`
+fn sweep(line: u32, local_index: u32, rows: bool) {
+    let width = config.width;
+    let height = config.height;
+    var n = height;
+    var lines = width;
+    var start = line;
+    var stride = width;
+    // diffusion numbers along the line, treated implicitly, and across it, treated explicitly
+    var along = diffusion_number(config.weight_y);
+    var across = diffusion_number(config.weight_x);
+    if rows {
+        n = width;
+        lines = height;
+        start = line * width;
+        stride = 1u;
+        along = diffusion_number(config.weight_x);
+        across = diffusion_number(config.weight_y);
+    }
+    if line >= lines {
+        return;
+    }
+
+    for (var p = local_index; p < n; p += ADI_WORKGROUP) {
+        let i = start + p * stride;
+        let ab = stage[i];
+
+        // explicit second difference across the line
+        let x = i % width;
+        let y = i / width;
+        var across_previous = outside(ab);
+        var across_next = outside(ab);
+        if rows {
+            if y > 0 {
+                across_previous = stage[i - width];
+            }
+            if y < height - 1 {
+                across_next = stage[i + width];
+            }
+        } else {
+            if x > 0 {
+                across_previous = stage[i - 1];
+            }
+            if x < width - 1 {
+                across_next = stage[i + 1];
+            }
+        }
+        var rhs = ab + across * (across_previous + across_next - 2.0 * ab);
+
+        // implicit second difference along the line
+        var lower = -along;
+        var diagonal = 1.0 + 2.0 * along;
+        var upper = -along;
+        if p == 0 {
+            lower = vec2<f32>(0.0);
+            if config.boundary == DIRICHLET {
+                rhs += along * dirichlet_value();
+            } else {
+                diagonal -= along;
+            }
+        }
+        if p == n - 1 {
+            upper = vec2<f32>(0.0);
+            if config.boundary == DIRICHLET {
+                rhs += along * dirichlet_value();
+            } else {
+                diagonal -= along;
+            }
+        }
+        system[i] = Row(lower, diagonal, upper, rhs);
+    }
+    storageBarrier();
+
+    cyclic_reduction(local_index, start, stride, n);
+}
+
+// First half step, implicit along the rows, one workgroup per row
+@compute @workgroup_size(64)
+fn adi_rows(
+    @builtin(workgroup_id) workgroup_id: vec3<u32>,
+    @builtin(local_invocation_index) local_index: u32,
+) {
+    sweep(workgroup_id.x, local_index, true);
+}
+
+// Second half step, implicit along the columns, one workgroup per column
+@compute @workgroup_size(64)
+fn adi_columns(
+    @builtin(workgroup_id) workgroup_id: vec3<u32>,
+    @builtin(local_invocation_index) local_index: u32,
+) {
+    sweep(workgroup_id.x, local_index, false);
+}
+
+// Copy the solution of the first half step to `stage`, for the second half step
+@compute @workgroup_size(64)
+fn adi_stage(@builtin(global_invocation_id) global_invocation_id: vec3<u32>) {
+    let i = global_invocation_id.x;
+    if i >= config.size {
+        return;
+    }
+    stage[i] = system[i].rhs;
+}
+
+// Copy the solution of the second half step to the output field
+@compute @workgroup_size(64)
+fn adi_store(@builtin(global_invocation_id) global_invocation_id: vec3<u32>) {
+    let i = global_invocation_id.x;
+    if i >= config.size {
+        return;
+    }
+    store(i % config.width, i / config.width, system[i].rhs);
+}
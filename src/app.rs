@@ -1,9 +1,8 @@
-use std::sync::Arc;
-use wgpu::util::DeviceExt;
+use std::sync::{Arc, Mutex};
 use winit::{
     application::ApplicationHandler,
-    dpi::PhysicalSize,
-    event::{ElementState, KeyEvent, WindowEvent},
+    dpi::{PhysicalPosition, PhysicalSize},
+    event::{ElementState, KeyEvent, MouseButton, WindowEvent},
     event_loop::{EventLoop, EventLoopProxy},
     keyboard::{KeyCode, PhysicalKey},
     window::Window,
@@ -15,26 +14,91 @@ use winit::platform::web::EventLoopExtWebSys;
 #[cfg(target_arch = "wasm32")]
 use winit::platform::web::WindowExtWebSys;
 
-use crate::config::Config;
+use crate::config::{Axis, Colormap, Config, NoiseMode, TimestepMode, View};
+use crate::device;
 use crate::diffusion::Diffusion;
 use crate::event;
+use crate::health::Divergence;
 use crate::log::log;
+use crate::overlay::{Corner, Overlay};
+use crate::renderer::FieldRenderer;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::shader_dir::ShaderDir;
+use crate::stats::{RateCounter, Stats};
+use crate::storage::Storage;
+use crate::system::System;
+use crate::target::Target;
 
 struct State {
     window: Arc<Window>,
     surface: wgpu::Surface<'static>,
+    surface_config: wgpu::SurfaceConfiguration,
     device: wgpu::Device,
     queue: wgpu::Queue,
-    vertex_buffer: wgpu::Buffer,
-    render_pipeline: wgpu::RenderPipeline,
+    field_renderer: FieldRenderer,
     diffusion: Diffusion,
+    hud: Overlay,
+    show_hud: bool,
+    help: Overlay,
+    show_help: bool,
+    shift_pressed: bool,
+    cursor_position: Option<PhysicalPosition<f64>>,
+    /// Whether dragging the mouse draws walls (`Some(true)`) or erases them (`Some(false)`)
+    drawing_walls: Option<bool>,
+    stats: Arc<Mutex<Stats>>,
+    rate_counter: RateCounter,
     steps_per_frame: u32,
     frame_number: u64,
     paused: bool,
+    /// Steps requested with `Event::Step`, run in the next frame
+    pending_steps: u32,
+    target: Target,
+    /// Shaders reloaded when their files change, see `Config::shader_dir`
+    #[cfg(not(target_arch = "wasm32"))]
+    shader_dir: Option<ShaderDir>,
+    #[cfg(target_arch = "wasm32")]
+    target_listeners: Vec<js_sys::Function>,
+    #[cfg(target_arch = "wasm32")]
+    divergence_listeners: Vec<js_sys::Function>,
 }
 
+/// Keyboard bindings of the native application, shown in the help overlay
+const KEY_BINDINGS: &[(&str, &str)] = &[
+    ("Space", "Pause/resume"),
+    (".", "Single step"),
+    ("R", "Reset"),
+    ("F/Shift+F", "Feed +/-"),
+    ("K/Shift+K", "Kill +/-"),
+    ("T/Shift+T", "Timestep +/-"),
+    ("S/Shift+S", "Steps/frame +/-"),
+    ("C", "Next colormap"),
+    ("V", "Next 3D view"),
+    ("A", "Next slice axis"),
+    ("[/]", "Previous/next slice"),
+    ("Arrows", "Rotate 3D camera"),
+    ("LMB/RMB", "Draw/erase walls"),
+    ("P", "Save screenshot"),
+    ("H", "Toggle HUD"),
+    ("F1", "Toggle help"),
+    ("Esc", "Quit"),
+];
+
 impl State {
-    async fn new(config: &Config, window: Arc<Window>) -> State {
+    /// Wide enough for the 20 digits of the largest seeds
+    const HUD_COLUMNS: u32 = 26;
+    const HUD_ROWS: u32 = 13;
+    const HELP_COLUMNS: u32 = 28;
+
+    const FEED_INCREMENT: f32 = 0.001;
+    const KILL_INCREMENT: f32 = 0.001;
+    const TIMESTEP_INCREMENT: f32 = 0.05;
+    const STEPS_PER_FRAME_INCREMENT: u32 = 5;
+    /// Rotation of the camera of the isosurface and mesh views by the arrow keys, in radians
+    const CAMERA_INCREMENT: f32 = 0.1;
+    /// Radius in cells of the brush drawing walls with the mouse
+    const MASK_BRUSH_RADIUS: u32 = 4;
+
+    async fn new(config: &Config, window: Arc<Window>, stats: Arc<Mutex<Stats>>) -> State {
         let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor::default());
         let surface = instance.create_surface(window.clone()).unwrap();
         let adapter = instance
@@ -45,94 +109,292 @@ impl State {
             .await
             .unwrap();
 
-        let (device, queue) = adapter
-            .request_device(&wgpu::DeviceDescriptor::default(), None)
+        let (device, queue) = device::request_device(&adapter, None, wgpu::Features::empty())
             .await
             .unwrap();
 
-        let mut surface_config = surface
-            .get_default_config(&adapter, config.width, config.height)
-            .unwrap();
+        let (width, height) = config.image_size();
+        let mut surface_config = surface.get_default_config(&adapter, width, height).unwrap();
         // enable vsync
         surface_config.present_mode = wgpu::PresentMode::AutoVsync;
 
-        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: Some("Shader"),
-            source: wgpu::ShaderSource::Wgsl(include_str!("shader.wgsl").into()),
-        });
-
-        let vertices = &[
-            [-1.0f32, -1.0, 0.0],
-            [1.0, -1.0, 0.0],
-            [1.0, 1.0, 0.0],
-            [-1.0, -1.0, 0.0],
-            [1.0, 1.0, 0.0],
-            [-1.0, 1.0, 0.0],
-        ];
-
-        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Vertex buffer"),
-            contents: bytemuck::cast_slice(vertices),
-            usage: wgpu::BufferUsages::VERTEX,
-        });
-
-        let vertex_buffer_layout = wgpu::VertexBufferLayout {
-            array_stride: std::mem::size_of_val(&vertices[0]) as wgpu::BufferAddress,
-            step_mode: wgpu::VertexStepMode::Vertex,
-            attributes: &[wgpu::VertexAttribute {
-                offset: 0,
-                format: wgpu::VertexFormat::Float32x3,
-                shader_location: 0,
-            }],
+        let config = Config {
+            storage: Storage::supported_layout(
+                config.storage,
+                System::species_count(config),
+                &adapter,
+            ),
+            ..config.clone()
         };
+        let diffusion = Diffusion::new(&config, &device, &queue);
+
+        let field_renderer = FieldRenderer::new(
+            &device,
+            &diffusion,
+            surface_config.format,
+            surface_config.width,
+            surface_config.height,
+        );
+
+        let hud = Overlay::new(
+            &device,
+            surface_config.format,
+            surface_config.width,
+            surface_config.height,
+            Self::HUD_COLUMNS,
+            Self::HUD_ROWS,
+            Corner::TopLeft,
+        );
+
+        let mut help = Overlay::new(
+            &device,
+            surface_config.format,
+            surface_config.width,
+            surface_config.height,
+            Self::HELP_COLUMNS,
+            KEY_BINDINGS.len() as u32,
+            Corner::TopRight,
+        );
+        let help_text: Vec<_> = KEY_BINDINGS
+            .iter()
+            .map(|(key, action)| format!("{:<11}{}", key, action))
+            .collect();
+        help.set_text(&queue, &help_text.join("\n"));
 
-        let diffusion = Diffusion::new(config, &device);
-
-        let render_pipeline_layout =
-            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                label: Some("Render Pipeline Layout"),
-                bind_group_layouts: &[diffusion.bind_group_layout()],
-                push_constant_ranges: &[],
-            });
-
-        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("Render Pipeline"),
-            layout: Some(&render_pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &shader,
-                entry_point: None,
-                buffers: &[vertex_buffer_layout],
-                compilation_options: Default::default(),
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &shader,
-                entry_point: None,
-                targets: &[Some(surface.get_capabilities(&adapter).formats[0].into())],
-                compilation_options: Default::default(),
-            }),
-            primitive: wgpu::PrimitiveState {
-                cull_mode: Some(wgpu::Face::Back),
-                ..Default::default()
-            },
-            depth_stencil: None,
-            multisample: Default::default(),
-            multiview: None,
-            cache: None,
-        });
         // TODO: this should go in resize
         surface.configure(&device, &surface_config);
 
         Self {
             window,
             surface,
+            surface_config,
             device,
             queue,
-            render_pipeline,
-            vertex_buffer,
+            field_renderer,
             diffusion,
+            hud,
+            show_hud: config.hud,
+            help,
+            show_help: false,
+            shift_pressed: false,
+            cursor_position: None,
+            drawing_walls: None,
+            stats,
+            rate_counter: RateCounter::new(),
             steps_per_frame: config.steps_per_frame,
             frame_number: 0,
             paused: false,
+            pending_steps: 0,
+            target: Target::new(&config),
+            #[cfg(not(target_arch = "wasm32"))]
+            shader_dir: config.shader_dir.clone().map(ShaderDir::new),
+            #[cfg(target_arch = "wasm32")]
+            target_listeners: Vec::new(),
+            #[cfg(target_arch = "wasm32")]
+            divergence_listeners: Vec::new(),
+        }
+    }
+
+    /// Event painting the mask under the cursor, if a mouse button is held down
+    fn mask_brush(&self) -> Option<event::Event> {
+        let wall = self.drawing_walls?;
+        let position = self.cursor_position?;
+        let (image_width, image_height) = self.diffusion.image_size();
+        let pixel = (
+            position.x * image_width as f64 / self.surface_config.width as f64,
+            position.y * image_height as f64 / self.surface_config.height as f64,
+        );
+        let (x, y) = self
+            .diffusion
+            .lattice()
+            .cell(pixel, self.diffusion.height());
+        Some(event::Event::PaintMask {
+            x,
+            y,
+            radius: Self::MASK_BRUSH_RADIUS,
+            wall,
+        })
+    }
+
+    /// Event triggered by pressing `key`, if any
+    fn key_binding(&self, key: KeyCode, repeat: bool) -> Option<event::Event> {
+        let sign = if self.shift_pressed { -1.0 } else { 1.0 };
+        let event = match key {
+            KeyCode::KeyF => event::Event::SetFeed(
+                (self.diffusion.feed() + sign * Self::FEED_INCREMENT).max(0.0),
+            ),
+            KeyCode::KeyK => event::Event::SetKill(
+                (self.diffusion.kill() + sign * Self::KILL_INCREMENT).max(0.0),
+            ),
+            KeyCode::KeyT => event::Event::SetTimestep(
+                (self.diffusion.timestep() + sign * Self::TIMESTEP_INCREMENT).max(0.0),
+            ),
+            KeyCode::KeyS => event::Event::SetStepsPerFrame(if self.shift_pressed {
+                self.steps_per_frame
+                    .saturating_sub(Self::STEPS_PER_FRAME_INCREMENT)
+            } else {
+                self.steps_per_frame + Self::STEPS_PER_FRAME_INCREMENT
+            }),
+            KeyCode::BracketLeft => {
+                event::Event::SetSlice(self.diffusion.slice().saturating_sub(1))
+            }
+            KeyCode::BracketRight => event::Event::SetSlice(self.diffusion.slice() + 1),
+            KeyCode::ArrowLeft | KeyCode::ArrowRight | KeyCode::ArrowUp | KeyCode::ArrowDown => {
+                let (yaw, pitch) = self.diffusion.camera();
+                let (yaw_sign, pitch_sign) = match key {
+                    KeyCode::ArrowLeft => (-1.0, 0.0),
+                    KeyCode::ArrowRight => (1.0, 0.0),
+                    KeyCode::ArrowUp => (0.0, 1.0),
+                    _ => (0.0, -1.0),
+                };
+                event::Event::SetCamera(
+                    yaw + yaw_sign * Self::CAMERA_INCREMENT,
+                    pitch + pitch_sign * Self::CAMERA_INCREMENT,
+                )
+            }
+            // the following bindings are not repeated when the key is held down
+            _ if repeat => return None,
+            KeyCode::Space if self.paused => event::Event::Start,
+            KeyCode::Space => event::Event::Pause,
+            KeyCode::Period => event::Event::Step(1),
+            KeyCode::KeyR => event::Event::Reset,
+            KeyCode::KeyC => event::Event::SetColormap(self.diffusion.colormap().next()),
+            KeyCode::KeyV => event::Event::SetView(self.diffusion.view().next()),
+            KeyCode::KeyA => event::Event::SetSliceAxis(self.diffusion.slice_axis().next()),
+            KeyCode::KeyP => event::Event::Screenshot,
+            KeyCode::KeyH => event::Event::ToggleHud,
+            KeyCode::F1 => event::Event::ToggleHelp,
+            _ => return None,
+        };
+        Some(event)
+    }
+
+    /// Save the current field to a PNG file in the working directory
+    #[cfg(not(target_arch = "wasm32"))]
+    fn save_screenshot(&self) {
+        let path =
+            std::path::PathBuf::from(format!("screenshot-{}.png", self.diffusion.step_number()));
+        let result = crate::screenshot::save(
+            &path,
+            &self.device,
+            &self.queue,
+            self.surface_config.format,
+            self.surface_config.width,
+            self.surface_config.height,
+            &self.diffusion.metadata(),
+            |encoder, view| self.draw_field(encoder, view),
+        );
+        match result {
+            Ok(()) => log(&format!("screenshot saved to {}", path.display())),
+            Err(e) => log(&format!("error while saving screenshot: {}", e)),
+        }
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    fn save_screenshot(&self) {
+        log("screenshots are not supported in the browser");
+    }
+
+    /// Rebuild the pipelines of the shaders whose file changed in the shader directory, keeping
+    /// the current ones if the new shaders do not compile
+    #[cfg(not(target_arch = "wasm32"))]
+    fn reload_shaders(&mut self) {
+        let Some(shader_dir) = &mut self.shader_dir else {
+            return;
+        };
+        let shaders = shader_dir.changed();
+        if let Some(shader) = shaders.diffusion {
+            match self.diffusion.reload_shader(&self.device, &shader) {
+                Ok(()) => log("diffusion.wgsl loaded"),
+                Err(e) => log(&format!("error in diffusion.wgsl: {}", e)),
+            }
+        }
+        if let Some(shader) = shaders.render {
+            match self
+                .field_renderer
+                .reload_shader(&self.device, &self.diffusion, &shader)
+            {
+                Ok(()) => {
+                    log("shader.wgsl loaded");
+                    self.window.request_redraw();
+                }
+                Err(e) => log(&format!("error in shader.wgsl: {}", e)),
+            }
+        }
+    }
+
+    fn draw_field(&self, encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView) {
+        self.field_renderer.render(encoder, view, &self.diffusion);
+    }
+
+    /// Publish the current state of the simulation, for the HUD and for `AppUpdater::stats`
+    fn update_stats(&mut self) {
+        let paused = self.paused;
+        let stats = Stats {
+            fps: if paused { 0.0 } else { self.rate_counter.fps() },
+            steps_per_second: if paused {
+                0.0
+            } else {
+                self.rate_counter.steps_per_second()
+            },
+            step_number: self.diffusion.step_number(),
+            time: self.diffusion.time(),
+            width: self.diffusion.width(),
+            height: self.diffusion.height(),
+            steps_per_frame: self.steps_per_frame,
+            timestep: self.diffusion.timestep(),
+            stable_timestep: self.diffusion.stable_timestep(),
+            diffusion_a: self.diffusion.diffusion(0),
+            diffusion_b: self.diffusion.diffusion(1),
+            feed: self.diffusion.feed(),
+            kill: self.diffusion.kill(),
+            seed: self.diffusion.seed(),
+            paused,
+        };
+        *self.stats.lock().unwrap() = stats;
+        if self.show_hud {
+            self.hud.set_text(&self.queue, &stats.hud_text());
+        }
+    }
+
+    /// Notify that the simulation stopped at its target
+    fn target_reached(&mut self) {
+        log(&format!(
+            "target reached at step {}, time {}",
+            self.diffusion.step_number(),
+            self.diffusion.time()
+        ));
+        #[cfg(target_arch = "wasm32")]
+        {
+            self.update_stats();
+            let stats = JsValue::from(*self.stats.lock().unwrap());
+            for callback in &self.target_listeners {
+                if let Err(e) = callback.call1(&JsValue::NULL, &stats) {
+                    log(&format!("error in target callback: {:?}", e));
+                }
+            }
+        }
+    }
+
+    /// Pause the simulation after it diverged, and notify it with the parameters that caused it
+    fn diverged(&mut self, divergence: Divergence) {
+        self.paused = true;
+        log(&divergence.message(&self.diffusion.metadata()));
+        if divergence.rolled_back {
+            log(&format!(
+                "rolled back to step {}, time {}",
+                self.diffusion.step_number(),
+                self.diffusion.time()
+            ));
+            // redraw so that the restored field is shown while paused
+            self.window.request_redraw();
+        }
+        self.update_stats();
+        #[cfg(target_arch = "wasm32")]
+        for callback in &self.divergence_listeners {
+            if let Err(e) = callback.call1(&JsValue::NULL, &JsValue::from(divergence)) {
+                log(&format!("error in divergence callback: {:?}", e));
+            }
         }
     }
 
@@ -147,14 +409,32 @@ impl State {
                 label: Some("Render Encoder"),
             });
 
-        {
-            let mut _render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: Some("Render Pass"),
+        // advance the simulation before drawing, so that the latest field is shown
+        let steps = if self.paused {
+            std::mem::take(&mut self.pending_steps)
+        } else {
+            self.steps_per_frame
+                .saturating_add(std::mem::take(&mut self.pending_steps))
+        };
+        let steps = self.target.clamp_steps(&self.diffusion, steps);
+        self.diffusion.step_many(&self.queue, &mut encoder, steps);
+        if self.target.is_reached(&self.diffusion) && (steps > 0 || !self.paused) {
+            self.paused = true;
+            if steps > 0 {
+                self.target_reached();
+            }
+        }
+        self.update_stats();
+
+        self.draw_field(&mut encoder, &view);
+        if self.show_hud || self.show_help {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Overlay Render Pass"),
                 color_attachments: &[Some(wgpu::RenderPassColorAttachment {
                     view: &view,
                     resolve_target: None,
                     ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color::RED),
+                        load: wgpu::LoadOp::Load,
                         store: wgpu::StoreOp::Store,
                     },
                 })],
@@ -162,23 +442,32 @@ impl State {
                 occlusion_query_set: None,
                 timestamp_writes: None,
             });
-            _render_pass.set_pipeline(&self.render_pipeline);
-            _render_pass.set_bind_group(0, self.diffusion.current_bind_group(), &[]);
-            _render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
-            _render_pass.draw(0..6, 0..1);
-        }
-
-        for _ in 0..self.steps_per_frame {
-            self.diffusion.render(&mut self.queue, &mut encoder);
+            if self.show_hud {
+                self.hud.draw(&mut render_pass);
+            }
+            if self.show_help {
+                self.help.draw(&mut render_pass);
+            }
         }
 
         self.queue.submit(std::iter::once(encoder.finish()));
         frame.present();
 
+        // the result of the divergence check is read back asynchronously, without waiting
+        self.device.poll(wgpu::Maintain::Poll);
+        if let Some(divergence) = self.diffusion.check_health() {
+            self.diverged(divergence);
+        }
+
         self.frame_number += 1;
+        self.rate_counter.record_frame(steps);
 
         #[cfg(debug_assertions)]
-        log(&format!("frame number {}", self.frame_number));
+        log(&format!(
+            "frame number {}, step number {}",
+            self.frame_number,
+            self.diffusion.step_number()
+        ));
 
         Ok(())
     }
@@ -210,6 +499,45 @@ impl ApplicationHandler<event::Event> for State {
                     },
                 ..
             } => event_loop.exit(),
+            WindowEvent::ModifiersChanged(modifiers) => {
+                self.shift_pressed = modifiers.state().shift_key();
+            }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        state: ElementState::Pressed,
+                        physical_key: PhysicalKey::Code(key),
+                        repeat,
+                        ..
+                    },
+                ..
+            } => {
+                if let Some(event) = self.key_binding(key, repeat) {
+                    self.user_event(event_loop, event);
+                }
+            }
+            WindowEvent::CursorMoved { position, .. } => {
+                self.cursor_position = Some(position);
+                if let Some(event) = self.mask_brush() {
+                    self.user_event(event_loop, event);
+                }
+            }
+            WindowEvent::CursorLeft { .. } => self.cursor_position = None,
+            WindowEvent::MouseInput { state, button, .. } => {
+                let wall = match button {
+                    MouseButton::Left => true,
+                    MouseButton::Right => false,
+                    _ => return,
+                };
+                if state == ElementState::Pressed && !self.diffusion.mask_supported() {
+                    log("the mask is only supported by the explicit solver on 2D grids");
+                    return;
+                }
+                self.drawing_walls = (state == ElementState::Pressed).then_some(wall);
+                if let Some(event) = self.mask_brush() {
+                    self.user_event(event_loop, event);
+                }
+            }
             WindowEvent::RedrawRequested => {
                 match self.render() {
                     Ok(_) => (),
@@ -231,23 +559,118 @@ impl ApplicationHandler<event::Event> for State {
         match event {
             event::Event::SetKill(kill) => self.diffusion.set_kill(kill),
             event::Event::SetFeed(feed) => self.diffusion.set_feed(feed),
-            event::Event::SetDiffusionA(diffusion_a) => self.diffusion.set_diffusion_a(diffusion_a),
-            event::Event::SetDiffusionB(diffusion_b) => self.diffusion.set_diffusion_b(diffusion_b),
+            event::Event::SetParameter(name, value) => self.diffusion.set_parameter(&name, value),
+            event::Event::SetDiffusion(species, diffusion) => {
+                self.diffusion.set_diffusion(species, diffusion)
+            }
             event::Event::SetStepsPerFrame(steps_per_frame) => {
                 self.steps_per_frame = steps_per_frame
             }
             event::Event::SetTimestep(timestep) => self.diffusion.set_timestep(timestep),
+            event::Event::SetTimestepMode(timestep_mode) => {
+                self.diffusion.set_timestep_mode(timestep_mode)
+            }
+            event::Event::SetColormap(colormap) => {
+                self.diffusion.set_colormap(colormap);
+                self.window.request_redraw();
+            }
+            event::Event::SetDiffusionX(diffusion_x) => self.diffusion.set_diffusion_x(diffusion_x),
+            event::Event::SetDiffusionY(diffusion_y) => self.diffusion.set_diffusion_y(diffusion_y),
+            event::Event::SetDiffusionAngle(diffusion_angle) => {
+                self.diffusion.set_diffusion_angle(diffusion_angle)
+            }
+            event::Event::SetDiffusivityMap(map) => self
+                .diffusion
+                .set_diffusivity_map(&self.queue, map.as_deref()),
+            event::Event::SetMask(mask) => {
+                self.diffusion.set_mask(&self.queue, mask.as_deref());
+                self.window.request_redraw();
+            }
+            event::Event::PaintMask { x, y, radius, wall } => {
+                self.diffusion.paint_mask(&self.queue, x, y, radius, wall);
+                self.window.request_redraw();
+            }
+            event::Event::SetVelocityStrength(velocity_strength) => {
+                self.diffusion.set_velocity_strength(velocity_strength)
+            }
+            event::Event::SetNoise(noise) => self.diffusion.set_noise(noise),
+            event::Event::SetSeed(seed) => self.diffusion.set_seed(seed),
+            event::Event::SetStochasticNoise(stochastic_noise) => {
+                self.diffusion.set_stochastic_noise(stochastic_noise)
+            }
+            event::Event::SetNoiseMode(noise_mode) => self.diffusion.set_noise_mode(noise_mode),
             event::Event::Reset => {
                 self.diffusion.reset();
                 // request redraw so that surface updates even when the simulation is paused
                 self.window.request_redraw();
             }
-            event::Event::Start => self.paused = false,
+            event::Event::Start => {
+                self.paused = false;
+                // do not count the time spent paused
+                self.rate_counter.reset();
+            }
             event::Event::Pause => self.paused = true,
+            event::Event::Step(steps) => {
+                // the steps are run in the next frame, in addition to the usual steps per frame
+                // if the simulation is not paused
+                self.pending_steps = self.pending_steps.saturating_add(steps);
+                self.window.request_redraw();
+            }
+            event::Event::SetMaxSteps(max_steps) => self.target.set_max_steps(max_steps),
+            event::Event::SetMaxTime(max_time) => self.target.set_max_time(max_time),
+            #[cfg(target_arch = "wasm32")]
+            event::Event::OnTargetReached(callback) => self.target_listeners.push(callback),
+            event::Event::SetCheckInterval(check_interval) => {
+                self.diffusion.set_check_interval(check_interval)
+            }
+            event::Event::SetMaxValue(max_value) => self.diffusion.set_max_value(max_value),
+            #[cfg(target_arch = "wasm32")]
+            event::Event::OnDivergence(callback) => self.divergence_listeners.push(callback),
+            event::Event::SetView(view) => {
+                self.diffusion.set_view(view);
+                self.window.request_redraw();
+            }
+            event::Event::SetSliceAxis(slice_axis) => {
+                self.diffusion.set_slice_axis(slice_axis);
+                self.window.request_redraw();
+            }
+            event::Event::SetSlice(slice) => {
+                self.diffusion.set_slice(slice);
+                self.window.request_redraw();
+            }
+            event::Event::SetIsoLevel(iso_level) => {
+                self.diffusion.set_iso_level(iso_level);
+                self.window.request_redraw();
+            }
+            event::Event::SetCamera(yaw, pitch) => {
+                self.diffusion.set_camera(yaw, pitch);
+                self.window.request_redraw();
+            }
+            event::Event::Screenshot => self.save_screenshot(),
+            event::Event::ToggleHud => {
+                self.show_hud = !self.show_hud;
+                self.window.request_redraw();
+            }
+            event::Event::ToggleHelp => {
+                self.show_help = !self.show_help;
+                self.window.request_redraw();
+            }
         }
+        self.update_stats();
     }
 
-    fn about_to_wait(&mut self, _event_loop: &winit::event_loop::ActiveEventLoop) {
+    // the shaders are only reloaded natively
+    #[cfg_attr(target_arch = "wasm32", allow(unused_variables))]
+    fn about_to_wait(&mut self, event_loop: &winit::event_loop::ActiveEventLoop) {
+        #[cfg(not(target_arch = "wasm32"))]
+        if self.shader_dir.is_some() {
+            self.reload_shaders();
+            // keep checking the files while paused
+            event_loop.set_control_flow(winit::event_loop::ControlFlow::wait_duration(
+                ShaderDir::CHECK_INTERVAL,
+            ));
+        }
+
         // request next frame to advance the simulation
         if !self.paused {
             self.window.request_redraw();
@@ -260,6 +683,7 @@ pub struct App {
     event_loop: EventLoop<event::Event>,
     window_handle: Arc<Window>,
     config: Config,
+    stats: Arc<Mutex<Stats>>,
 }
 
 #[wasm_bindgen]
@@ -268,17 +692,20 @@ impl App {
         let event_loop = EventLoop::<event::Event>::with_user_event()
             .build()
             .unwrap();
+        let (width, height) = config.image_size();
         let window_attributes = Window::default_attributes()
             .with_active(true)
-            .with_inner_size(PhysicalSize::new(config.width, config.height));
+            .with_inner_size(PhysicalSize::new(width, height));
 
         // TODO: fix deprecation, this should go inside `resumed`
+        #[allow(deprecated)]
         let window = event_loop.create_window(window_attributes).unwrap();
 
         Self {
             event_loop,
             window_handle: Arc::new(window),
             config,
+            stats: Default::default(),
         }
     }
 
@@ -292,11 +719,15 @@ impl App {
     pub fn updater(&self) -> AppUpdater {
         AppUpdater {
             event_loop_proxy: self.event_loop.create_proxy(),
+            stats: self.stats.clone(),
+            species: System::species_count(&self.config),
+            parameters: System::parameters(&self.config),
         }
     }
 
     pub async fn run(self) {
-        let mut state = State::new(&self.config, self.window_handle.clone()).await;
+        let mut state =
+            State::new(&self.config, self.window_handle.clone(), self.stats.clone()).await;
         #[cfg(target_arch = "wasm32")]
         self.event_loop.spawn_app(state);
         #[cfg(not(target_arch = "wasm32"))]
@@ -307,6 +738,11 @@ impl App {
 #[wasm_bindgen]
 pub struct AppUpdater {
     event_loop_proxy: EventLoopProxy<event::Event>,
+    stats: Arc<Mutex<Stats>>,
+    /// Number of species of the system
+    species: usize,
+    /// Named parameters of the reactions and their initial values
+    parameters: Vec<(String, f32)>,
 }
 
 #[wasm_bindgen]
@@ -325,14 +761,95 @@ impl AppUpdater {
         self.send_event(event::Event::SetFeed(feed));
     }
 
-    #[wasm_bindgen(js_name = setDiffusionA)]
-    pub fn set_diffusion_a(&self, diffusion_a: f32) {
-        self.send_event(event::Event::SetDiffusionA(diffusion_a));
+    /// Names of the parameters of the reactions given as expressions, in the order of
+    /// `Config::add_parameter`, empty for the models
+    pub fn parameters(&self) -> Vec<String> {
+        self.parameters
+            .iter()
+            .map(|(name, _)| name.clone())
+            .collect()
+    }
+
+    /// Initial value of the parameter called `name`, if the reactions have it
+    #[wasm_bindgen(js_name = parameterValue)]
+    pub fn parameter_value(&self, name: &str) -> Option<f32> {
+        self.parameters
+            .iter()
+            .find_map(|(n, value)| (n == name).then_some(*value))
+    }
+
+    /// Change the parameter called `name`, one of `parameters`
+    #[wasm_bindgen(js_name = setParameter)]
+    pub fn set_parameter(&self, name: String, value: f32) -> Result<(), String> {
+        if self.parameter_value(&name).is_none() {
+            return Err(format!("the reactions have no parameter {}", name));
+        }
+        self.send_event(event::Event::SetParameter(name, value));
+        Ok(())
     }
 
-    #[wasm_bindgen(js_name = setDiffusionB)]
-    pub fn set_diffusion_b(&self, diffusion_b: f32) {
-        self.send_event(event::Event::SetDiffusionB(diffusion_b));
+    /// Change the diffusion rate of the species `species`, 0 for A, 1 for B and so on
+    #[wasm_bindgen(js_name = setDiffusion)]
+    pub fn set_diffusion(&self, species: usize, diffusion: f32) -> Result<(), String> {
+        if species >= self.species {
+            return Err(format!(
+                "the system has {} species, no species {}",
+                self.species, species
+            ));
+        }
+        self.send_event(event::Event::SetDiffusion(species, diffusion));
+        Ok(())
+    }
+
+    /// Change the diffusion along the first principal axis, relative to the diffusion rates
+    #[wasm_bindgen(js_name = setDiffusionX)]
+    pub fn set_diffusion_x(&self, diffusion_x: f32) {
+        self.send_event(event::Event::SetDiffusionX(diffusion_x));
+    }
+
+    /// Change the diffusion along the second principal axis, relative to the diffusion rates
+    #[wasm_bindgen(js_name = setDiffusionY)]
+    pub fn set_diffusion_y(&self, diffusion_y: f32) {
+        self.send_event(event::Event::SetDiffusionY(diffusion_y));
+    }
+
+    /// Change the angle of the first principal axis of diffusion, in degrees
+    #[wasm_bindgen(js_name = setDiffusionAngle)]
+    pub fn set_diffusion_angle(&self, diffusion_angle: f32) {
+        self.send_event(event::Event::SetDiffusionAngle(diffusion_angle));
+    }
+
+    /// Multiply the diffusion in each cell by the given values, row by row, or remove the map if
+    /// undefined
+    #[wasm_bindgen(js_name = setDiffusivityMap)]
+    pub fn set_diffusivity_map(&self, map: Option<Vec<f32>>) {
+        self.send_event(event::Event::SetDiffusivityMap(map));
+    }
+
+    /// Replace the mask with one value per cell, row by row, zero for the walls and nonzero for
+    /// the open cells, or remove it if undefined
+    #[wasm_bindgen(js_name = setMask)]
+    pub fn set_mask(&self, mask: Option<Vec<u8>>) {
+        self.send_event(event::Event::SetMask(mask));
+    }
+
+    /// Turn the cells within `radius` of the cell (`x`, `y`) into walls, or open them if `wall`
+    /// is false, the distances being those between the centres of the cells of the lattice
+    #[wasm_bindgen(js_name = paintMask)]
+    pub fn paint_mask(&self, x: i32, y: i32, radius: u32, wall: bool) {
+        self.send_event(event::Event::PaintMask {
+            x: x as i64,
+            y: y as i64,
+            radius,
+            wall,
+        });
+    }
+
+    /// Multiply the velocity field advecting the species by `velocity_strength`, its largest
+    /// speed being 1 in the built-in fields
+    #[wasm_bindgen(js_name = setVelocityStrength)]
+    pub fn set_velocity_strength(&self, velocity_strength: f32) {
+        self.send_event(event::Event::SetVelocityStrength(velocity_strength));
     }
 
     #[wasm_bindgen(js_name = setTimestep)]
@@ -340,6 +857,40 @@ impl AppUpdater {
         self.send_event(event::Event::SetTimestep(timestep));
     }
 
+    /// Change what happens when the timestep exceeds the stability limit
+    #[wasm_bindgen(js_name = setTimestepMode)]
+    pub fn set_timestep_mode(&self, timestep_mode: TimestepMode) {
+        self.send_event(event::Event::SetTimestepMode(timestep_mode));
+    }
+
+    #[wasm_bindgen(js_name = setColormap)]
+    pub fn set_colormap(&self, colormap: Colormap) {
+        self.send_event(event::Event::SetColormap(colormap));
+    }
+
+    /// Change the amplitude of the initial noise, applied at the next reset
+    #[wasm_bindgen(js_name = setNoise)]
+    pub fn set_noise(&self, noise: f32) {
+        self.send_event(event::Event::SetNoise(noise));
+    }
+
+    /// Change the seed of the initial and stochastic noise, applied at the next reset
+    #[wasm_bindgen(js_name = setSeed)]
+    pub fn set_seed(&self, seed: u64) {
+        self.send_event(event::Event::SetSeed(seed));
+    }
+
+    /// Change the amplitude of the noise added at each step, zero to disable it
+    #[wasm_bindgen(js_name = setStochasticNoise)]
+    pub fn set_stochastic_noise(&self, stochastic_noise: f32) {
+        self.send_event(event::Event::SetStochasticNoise(stochastic_noise));
+    }
+
+    #[wasm_bindgen(js_name = setNoiseMode)]
+    pub fn set_noise_mode(&self, noise_mode: NoiseMode) {
+        self.send_event(event::Event::SetNoiseMode(noise_mode));
+    }
+
     #[wasm_bindgen(js_name = setStepsPerFrame)]
     pub fn set_steps_per_frame(&self, steps_per_frame: u32) {
         self.send_event(event::Event::SetStepsPerFrame(steps_per_frame));
@@ -356,4 +907,88 @@ impl AppUpdater {
     pub fn pause(&self) {
         self.send_event(event::Event::Pause);
     }
+
+    /// Advance the simulation by exactly `steps` steps, without resuming it if paused
+    pub fn step(&self, steps: u32) {
+        self.send_event(event::Event::Step(steps));
+    }
+
+    /// Stop the simulation after `max_steps` steps, or never if undefined
+    #[wasm_bindgen(js_name = setMaxSteps)]
+    pub fn set_max_steps(&self, max_steps: Option<u64>) {
+        self.send_event(event::Event::SetMaxSteps(max_steps));
+    }
+
+    /// Stop the simulation once the simulated time reaches `max_time`, or never if undefined
+    #[wasm_bindgen(js_name = setMaxTime)]
+    pub fn set_max_time(&self, max_time: Option<f64>) {
+        self.send_event(event::Event::SetMaxTime(max_time));
+    }
+
+    /// Call `callback` with the `Stats` each time the simulation is paused at its target
+    #[cfg(target_arch = "wasm32")]
+    #[wasm_bindgen(js_name = onTargetReached)]
+    pub fn on_target_reached(&self, callback: js_sys::Function) {
+        self.send_event(event::Event::OnTargetReached(callback));
+    }
+
+    /// Check the field for divergence every `check_interval` steps, or never if zero
+    #[wasm_bindgen(js_name = setCheckInterval)]
+    pub fn set_check_interval(&self, check_interval: u64) {
+        self.send_event(event::Event::SetCheckInterval(check_interval));
+    }
+
+    /// Change the largest absolute concentration accepted by the divergence check
+    #[wasm_bindgen(js_name = setMaxValue)]
+    pub fn set_max_value(&self, max_value: f32) {
+        self.send_event(event::Event::SetMaxValue(max_value));
+    }
+
+    /// Call `callback` with the `Divergence` each time the simulation is paused because it
+    /// diverged
+    #[cfg(target_arch = "wasm32")]
+    #[wasm_bindgen(js_name = onDivergence)]
+    pub fn on_divergence(&self, callback: js_sys::Function) {
+        self.send_event(event::Event::OnDivergence(callback));
+    }
+
+    /// Change how 3D volumes are drawn
+    #[wasm_bindgen(js_name = setView)]
+    pub fn set_view(&self, view: View) {
+        self.send_event(event::Event::SetView(view));
+    }
+
+    /// Change the axis normal to the slice drawn by the slice view
+    #[wasm_bindgen(js_name = setSliceAxis)]
+    pub fn set_slice_axis(&self, slice_axis: Axis) {
+        self.send_event(event::Event::SetSliceAxis(slice_axis));
+    }
+
+    /// Change the index of the slice drawn by the slice view, clamped to the volume
+    #[wasm_bindgen(js_name = setSlice)]
+    pub fn set_slice(&self, slice: u32) {
+        self.send_event(event::Event::SetSlice(slice));
+    }
+
+    /// Change the concentration of B on the isosurface drawn by the isosurface view
+    #[wasm_bindgen(js_name = setIsoLevel)]
+    pub fn set_iso_level(&self, iso_level: f32) {
+        self.send_event(event::Event::SetIsoLevel(iso_level));
+    }
+
+    /// Orient the camera of the isosurface and mesh views, with the yaw and pitch in radians
+    #[wasm_bindgen(js_name = setCamera)]
+    pub fn set_camera(&self, yaw: f32, pitch: f32) {
+        self.send_event(event::Event::SetCamera(yaw, pitch));
+    }
+
+    #[wasm_bindgen(js_name = toggleHud)]
+    pub fn toggle_hud(&self) {
+        self.send_event(event::Event::ToggleHud);
+    }
+
+    /// Latest performance and state of the simulation
+    pub fn stats(&self) -> Stats {
+        *self.stats.lock().unwrap()
+    }
 }
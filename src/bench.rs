@@ -0,0 +1,319 @@
+use std::time::{Duration, Instant};
+
+use clap::{Args, ValueEnum};
+
+use crate::config::Config;
+use crate::device;
+use crate::diffusion::Diffusion;
+use crate::storage::Storage;
+use crate::system::System;
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum OutputFormat {
+    Table,
+    Json,
+}
+
+/// Run the compute kernel without opening a window, and measure its throughput
+#[derive(Debug, Clone, Args)]
+pub struct Bench {
+    /// Grid sizes, either `N` for a square grid or `WIDTHxHEIGHT`
+    #[arg(long, value_delimiter = ',', value_parser = parse_size, default_value = "256,512,1024")]
+    pub sizes: Vec<(u32, u32)>,
+    /// Steps per frame
+    #[arg(long, value_delimiter = ',', value_parser = clap::value_parser!(u32).range(1..), default_value = "1,20,100")]
+    pub steps: Vec<u32>,
+    /// Steps per dispatch of the compute kernel
+    #[arg(
+        long,
+        value_delimiter = ',',
+        value_parser = clap::value_parser!(u32).range(1..=Diffusion::MAX_STEPS_PER_DISPATCH as i64),
+        default_value = "1"
+    )]
+    pub dispatch_steps: Vec<u32>,
+    /// Number of timed frames for each combination of parameters
+    #[arg(long, default_value_t = 100, value_parser = clap::value_parser!(u32).range(1..=Bench::MAX_FRAMES as i64))]
+    pub frames: u32,
+    /// Number of frames run before starting the measurement
+    #[arg(long, default_value_t = 10)]
+    pub warmup_frames: u32,
+    #[arg(long, value_enum, default_value_t = OutputFormat::Table)]
+    pub format: OutputFormat,
+}
+
+fn parse_size(s: &str) -> Result<(u32, u32), String> {
+    let parse = |s: &str| match s.parse::<u32>() {
+        Ok(0) | Err(_) => Err(format!("invalid grid size `{}`", s)),
+        Ok(n) => Ok(n),
+    };
+    match s.split_once('x') {
+        Some((width, height)) => Ok((parse(width)?, parse(height)?)),
+        None => parse(s).map(|n| (n, n)),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Timer {
+    /// GPU timestamp queries, measuring only the compute passes
+    Gpu,
+    /// Wall clock, measuring everything from the first submission until the device is idle
+    Wall,
+}
+
+impl Timer {
+    fn name(&self) -> &'static str {
+        match self {
+            Timer::Gpu => "gpu",
+            Timer::Wall => "wall",
+        }
+    }
+}
+
+struct Measurement {
+    width: u32,
+    height: u32,
+    steps_per_frame: u32,
+    steps_per_dispatch: u32,
+    timer: Timer,
+    elapsed: Duration,
+    steps: u64,
+}
+
+impl Measurement {
+    fn steps_per_second(&self) -> f64 {
+        self.steps as f64 / self.elapsed.as_secs_f64()
+    }
+
+    fn cell_updates_per_second(&self) -> f64 {
+        self.steps_per_second() * self.width as f64 * self.height as f64
+    }
+}
+
+impl Bench {
+    /// Timestamp queries are written in a single query set, two for each frame
+    const MAX_FRAMES: u32 = wgpu::QUERY_SET_MAX_QUERIES / 2;
+
+    /// Run all the combinations of parameters, using `config` for the rest of the settings
+    pub fn run(&self, config: &Config) {
+        pollster::block_on(self.run_async(config));
+    }
+
+    async fn run_async(&self, config: &Config) {
+        let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor::default());
+        let adapter = instance
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::HighPerformance,
+                ..Default::default()
+            })
+            .await
+            .expect("no suitable GPU adapter found");
+
+        let timer = if adapter.features().contains(wgpu::Features::TIMESTAMP_QUERY) {
+            Timer::Gpu
+        } else {
+            Timer::Wall
+        };
+        let required_features = match timer {
+            Timer::Gpu => wgpu::Features::TIMESTAMP_QUERY,
+            Timer::Wall => wgpu::Features::empty(),
+        };
+        let (device, queue) =
+            device::request_device(&adapter, Some("Bench device"), required_features)
+                .await
+                .unwrap();
+
+        let info = adapter.get_info();
+        eprintln!(
+            "adapter: {} ({:?}), timer: {}",
+            info.name,
+            info.backend,
+            timer.name()
+        );
+
+        let storage =
+            Storage::supported_layout(config.storage, System::species_count(config), &adapter);
+        let mut measurements = Vec::new();
+        for &(width, height) in &self.sizes {
+            for &steps_per_dispatch in &self.dispatch_steps {
+                for &steps_per_frame in &self.steps {
+                    let config = Config {
+                        width,
+                        height,
+                        steps_per_frame,
+                        steps_per_dispatch,
+                        storage,
+                        ..config.clone()
+                    };
+                    let (elapsed, steps) = self.measure(&config, &device, &queue, timer);
+                    let measurement = Measurement {
+                        width,
+                        height,
+                        steps_per_frame,
+                        steps_per_dispatch,
+                        timer,
+                        elapsed,
+                        steps,
+                    };
+                    if let OutputFormat::Table = self.format {
+                        if measurements.is_empty() {
+                            Self::print_table_header();
+                        }
+                        Self::print_table_row(&measurement);
+                    }
+                    measurements.push(measurement);
+                }
+            }
+        }
+
+        if let OutputFormat::Json = self.format {
+            Self::print_json(&measurements);
+        }
+    }
+
+    fn measure(
+        &self,
+        config: &Config,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        timer: Timer,
+    ) -> (Duration, u64) {
+        let mut diffusion = Diffusion::new(config, device, queue);
+
+        for _ in 0..self.warmup_frames {
+            let mut encoder = device.create_command_encoder(&Default::default());
+            diffusion.step_many(queue, &mut encoder, config.steps_per_frame);
+            queue.submit(std::iter::once(encoder.finish()));
+        }
+        device.poll(wgpu::Maintain::Wait);
+        let first_step = diffusion.step_number();
+
+        let elapsed = match timer {
+            Timer::Wall => {
+                let start = Instant::now();
+                for _ in 0..self.frames {
+                    let mut encoder = device.create_command_encoder(&Default::default());
+                    diffusion.step_many(queue, &mut encoder, config.steps_per_frame);
+                    queue.submit(std::iter::once(encoder.finish()));
+                }
+                device.poll(wgpu::Maintain::Wait);
+                start.elapsed()
+            }
+            Timer::Gpu => {
+                let query_count = 2 * self.frames;
+                let query_set = device.create_query_set(&wgpu::QuerySetDescriptor {
+                    label: Some("Bench timestamps"),
+                    ty: wgpu::QueryType::Timestamp,
+                    count: query_count,
+                });
+                let size = query_count as u64 * wgpu::QUERY_SIZE as u64;
+                let resolve_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some("Bench timestamps resolve"),
+                    size,
+                    usage: wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC,
+                    mapped_at_creation: false,
+                });
+                let readback_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some("Bench timestamps readback"),
+                    size,
+                    usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+                    mapped_at_creation: false,
+                });
+
+                for frame in 0..self.frames {
+                    let mut encoder = device.create_command_encoder(&Default::default());
+                    diffusion.step_many_with_timestamps(
+                        queue,
+                        &mut encoder,
+                        config.steps_per_frame,
+                        Some(wgpu::ComputePassTimestampWrites {
+                            query_set: &query_set,
+                            beginning_of_pass_write_index: Some(2 * frame),
+                            end_of_pass_write_index: Some(2 * frame + 1),
+                        }),
+                    );
+                    queue.submit(std::iter::once(encoder.finish()));
+                }
+
+                let mut encoder = device.create_command_encoder(&Default::default());
+                encoder.resolve_query_set(&query_set, 0..query_count, &resolve_buffer, 0);
+                encoder.copy_buffer_to_buffer(&resolve_buffer, 0, &readback_buffer, 0, size);
+                queue.submit(std::iter::once(encoder.finish()));
+
+                let slice = readback_buffer.slice(..);
+                slice.map_async(wgpu::MapMode::Read, |result| result.unwrap());
+                device.poll(wgpu::Maintain::Wait);
+                let ticks: u64 = {
+                    let data = slice.get_mapped_range();
+                    let timestamps: &[u64] = bytemuck::cast_slice(&data);
+                    timestamps
+                        .chunks_exact(2)
+                        .map(|pair| pair[1].saturating_sub(pair[0]))
+                        .sum()
+                };
+                readback_buffer.unmap();
+
+                Duration::from_nanos((ticks as f64 * queue.get_timestamp_period() as f64) as u64)
+            }
+        };
+        (elapsed, diffusion.step_number() - first_step)
+    }
+
+    fn print_table_header() {
+        println!(
+            "{:>6} {:>6} {:>6} {:>9} {:>5} {:>10} {:>12} {:>16}",
+            "width",
+            "height",
+            "steps",
+            "dispatch",
+            "timer",
+            "time (ms)",
+            "steps/s",
+            "cell updates/s"
+        );
+    }
+
+    fn print_table_row(m: &Measurement) {
+        println!(
+            "{:>6} {:>6} {:>6} {:>9} {:>5} {:>10.2} {:>12.1} {:>16.4e}",
+            m.width,
+            m.height,
+            m.steps_per_frame,
+            m.steps_per_dispatch,
+            m.timer.name(),
+            m.elapsed.as_secs_f64() * 1000.0,
+            m.steps_per_second(),
+            m.cell_updates_per_second()
+        );
+    }
+
+    fn print_json(measurements: &[Measurement]) {
+        let rows: Vec<_> = measurements
+            .iter()
+            .map(|m| {
+                format!(
+                    "  {{\"width\": {}, \"height\": {}, \"steps_per_frame\": {}, \
+                     \"steps_per_dispatch\": {}, \"timer\": \"{}\", \"seconds\": {}, \
+                     \"steps_per_second\": {}, \"cell_updates_per_second\": {}}}",
+                    m.width,
+                    m.height,
+                    m.steps_per_frame,
+                    m.steps_per_dispatch,
+                    m.timer.name(),
+                    Self::json_number(m.elapsed.as_secs_f64()),
+                    Self::json_number(m.steps_per_second()),
+                    Self::json_number(m.cell_updates_per_second())
+                )
+            })
+            .collect();
+        println!("[\n{}\n]", rows.join(",\n"));
+    }
+
+    /// `value` as a JSON number, or `null` if it is infinite or NaN, which JSON cannot represent
+    fn json_number(value: f64) -> String {
+        if value.is_finite() {
+            value.to_string()
+        } else {
+            "null".to_string()
+        }
+    }
+}
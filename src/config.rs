@@ -1,26 +1,437 @@
-use clap::Parser;
+use std::path::{Path, PathBuf};
+
+use clap::{Parser, ValueEnum};
 use wasm_bindgen::prelude::*;
 
+use crate::diffusion::Diffusion;
+
+/// How the concentrations of the species are stored on the GPU
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum StorageLayout {
+    /// Two `f32` storage buffers, each holding the species one after the other
+    Buffers,
+    /// A pair of storage textures with one channel per species, `rg32float` for up to two species
+    /// and `rgba32float` for up to four
+    Texture,
+    /// Like `Texture` in half precision, `rg16float` or `rgba16float`, whose texels are filtered by
+    /// the hardware when the field is drawn
+    HalfTexture,
+}
+
+/// Colormap used to draw the concentrations
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Colormap {
+    Grayscale,
+    Viridis,
+    Magma,
+    Inferno,
+}
+
+impl Colormap {
+    /// Colormap following this one, wrapping around after the last one
+    pub(crate) fn next(self) -> Self {
+        match self {
+            Colormap::Grayscale => Colormap::Viridis,
+            Colormap::Viridis => Colormap::Magma,
+            Colormap::Magma => Colormap::Inferno,
+            Colormap::Inferno => Colormap::Grayscale,
+        }
+    }
+}
+
+/// Reaction system simulated on the grid, see `system.rs`
 #[wasm_bindgen]
-#[derive(Debug, Parser)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Model {
+    /// Gray-Scott model, with the species A and B
+    GrayScott,
+    /// Gray-Scott model with a third species C, produced by B and inhibiting its growth
+    GrayScottInhibitor,
+    /// Cyclic competition of four species A, B, C and D, each one outcompeting the next
+    LotkaVolterra,
+}
+
+/// Discrete Laplacian used for the diffusion in 3D volumes
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum VolumeStencil {
+    /// Standard 7-point stencil, with the faces
+    SevenPoint,
+    /// Isotropic 19-point stencil, with the faces and the edges
+    NineteenPoint,
+    /// Isotropic 27-point stencil, with the faces, the edges and the corners
+    TwentySevenPoint,
+}
+
+/// How 3D volumes are drawn
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum View {
+    /// Axis-aligned slice of the volume
+    Slice,
+    /// Raymarched isosurface of the concentration of B
+    Isosurface,
+}
+
+impl View {
+    /// View following this one, wrapping around after the last one
+    pub(crate) fn next(self) -> Self {
+        match self {
+            View::Slice => View::Isosurface,
+            View::Isosurface => View::Slice,
+        }
+    }
+}
+
+/// Axis of the grid
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Axis {
+    X,
+    Y,
+    Z,
+}
+
+impl Axis {
+    /// Axis following this one, wrapping around after the last one
+    pub(crate) fn next(self) -> Self {
+        match self {
+            Axis::X => Axis::Y,
+            Axis::Y => Axis::Z,
+            Axis::Z => Axis::X,
+        }
+    }
+}
+
+/// What to do when the timestep exceeds the stability limit of the explicit scheme
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum TimestepMode {
+    /// Keep the timestep and log a warning
+    Warn,
+    /// Reduce the timestep to the stability limit
+    Clamp,
+    /// Always use the largest stable timestep, ignoring `timestep`
+    Adaptive,
+}
+
+/// Discrete Laplacian used for the diffusion
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Stencil {
+    /// 9-point stencil by Karl Sims, 0.3 times the Laplacian, matching his parameter values
+    Sims,
+    /// Standard 5-point stencil
+    FivePoint,
+    /// 9-point stencil with isotropic error, by Patra and Karttunen
+    Isotropic,
+    /// 9-point stencil by Oono and Puri
+    OonoPuri,
+    /// Fourth order stencil on the 13-point neighbourhood, whose corners have no weight, so that
+    /// only the cells up to two apart along the axes are used
+    ThirteenPoint,
+}
+
+/// Arrangement of the cells of 2D grids
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Lattice {
+    /// Square cells, with the neighbours used by `Stencil`
+    Square,
+    /// Hexagonal cells with six neighbours at the same distance, stored by rows with the odd
+    /// rows shifted right by half a cell, and the 7-point stencil scaled like `Stencil`
+    Hexagonal,
+}
+
+impl Lattice {
+    /// Height in pixels of the images of a grid of `rows` rows, the rows of the hexagonal
+    /// lattice being `sqrt(3) / 2` cells apart
+    pub(crate) fn image_height(self, rows: u32) -> u32 {
+        match self {
+            Lattice::Square => rows,
+            Lattice::Hexagonal => ((rows as f32 * 3f32.sqrt() / 2.0).round() as u32).max(1),
+        }
+    }
+
+    /// Distance between the rows, in cells
+    pub(crate) fn row_spacing(self) -> f64 {
+        match self {
+            Lattice::Square => 1.0,
+            Lattice::Hexagonal => 3f64.sqrt() / 2.0,
+        }
+    }
+
+    /// Position of the centre of the cell at (`column`, `row`), in cells, the odd rows of the
+    /// hexagonal lattice being shifted right by half a cell
+    pub(crate) fn centre(self, (column, row): (i64, i64)) -> (f64, f64) {
+        let shift = match self {
+            Lattice::Square => 0.0,
+            Lattice::Hexagonal => 0.5 * (row & 1) as f64,
+        };
+        (column as f64 + shift, row as f64 * self.row_spacing())
+    }
+
+    /// Cell under the pixel at (`x`, `y`) of the image of a grid of `rows` rows, the one whose
+    /// centre is the nearest, like `hexagonal_cell` in `shader.wgsl`
+    pub(crate) fn cell(self, (x, y): (f64, f64), rows: u32) -> (i64, i64) {
+        // position in cells along x and in rows along y
+        let y = y * rows as f64 / self.image_height(rows) as f64;
+        match self {
+            Lattice::Square => (x.floor() as i64, y.floor() as i64),
+            Lattice::Hexagonal => {
+                // the nearest centre is on one of the two rows around the pixel
+                let first_row = (y - 0.5).floor() as i64;
+                let nearest = (first_row..=first_row + 1)
+                    .map(|row| {
+                        let shift = 0.5 * (row & 1) as f64;
+                        let column = (x - shift).floor() as i64;
+                        let distance = (x - (column as f64 + shift + 0.5))
+                            .hypot((y - (row as f64 + 0.5)) * self.row_spacing());
+                        (distance, (column, row))
+                    })
+                    .min_by(|a, b| a.0.total_cmp(&b.0));
+                nearest.map_or((0, 0), |(_, cell)| cell)
+            }
+        }
+    }
+}
+
+/// Time integration of the diffusion term
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Solver {
+    /// Explicit Euler steps of the stencil, limited by the stability of the diffusion
+    Explicit,
+    /// Implicit diffusion in Fourier space and explicit reaction, stable for any timestep. The
+    /// domain is periodic and its width and height must be powers of two
+    Spectral,
+    /// Alternating direction implicit (Peaceman-Rachford) diffusion and explicit reaction,
+    /// stable for any timestep, with walls given by `boundary`
+    Adi,
+}
+
+/// Boundary condition at the walls of the domain, used by the ADI solver
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Boundary {
+    /// Zero flux through the walls
+    Neumann,
+    /// Concentrations held at the background of each species outside the walls, A = 1 and B = 0 for
+    /// Gray-Scott
+    Dirichlet,
+}
+
+/// How the stochastic noise is applied at each step
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum NoiseMode {
+    /// Independent of the concentrations
+    Additive,
+    /// Proportional to the concentrations
+    Multiplicative,
+}
+
+/// Built-in velocity field advecting the species
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum VelocityField {
+    /// No advection
+    None,
+    /// Same velocity everywhere, along `velocity_angle`
+    Uniform,
+    /// Rotation around the centre of the domain
+    Vortex,
+    /// Flow along x, with a sinusoidal profile along y
+    Shear,
+}
+
+/// Discretisation of the advection term. The discriminants are the values seen by the shaders,
+/// where zero means no advection
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Advection {
+    /// First order upwind differences, limited by the Courant number like the diffusion
+    Upwind = 1,
+    /// Concentrations interpolated at the departure point of the flow, stable for any timestep
+    SemiLagrangian = 2,
+}
+
+/// How the domain grows along x over time
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Growth {
+    /// Fixed domain
+    None,
+    /// Exponential growth of the whole domain, stretching the grid and diluting the species
+    Uniform,
+    /// Linear growth at the tip, the right end of the domain, adding cells at the background
+    /// concentrations
+    Apical,
+}
+
+#[wasm_bindgen]
+#[derive(Debug, Clone, Parser)]
 #[command(version, about, long_about = None)]
 pub struct Config {
     #[arg(long, default_value_t = 512)]
     pub width: u32,
     #[arg(long, default_value_t = 512)]
     pub height: u32,
+    /// Physical width of the domain, the grid spacing is this divided by `width` (1 if not set)
+    #[arg(long)]
+    pub domain_width: Option<f32>,
+    /// Physical height of the domain, with square cells if not set
+    #[arg(long)]
+    pub domain_height: Option<f32>,
+    /// Number of layers of the grid, greater than one to simulate a 3D volume
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    pub depth: u32,
+    /// Triangle mesh, as an OBJ or PLY file, on whose vertices the species react and diffuse
+    /// instead of the cells of the grid, `width` and `height` being the size of the image. Only
+    /// read natively, see `read_file`.
+    #[wasm_bindgen(skip)]
+    #[arg(long)]
+    pub mesh: Option<PathBuf>,
     #[arg(long, default_value_t = 20)]
     pub steps_per_frame: u32,
     #[arg(long, default_value_t = 1.0)]
     pub timestep: f32,
+    #[arg(long, value_enum, default_value_t = TimestepMode::Warn)]
+    pub timestep_mode: TimestepMode,
+    #[arg(long, value_enum, default_value_t = Model::GrayScott)]
+    pub model: Model,
     #[arg(long, default_value_t = 0.5)]
     pub diffusion_a: f32,
     #[arg(long, default_value_t = 0.25)]
     pub diffusion_b: f32,
+    /// Diffusion rate of the next species after A and B, in the order of the species. Repeat it
+    /// for each species, those without one keeping the default of the model
+    #[wasm_bindgen(skip)]
+    #[arg(long = "diffusion-rate")]
+    pub diffusion_rates: Vec<f32>,
     #[arg(long, default_value_t = 0.03)]
     pub feed: f32,
     #[arg(long, default_value_t = 0.09)]
     pub kill: f32,
+    /// Reaction term of a species, as `da = -a*b*b + f*(1 - a)` for the species A, replacing the
+    /// kinetics of `model`. Repeat it for each species, in order
+    #[wasm_bindgen(skip)]
+    #[arg(long = "reaction")]
+    pub reactions: Vec<String>,
+    /// Named parameter of the reactions, as `f=0.03`
+    #[wasm_bindgen(skip)]
+    #[arg(long = "parameter", value_parser = parse_parameter)]
+    pub parameters: Vec<(String, f32)>,
+    #[arg(long, value_enum, default_value_t = Stencil::Sims)]
+    pub stencil: Stencil,
+    #[arg(long, value_enum, default_value_t = Lattice::Square)]
+    pub lattice: Lattice,
+    #[arg(long, value_enum, default_value_t = VolumeStencil::SevenPoint)]
+    pub volume_stencil: VolumeStencil,
+    #[arg(long, value_enum, default_value_t = Solver::Explicit)]
+    pub solver: Solver,
+    #[arg(long, value_enum, default_value_t = Boundary::Neumann)]
+    pub boundary: Boundary,
+    /// Diffusion along the first principal axis, relative to `diffusion_a` and `diffusion_b`
+    #[arg(long, default_value_t = 1.0)]
+    pub diffusion_x: f32,
+    /// Diffusion along the second principal axis, relative to `diffusion_a` and `diffusion_b`
+    #[arg(long, default_value_t = 1.0)]
+    pub diffusion_y: f32,
+    /// Angle of the first principal axis of diffusion from the x axis, in degrees
+    #[arg(long, default_value_t = 0.0, allow_negative_numbers = true)]
+    pub diffusion_angle: f32,
+    /// Grayscale PNG image whose luminance multiplies the diffusion in each cell
+    #[wasm_bindgen(skip)]
+    #[arg(long)]
+    pub diffusivity_map: Option<PathBuf>,
+    /// Black and white PNG image of the domain, whose dark cells are impermeable walls
+    #[wasm_bindgen(skip)]
+    #[arg(long)]
+    pub mask: Option<PathBuf>,
+    /// Velocity field advecting the species
+    #[arg(long, value_enum, default_value_t = VelocityField::None)]
+    pub velocity: VelocityField,
+    /// PNG image, with the velocity in the red and green channels, or `.npy` array of shape
+    /// (rows, columns, 2) of the velocity in each cell, replacing `velocity`
+    #[wasm_bindgen(skip)]
+    #[arg(long)]
+    pub velocity_map: Option<PathBuf>,
+    /// Factor of the velocity, whose largest speed is 1 in the built-in fields
+    #[arg(long, default_value_t = 0.1, allow_negative_numbers = true)]
+    pub velocity_strength: f32,
+    /// Direction of the uniform velocity field from the x axis, in degrees
+    #[arg(long, default_value_t = 0.0, allow_negative_numbers = true)]
+    pub velocity_angle: f32,
+    #[arg(long, value_enum, default_value_t = Advection::Upwind)]
+    pub advection: Advection,
+    #[arg(long, value_enum, default_value_t = Growth::None)]
+    pub growth: Growth,
+    /// Rate of growth of the domain, relative to its initial length
+    #[arg(long, default_value_t = 0.0001)]
+    pub growth_rate: f32,
+    /// Final length of the domain relative to the initial one, after which the growth stops
+    #[arg(long, default_value_t = 4.0)]
+    pub growth_factor: f32,
+    /// Amplitude of the random noise added to the initial concentrations of the species
+    #[arg(long, default_value_t = 0.0)]
+    pub noise: f32,
+    /// Seed of the initial and stochastic noise, chosen at random when not set
+    #[arg(long)]
+    pub seed: Option<u64>,
+    /// Amplitude of the stochastic noise added to the concentrations at each step
+    #[arg(long, default_value_t = 0.0)]
+    pub stochastic_noise: f32,
+    #[arg(long, value_enum, default_value_t = NoiseMode::Additive)]
+    pub noise_mode: NoiseMode,
+    /// Number of steps run by each dispatch of the compute kernel, using workgroup memory
+    #[arg(
+        long,
+        default_value_t = 1,
+        value_parser = clap::value_parser!(u32).range(1..=Diffusion::MAX_STEPS_PER_DISPATCH as i64),
+    )]
+    pub steps_per_dispatch: u32,
+    #[arg(long, value_enum, default_value_t = StorageLayout::Buffers)]
+    pub storage: StorageLayout,
+    /// Stop the simulation after this number of steps
+    #[arg(long)]
+    pub max_steps: Option<u64>,
+    /// Stop the simulation once this simulated time is reached
+    #[arg(long)]
+    pub max_time: Option<f64>,
+    /// Check the field for NaN, infinite or out of range values every this number of steps, and
+    /// pause the simulation if any is found. Zero disables the check
+    #[arg(long, default_value_t = 1000)]
+    pub check_interval: u64,
+    /// Largest absolute concentration accepted by the check
+    #[arg(long, default_value_t = 10.0)]
+    pub max_value: f32,
+    /// Restore the last checked field when the simulation diverges
+    #[arg(long)]
+    pub rollback: bool,
+    #[arg(long, value_enum, default_value_t = Colormap::Grayscale)]
+    pub colormap: Colormap,
+    #[arg(long, value_enum, default_value_t = View::Slice)]
+    pub view: View,
+    /// Axis normal to the slice shown by the slice view
+    #[arg(long, value_enum, default_value_t = Axis::Z)]
+    pub slice_axis: Axis,
+    /// Index of the slice shown by the slice view, the middle one when not set
+    #[arg(long)]
+    pub slice: Option<u32>,
+    /// Concentration of B on the isosurface drawn by the isosurface view
+    #[arg(long, default_value_t = 0.25)]
+    pub iso_level: f32,
+    /// Show the HUD at startup, it can be toggled with H
+    #[arg(long)]
+    pub hud: bool,
+    /// Directory from which `diffusion.wgsl` and `shader.wgsl` are loaded instead of the built-in
+    /// shaders, and reloaded when they change. Native only
+    #[wasm_bindgen(skip)]
+    #[arg(long)]
+    pub shader_dir: Option<PathBuf>,
 }
 
 impl Default for Config {
@@ -28,16 +439,105 @@ impl Default for Config {
         Self {
             width: 512,
             height: 512,
+            domain_width: None,
+            domain_height: None,
+            depth: 1,
+            mesh: None,
             steps_per_frame: 20,
             timestep: 1.0,
+            timestep_mode: TimestepMode::Warn,
+            model: Model::GrayScott,
             diffusion_a: 0.5,
             diffusion_b: 0.25,
+            diffusion_rates: Vec::new(),
             feed: 0.03,
             kill: 0.09,
+            reactions: Vec::new(),
+            parameters: Vec::new(),
+            stencil: Stencil::Sims,
+            lattice: Lattice::Square,
+            volume_stencil: VolumeStencil::SevenPoint,
+            solver: Solver::Explicit,
+            boundary: Boundary::Neumann,
+            diffusion_x: 1.0,
+            diffusion_y: 1.0,
+            diffusion_angle: 0.0,
+            diffusivity_map: None,
+            mask: None,
+            velocity: VelocityField::None,
+            velocity_map: None,
+            velocity_strength: 0.1,
+            velocity_angle: 0.0,
+            advection: Advection::Upwind,
+            growth: Growth::None,
+            growth_rate: 0.0001,
+            growth_factor: 4.0,
+            noise: 0.0,
+            seed: None,
+            stochastic_noise: 0.0,
+            noise_mode: NoiseMode::Additive,
+            steps_per_dispatch: 1,
+            storage: StorageLayout::Buffers,
+            max_steps: None,
+            max_time: None,
+            check_interval: 1000,
+            max_value: 10.0,
+            rollback: false,
+            colormap: Colormap::Grayscale,
+            view: View::Slice,
+            slice_axis: Axis::Z,
+            slice: None,
+            iso_level: 0.25,
+            hud: false,
+            shader_dir: None,
+        }
+    }
+}
+
+impl Config {
+    /// Grid spacing along x and y, derived from the size of the domain
+    pub(crate) fn spacing(&self) -> (f32, f32) {
+        let dx = self
+            .domain_width
+            .map_or(1.0, |domain_width| domain_width / self.width as f32);
+        let dy = self
+            .domain_height
+            .map_or(dx, |domain_height| domain_height / self.height as f32);
+        (dx, dy)
+    }
+
+    /// Size in pixels of the images of the field, see `Lattice::image_height`. 3D volumes only
+    /// have square cells, and meshes are drawn on images of `width` × `height` pixels
+    pub(crate) fn image_size(&self) -> (u32, u32) {
+        if self.depth > 1 || self.mesh.is_some() {
+            return (self.width, self.height);
         }
+        (self.width, self.lattice.image_height(self.height))
     }
 }
 
+/// Parse a parameter of the reactions written as `name=value`
+fn parse_parameter(parameter: &str) -> Result<(String, f32), String> {
+    let (name, value) = parameter
+        .split_once('=')
+        .ok_or_else(|| format!("expected name=value, found \"{}\"", parameter))?;
+    let value = value.trim().parse().map_err(|e| format!("{}", e))?;
+    Ok((name.trim().to_string(), value))
+}
+
+/// Contents of the file at `path`, such as the images given in the `Config`
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn read_file(path: &Path) -> Result<Vec<u8>, String> {
+    std::fs::read(path).map_err(|e| e.to_string())
+}
+
+/// Files cannot be read in the browser, where the diffusivity map and the mask are given as values
+/// to the `AppUpdater`
+#[cfg(target_arch = "wasm32")]
+pub(crate) fn read_file(path: &Path) -> Result<Vec<u8>, String> {
+    Err(format!("{} cannot be read in the browser", path.display()))
+}
+
 #[wasm_bindgen]
 impl Config {
     pub fn with_size(width: u32, height: u32) -> Self {
@@ -47,4 +547,19 @@ impl Config {
             ..Default::default()
         }
     }
+
+    /// Add the diffusion rate of the next species after A and B, see `diffusion_rates`
+    pub fn add_diffusion_rate(&mut self, diffusion: f32) {
+        self.diffusion_rates.push(diffusion);
+    }
+
+    /// Add the reaction term of the next species, see `reactions`
+    pub fn add_reaction(&mut self, reaction: String) {
+        self.reactions.push(reaction);
+    }
+
+    /// Add a named parameter of the reactions, see `parameters`
+    pub fn add_parameter(&mut self, name: String, value: f32) {
+        self.parameters.push((name, value));
+    }
 }
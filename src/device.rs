@@ -0,0 +1,22 @@
+/// Request a device from `adapter` with `features` and the largest limits it supports, since the
+/// storage buffers of models with many species may exceed the defaults. The formats of the storage
+/// textures may also need the usages specific to the adapter, see `Storage::supported_layout`.
+pub(crate) async fn request_device(
+    adapter: &wgpu::Adapter,
+    label: Option<&str>,
+    features: wgpu::Features,
+) -> Result<(wgpu::Device, wgpu::Queue), wgpu::RequestDeviceError> {
+    adapter
+        .request_device(
+            &wgpu::DeviceDescriptor {
+                label,
+                required_features: features
+                    | (adapter.features()
+                        & wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES),
+                required_limits: adapter.limits(),
+                ..Default::default()
+            },
+            None,
+        )
+        .await
+}
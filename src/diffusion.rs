@@ -1,6 +1,19 @@
-use wgpu::{util::DeviceExt, BufferUsages};
+use wgpu::util::DeviceExt;
 
-use crate::config::Config;
+use crate::adi::Adi;
+use crate::config::{
+    read_file, Advection, Axis, Boundary, Colormap, Config, Growth, Lattice, NoiseMode, Solver,
+    Stencil, StorageLayout, TimestepMode, VelocityField, View, VolumeStencil,
+};
+use crate::growth::DomainGrowth;
+use crate::health::{Divergence, Health};
+use crate::log::log;
+use crate::mesh::Mesh;
+use crate::random::{random_seed, Random};
+use crate::spectral::Spectral;
+use crate::storage::Storage;
+use crate::system::{Reactions, Species, System};
+use crate::tensor::{DiffusionTensor, VolumeWeights, Weights};
 
 #[repr(C)]
 #[derive(Copy, Clone, bytemuck::Zeroable, bytemuck::Pod)]
@@ -9,256 +22,1074 @@ struct ConfigUniform {
     height: u32,
     size: u32,
     timestep: f32,
-    diffusion_a: f32,
-    diffusion_b: f32,
     feed: f32,
     kill: f32,
+    steps_per_dispatch: u32,
+    colormap: u32,
+    dx: f32,
+    dy: f32,
+    stochastic_noise: f32,
+    noise_mode: u32,
+    seed: u32,
+    weight_x: f32,
+    weight_y: f32,
+    weight_diagonal: f32,
+    weight_antidiagonal: f32,
+    weight_x2: f32,
+    weight_y2: f32,
+    radius: u32,
+    has_diffusivity_map: u32,
+    max_value: f32,
+    boundary: u32,
+    depth: u32,
+    weight_face: f32,
+    weight_edge: f32,
+    weight_corner: f32,
+    view: u32,
+    slice_axis: u32,
+    slice: u32,
+    iso_level: f32,
+    camera_yaw: f32,
+    camera_pitch: f32,
+    has_mask: u32,
+    advection: u32,
+    velocity_strength: f32,
+    dilution: f32,
+    /// Number of columns covered by the domain, from the left, see `DomainGrowth::columns`
+    active_width: u32,
+    lattice: u32,
+    has_mesh: u32,
+    /// Diffusion rate of each species, zero for the species missing from the system
+    diffusion: [f32; 4],
+    /// Concentrations outside the walls of the ADI solver with Dirichlet boundaries, the
+    /// background of each species
+    boundary_values: [f32; 4],
+    /// Named parameters of the reactions, see `Reactions`
+    parameters: [[f32; 4]; Reactions::MAX_PARAMETERS / 4],
+}
+
+impl ConfigUniform {
+    fn set_weights(&mut self, weights: Weights) {
+        self.weight_x = weights.x;
+        self.weight_y = weights.y;
+        self.weight_diagonal = weights.diagonal;
+        self.weight_antidiagonal = weights.antidiagonal;
+        self.weight_x2 = weights.x2;
+        self.weight_y2 = weights.y2;
+    }
+
+    fn set_volume_weights(&mut self, weights: VolumeWeights) {
+        self.weight_face = weights.face;
+        self.weight_edge = weights.edge;
+        self.weight_corner = weights.corner;
+    }
+}
+
+/// Compute pipelines created from the diffusion shader, rebuilt when it is reloaded
+struct Pipelines {
+    step: wgpu::ComputePipeline,
+    /// Runs several steps per dispatch, only created if `steps_per_dispatch` is greater than one
+    fused: Option<wgpu::ComputePipeline>,
+    /// Semi-Lagrangian advection of the field, dispatched before each step, only created with that
+    /// advection scheme
+    advection: Option<wgpu::ComputePipeline>,
+    /// Advances `step_counter` on the GPU, between the dispatches of a compute pass
+    step_counter: wgpu::ComputePipeline,
+    /// Counts the invalid cells of the field, see `Health`
+    check: wgpu::ComputePipeline,
+    implicit_solver: Option<ImplicitSolver>,
+}
+
+/// Solver used instead of the explicit kernels, treating the diffusion implicitly
+enum ImplicitSolver {
+    Spectral(Spectral),
+    Adi(Adi),
+}
+
+impl ImplicitSolver {
+    fn step(&self, compute_pass: &mut wgpu::ComputePass, field_bind_group: &wgpu::BindGroup) {
+        match self {
+            Self::Spectral(spectral) => spectral.step(compute_pass, field_bind_group),
+            Self::Adi(adi) => adi.step(compute_pass, field_bind_group),
+        }
+    }
 }
 
 pub(crate) struct Diffusion {
     size: u32,
-    compute_pipeline: wgpu::ComputePipeline,
+    pipelines: Pipelines,
     bind_group_layout: wgpu::BindGroupLayout,
+    /// Layout of `aux_bind_group`
+    aux_bind_group_layout: wgpu::BindGroupLayout,
     bind_group_a: wgpu::BindGroup,
     bind_group_b: wgpu::BindGroup,
+    /// Step number seen by the compute shaders, which key the stochastic noise on it
+    step_counter: wgpu::Buffer,
+    diffusivity_map: wgpu::Buffer,
+    /// Walls of the domain, one value per cell, see `mask.rs`
+    mask: wgpu::Buffer,
+    /// Copy of `mask`, edited by `paint_mask`
+    mask_values: Vec<u32>,
+    /// Name of the velocity field advecting the species, if any
+    velocity: Option<String>,
+    /// Largest absolute velocity along x and y, before `velocity_strength`
+    velocity_max: [f32; 2],
+    advection: Advection,
+    growth: Option<DomainGrowth>,
+    /// Grid spacing along x of the domain before it grows
+    initial_dx: f32,
+    /// Relative length of the domain when the grid was last remapped, see `update_growth`
+    growth_length: f32,
+    /// Triangle mesh simulated instead of the grid, its vertices being stored row by row
+    mesh: Option<Mesh>,
+    /// Size in pixels of the images of the field, see `Config::image_size`
+    image_size: (u32, u32),
+    /// Bind group 1 of the compute shaders, with the step counter, the diffusivity map, the
+    /// velocity field and the Laplacian of the mesh
+    aux_bind_group: wgpu::BindGroup,
+    health: Health,
 
-    buffer_a0: wgpu::Buffer,
-    buffer_a1: wgpu::Buffer,
-    buffer_b0: wgpu::Buffer,
-    buffer_b1: wgpu::Buffer,
+    storage: Storage,
+    system: System,
 
     step_number: u64,
+    /// Simulated time, i.e. the sum of the timesteps of all the steps
+    time: f64,
+    /// Whether the current field is stored in the second copy of the storage
+    swapped: bool,
     uniform: ConfigUniform,
+    colormap: Colormap,
+    noise_mode: NoiseMode,
+    tensor: DiffusionTensor,
+    stencil: Stencil,
+    lattice: Lattice,
+    solver: Solver,
+    boundary: Boundary,
+    /// Weights of the diffusion operator, see `operator_weights`
+    weights: Weights,
+    /// Weights of the diffusion operator of 3D volumes, which replace `weights`
+    volume_weights: Option<VolumeWeights>,
+    volume_stencil: VolumeStencil,
+    view: View,
+    slice_axis: Axis,
+    /// Largest decay rate of the discrete diffusion operator, see `Weights::spectral_radius`
+    spectral_radius: f32,
+    /// Largest value of the diffusivity map, or 1 without a map
+    diffusivity_max: f32,
+    /// Timestep set by the user, the one used depends on `timestep_mode`
+    requested_timestep: f32,
+    timestep_mode: TimestepMode,
     uniform_buffer: wgpu::Buffer,
     uniform_has_changed: bool,
+    /// Set when the decay rates of the spectral solver must be uploaded
+    weights_have_changed: bool,
     to_be_reset: bool,
+    /// Set when the field should be restored from the last good state of `health`
+    to_be_rolled_back: bool,
+    /// Amplitude of the initial noise of the current field
+    noise: f32,
+    /// Seed of the initial and stochastic noise of the current field, kept so that resets
+    /// reproduce the same field
+    seed: u64,
+    /// Amplitude of the initial noise from the next reset, see `set_noise`
+    next_noise: f32,
+    /// Seed of the noise from the next reset, see `set_seed`
+    next_seed: u64,
 }
 
 impl Diffusion {
     const SHADER: &'static str = include_str!("diffusion.wgsl");
+    /// Side of the tile processed by each workgroup of the fused kernel, see `diffusion.wgsl`
+    const TILE: u32 = 32;
+    /// Relative growth of a uniformly growing domain after which the grid is remapped
+    const GROWTH_REMAP: f32 = 0.01;
+    /// Maximum number of steps that the fused kernel can run in a single dispatch
+    pub(crate) const MAX_STEPS_PER_DISPATCH: u32 = 8;
+    /// Fraction of the stability limit used by the clamped and adaptive timesteps
+    const TIMESTEP_SAFETY: f32 = 0.9;
+    /// Initial orientation of the camera of the isosurface view, in radians
+    const INITIAL_CAMERA_YAW: f32 = 0.6;
+    const INITIAL_CAMERA_PITCH: f32 = 0.4;
+    /// Largest pitch of the camera of the isosurface view, in radians
+    const MAX_CAMERA_PITCH: f32 = 1.5;
 
-    fn init_values(width: u32, height: u32) -> (Vec<f32>, Vec<f32>) {
+    /// Initial concentrations of each species, with uniform noise of amplitude `noise` generated
+    /// from `seed`, and the seeded square in the middle of the first `columns`. The layers of 3D
+    /// volumes follow each other, and meshes are seeded around a vertex, see `Mesh::seeded`.
+    fn init_values(
+        species: &[Species],
+        (width, height, depth): (u32, u32, u32),
+        columns: u32,
+        mesh: Option<&Mesh>,
+        noise: f32,
+        seed: u64,
+    ) -> Vec<Vec<f32>> {
+        let columns = columns as usize;
         let width = width as usize;
-        let height = height as usize;
-        let size = width * height;
+        let layer_size = width * height as usize;
+        let depth = depth as usize;
+        let size = layer_size * depth;
 
-        let mut a_init_values = Vec::<f32>::with_capacity(size);
-        let mut b_init_values = Vec::<f32>::with_capacity(size);
+        let mesh_seeded = mesh.map(Mesh::seeded);
+        let mut random = Random::new(seed);
+        let mut init_values = vec![Vec::<f32>::with_capacity(size); species.len()];
         for i in 0..size {
-            if i > size / 5 * 2
-                && i < size / 5 * 3
-                && i % width > width / 5 * 2
-                && i % width < width / 5 * 3
-            {
-                a_init_values.push(0.0);
-                b_init_values.push(1.0);
-            } else {
-                a_init_values.push(1.0);
-                b_init_values.push(0.0);
+            let j = i % layer_size;
+            // the layers in the middle of volumes, at least one
+            let z = i / layer_size;
+            let in_middle_layers = depth == 1 || (z >= depth / 5 * 2 && z <= depth / 5 * 3);
+            let seeded = match &mesh_seeded {
+                // the cells after the last vertex are not used
+                Some(seeded) => seeded.get(i).copied().unwrap_or(false),
+                None => {
+                    j > layer_size / 5 * 2
+                        && j < layer_size / 5 * 3
+                        && j % width > columns / 5 * 2
+                        && j % width < columns / 5 * 3
+                        && in_middle_layers
+                }
+            };
+            for (species, values) in species.iter().zip(&mut init_values) {
+                let value = if seeded {
+                    species.seeded
+                } else {
+                    species.background
+                };
+                if noise > 0.0 {
+                    let value_noise = noise * random.next_signed();
+                    values.push((value + value_noise).clamp(0.0, 1.0));
+                } else {
+                    values.push(value);
+                }
+            }
+        }
+        init_values
+    }
+
+    /// Weights of the diffusion operator of `solver`, the ADI solver only handling the second
+    /// derivatives along the axes, and the hexagonal lattice its isotropic operator
+    fn operator_weights(
+        tensor: &DiffusionTensor,
+        (stencil, lattice): (Stencil, Lattice),
+        solver: Solver,
+        spacing: (f32, f32),
+    ) -> Weights {
+        if lattice == Lattice::Hexagonal {
+            Weights::hexagonal(stencil, spacing.0)
+        } else if solver == Solver::Adi {
+            tensor.axis_weights(stencil, spacing)
+        } else {
+            tensor.weights(stencil, spacing)
+        }
+    }
+
+    /// Upload the weights of the diffusion operator computed from `tensor`
+    fn update_weights(&mut self) {
+        // the cotangent Laplacian of meshes is isotropic
+        if self.mesh.is_some() {
+            return;
+        }
+        let weights = Self::operator_weights(
+            &self.tensor,
+            (self.stencil, self.lattice),
+            self.solver,
+            (self.uniform.dx, self.uniform.dy),
+        );
+        self.weights = weights;
+        self.uniform_has_changed = true;
+        self.weights_have_changed = true;
+        self.uniform.set_weights(weights);
+        self.spectral_radius = Self::spectral_radius(weights, self.volume_weights);
+        self.update_timestep();
+    }
+
+    /// Largest decay rate of the diffusion operator, the one of the volume if there is one
+    fn spectral_radius(weights: Weights, volume_weights: Option<VolumeWeights>) -> f32 {
+        volume_weights.map_or_else(|| weights.spectral_radius(), |w| w.spectral_radius())
+    }
+
+    /// Largest timestep for which the explicit scheme is stable, considering the diffusion and
+    /// the upwind advection. The implicit solvers are stable for any timestep.
+    pub(crate) fn stable_timestep(&self) -> f32 {
+        if self.solver != Solver::Explicit {
+            return f32::INFINITY;
+        }
+        // the rates of the species missing from the model are zero
+        let diffusion = self.uniform.diffusion.into_iter().fold(0.0, f32::max)
+            * self.diffusivity_max
+            * self.spectral_radius;
+        // Courant number of the upwind scheme, the semi-Lagrangian one being stable
+        let advection = if self.uniform.advection == Advection::Upwind as u32 {
+            self.uniform.velocity_strength.abs()
+                * (self.velocity_max[0] / self.uniform.dx + self.velocity_max[1] / self.uniform.dy)
+        } else {
+            0.0
+        };
+        let rate = 0.5 * diffusion + advection;
+        if rate > 0.0 {
+            1.0 / rate
+        } else {
+            f32::INFINITY
+        }
+    }
+
+    /// Apply the timestep mode to the requested timestep
+    fn update_timestep(&mut self) {
+        let stable_timestep = self.stable_timestep();
+        let timestep = match self.timestep_mode {
+            TimestepMode::Warn => {
+                if self.requested_timestep > stable_timestep {
+                    log(&format!(
+                        "timestep {} exceeds the stability limit {}",
+                        self.requested_timestep, stable_timestep
+                    ));
+                }
+                self.requested_timestep
+            }
+            TimestepMode::Clamp => self
+                .requested_timestep
+                .min(Self::TIMESTEP_SAFETY * stable_timestep),
+            TimestepMode::Adaptive if stable_timestep.is_finite() => {
+                Self::TIMESTEP_SAFETY * stable_timestep
             }
+            TimestepMode::Adaptive => self.requested_timestep,
+        };
+        if timestep != self.uniform.timestep {
+            self.uniform_has_changed = true;
+            self.uniform.timestep = timestep;
         }
-        (a_init_values, b_init_values)
     }
 
-    pub(crate) fn new(config: &Config, device: &wgpu::Device) -> Self {
-        let width = config.width;
-        let height = config.height;
-        let size = width * height;
+    /// 32 bits seed of the stochastic noise, derived from the 64 bits one
+    fn shader_seed(seed: u64) -> u32 {
+        (seed ^ (seed >> 32)) as u32
+    }
+
+    /// Create the compute pipelines from `shader`, the diffusion shader preceded by the
+    /// reactions, for the storage, solver and grid of `uniform`
+    #[allow(clippy::too_many_arguments)]
+    fn create_pipelines(
+        device: &wgpu::Device,
+        shader: &str,
+        storage: &Storage,
+        bind_group_layouts: [&wgpu::BindGroupLayout; 2],
+        solver: Solver,
+        uniform: &ConfigUniform,
+        weights: Weights,
+    ) -> Pipelines {
+        let [bind_group_layout, aux_bind_group_layout] = bind_group_layouts;
+        let compute_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Diffusion PipelineLayout"),
+                bind_group_layouts: &bind_group_layouts,
+                push_constant_ranges: &[],
+            });
+
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Diffusion Shader"),
+            source: wgpu::ShaderSource::Wgsl(Self::prelude(storage, shader).into()),
+        });
+
+        let create_compute_pipeline = |label, entry_point| {
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some(label),
+                layout: Some(&compute_pipeline_layout),
+                module: &module,
+                entry_point: Some(entry_point),
+                compilation_options: Default::default(),
+                cache: None,
+            })
+        };
+        let step = if uniform.has_mesh != 0 {
+            create_compute_pipeline("Diffusion mesh ComputePipeline", "diffusion_step_mesh")
+        } else if uniform.depth > 1 {
+            create_compute_pipeline("Diffusion volume ComputePipeline", "diffusion_step_volume")
+        } else {
+            create_compute_pipeline("Diffusion ComputePipeline", "diffusion_step")
+        };
+        // the tile of the fused kernel only fits in workgroup memory with two species
+        let fused = (uniform.steps_per_dispatch > 1)
+            .then(|| create_compute_pipeline("Diffusion fused ComputePipeline", "diffusion_steps"));
+        let advection = (uniform.advection == Advection::SemiLagrangian as u32)
+            .then(|| create_compute_pipeline("Advection ComputePipeline", "advection_step"));
+        let step_counter =
+            create_compute_pipeline("Step counter ComputePipeline", "advance_step_counter");
+        let check = create_compute_pipeline("Check ComputePipeline", "check_field");
+
+        let implicit_shader =
+            |solver_shader| Self::prelude(storage, &format!("{}\n{}", shader, solver_shader));
+        let implicit_solver = match solver {
+            Solver::Explicit => None,
+            Solver::Spectral => Some(ImplicitSolver::Spectral(Spectral::new(
+                device,
+                implicit_shader(Spectral::SHADER),
+                bind_group_layout,
+                aux_bind_group_layout,
+                uniform.width,
+                uniform.height,
+                weights,
+            ))),
+            Solver::Adi => Some(ImplicitSolver::Adi(Adi::new(
+                device,
+                implicit_shader(Adi::SHADER),
+                bind_group_layout,
+                aux_bind_group_layout,
+                uniform.width,
+                uniform.height,
+            ))),
+        };
+
+        Pipelines {
+            step,
+            fused,
+            advection,
+            step_counter,
+            check,
+            implicit_solver,
+        }
+    }
+
+    /// Rebuild the compute pipelines from `shader`, a new source of `diffusion.wgsl`, keeping
+    /// the field. On error the current pipelines are kept and the error is returned.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn reload_shader(
+        &mut self,
+        device: &wgpu::Device,
+        shader: &str,
+    ) -> Result<(), String> {
+        device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let pipelines = Self::create_pipelines(
+            device,
+            &format!("{}\n{}", self.system.shader_source(), shader),
+            &self.storage,
+            [&self.bind_group_layout, &self.aux_bind_group_layout],
+            self.solver,
+            &self.uniform,
+            self.weights,
+        );
+        if let Some(error) = pollster::block_on(device.pop_error_scope()) {
+            return Err(error.to_string());
+        }
+        self.pipelines = pipelines;
+        Ok(())
+    }
+
+    pub(crate) fn new(config: &Config, device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+        let mesh = config.mesh.as_ref().and_then(|path| {
+            Mesh::load(path)
+                .inspect_err(|e| {
+                    log(&format!(
+                        "error while loading mesh {}: {}",
+                        path.display(),
+                        e
+                    ))
+                })
+                .ok()
+        });
+        let is_mesh = mesh.is_some();
+        if is_mesh && config.depth > 1 {
+            log("the depth is ignored on meshes");
+        }
+        let (width, height) = mesh
+            .as_ref()
+            .map_or((config.width, config.height), Mesh::grid_size);
+        let depth = if is_mesh { 1 } else { config.depth };
+        let is_volume = depth > 1;
+        let size = mesh
+            .as_ref()
+            .map_or(width * height * depth, |mesh| mesh.positions.len() as u32);
+
+        let seed = config.seed.unwrap_or_else(random_seed);
+        if config.noise > 0.0 {
+            log(&format!(
+                "initial noise {} with seed {}",
+                config.noise, seed
+            ));
+        }
+
+        if is_volume && config.diffusivity_map.is_some() {
+            log("the diffusivity map is ignored in 3D volumes");
+        } else if is_mesh && config.diffusivity_map.is_some() {
+            log("the diffusivity map is ignored on meshes");
+        }
+        let diffusivity_map = config
+            .diffusivity_map
+            .as_ref()
+            .filter(|_| !is_volume && !is_mesh)
+            .and_then(|path| {
+                read_file(path)
+                    .and_then(|bytes| crate::diffusivity::load(&bytes, width, height))
+                    .inspect_err(|e| {
+                        log(&format!(
+                            "error while loading diffusivity map {}: {}",
+                            path.display(),
+                            e
+                        ))
+                    })
+                    .ok()
+            });
+
+        let system = System::new(config);
+
+        let lattice = match config.lattice {
+            Lattice::Hexagonal if is_volume => {
+                log("the hexagonal lattice is ignored in 3D volumes");
+                Lattice::Square
+            }
+            Lattice::Hexagonal if is_mesh => {
+                log("the hexagonal lattice is ignored on meshes");
+                Lattice::Square
+            }
+            lattice => lattice,
+        };
+        let is_hexagonal = lattice == Lattice::Hexagonal;
+        // the 7-point stencil of the hexagonal lattice only has the nearest neighbours
+        let radius = if is_hexagonal {
+            1
+        } else {
+            config.stencil.radius()
+        };
+        let has_velocity = config.velocity != VelocityField::None || config.velocity_map.is_some();
+        // the tile of the fused kernel must keep a non empty interior
+        let steps_per_dispatch =
+            if is_volume || is_mesh || system.species.len() != 2 || has_velocity || is_hexagonal {
+                1
+            } else {
+                config
+                    .steps_per_dispatch
+                    .min(Self::MAX_STEPS_PER_DISPATCH / radius)
+            };
+        if is_volume && config.steps_per_dispatch > 1 {
+            log("3D volumes are simulated one step per dispatch");
+        } else if is_mesh && config.steps_per_dispatch > 1 {
+            log("meshes are simulated one step per dispatch");
+        } else if system.species.len() != 2 && config.steps_per_dispatch > 1 {
+            log(&format!(
+                "the {} model is simulated one step per dispatch",
+                system.name()
+            ));
+        } else if has_velocity && config.steps_per_dispatch > 1 {
+            log("advection is simulated one step per dispatch");
+        } else if is_hexagonal && config.steps_per_dispatch > 1 {
+            log("the hexagonal lattice is simulated one step per dispatch");
+        } else if steps_per_dispatch < config.steps_per_dispatch {
+            log(&format!(
+                "steps per dispatch limited to {} with the {:?} stencil",
+                steps_per_dispatch, config.stencil
+            ));
+        }
+
+        let solver = match config.solver {
+            solver if is_volume && solver != Solver::Explicit => {
+                log(&format!(
+                    "the {:?} solver does not support 3D volumes, falling back to the explicit \
+                     solver",
+                    solver
+                ));
+                Solver::Explicit
+            }
+            solver if is_mesh && solver != Solver::Explicit => {
+                log(&format!(
+                    "the {:?} solver does not support meshes, falling back to the explicit solver",
+                    solver
+                ));
+                Solver::Explicit
+            }
+            solver if is_hexagonal && solver != Solver::Explicit => {
+                log(&format!(
+                    "the {:?} solver does not support the hexagonal lattice, falling back to the \
+                     explicit solver",
+                    solver
+                ));
+                Solver::Explicit
+            }
+            solver if system.species.len() != 2 && solver != Solver::Explicit => {
+                log(&format!(
+                    "the {:?} solver only supports two species, falling back to the explicit \
+                     solver for the {} model",
+                    solver,
+                    system.name()
+                ));
+                Solver::Explicit
+            }
+            Solver::Spectral if !Spectral::supports(width, height) => {
+                log(&format!(
+                    "the spectral solver needs a width and a height that are powers of two, \
+                     falling back to the explicit solver for {}x{}",
+                    width, height
+                ));
+                Solver::Explicit
+            }
+            solver => solver,
+        };
+        if solver != Solver::Explicit && diffusivity_map.is_some() {
+            log(&format!(
+                "the {:?} solver ignores the diffusivity map",
+                solver
+            ));
+        }
+        let supports_mask = Self::supports_mask(is_volume || is_mesh, solver);
+        if config.mask.is_some() && !supports_mask {
+            log("the mask is only supported by the explicit solver on 2D grids");
+        }
+        let mask = config
+            .mask
+            .as_ref()
+            .filter(|_| supports_mask)
+            .and_then(|path| {
+                read_file(path)
+                    .and_then(|bytes| crate::mask::load(&bytes, width, height))
+                    .inspect_err(|e| {
+                        log(&format!(
+                            "error while loading mask {}: {}",
+                            path.display(),
+                            e
+                        ))
+                    })
+                    .ok()
+            });
+
+        let supports_advection =
+            Self::supports_advection(is_volume || is_mesh, solver) && !is_hexagonal;
+        if has_velocity && is_hexagonal {
+            log("advection is not supported on the hexagonal lattice");
+        } else if has_velocity && !supports_advection {
+            log("advection is only supported by the explicit solver on 2D grids");
+        }
+        let velocity_map = config
+            .velocity_map
+            .as_ref()
+            .filter(|_| supports_advection)
+            .and_then(|path| {
+                read_file(path)
+                    .and_then(|bytes| crate::velocity::load(&bytes, width, height))
+                    .inspect_err(|e| {
+                        log(&format!(
+                            "error while loading velocity map {}: {}",
+                            path.display(),
+                            e
+                        ))
+                    })
+                    .ok()
+            });
+        let velocity = match velocity_map {
+            Some(velocity) => Some(("Map".to_string(), velocity)),
+            None if supports_advection && config.velocity == VelocityField::Uniform => Some((
+                format!("Uniform at {} degrees", config.velocity_angle),
+                crate::velocity::field(config.velocity, width, height, config.velocity_angle),
+            )),
+            None if supports_advection && config.velocity != VelocityField::None => Some((
+                format!("{:?}", config.velocity),
+                crate::velocity::field(config.velocity, width, height, config.velocity_angle),
+            )),
+            None => None,
+        };
 
+        let growth = DomainGrowth::new(config).filter(|growth| {
+            let unsupported = match growth.mode {
+                _ if is_volume => Some("in 3D volumes"),
+                _ if is_mesh => Some("on meshes"),
+                Growth::Apical if solver != Solver::Explicit => Some("by the implicit solvers"),
+                Growth::Uniform if is_hexagonal => Some("on the hexagonal lattice"),
+                _ => None,
+            };
+            if let Some(unsupported) = unsupported {
+                log(&format!(
+                    "the {:?} growth is not supported {}",
+                    growth.mode, unsupported
+                ));
+            }
+            unsupported.is_none()
+        });
+        let active_width = growth.map_or(width, |growth| growth.columns(width, 0.0));
         // TODO: support more shapes
-        let (a_init_values, b_init_values) = Self::init_values(width, height);
+        let init_values = Self::init_values(
+            &system.species,
+            (width, height, depth),
+            active_width,
+            mesh.as_ref(),
+            config.noise,
+            seed,
+        );
 
-        let config_uniform = ConfigUniform {
+        let tensor = DiffusionTensor::new(config);
+        let (dx, dy) = config.spacing();
+        // the rows of the hexagonal lattice are closer than its columns
+        let dy = if is_hexagonal {
+            if config.domain_height.is_some() {
+                log("the domain height is ignored on the hexagonal lattice");
+            }
+            dx * 3f32.sqrt() / 2.0
+        } else if is_mesh || is_volume {
+            if config.domain_height.is_some() {
+                log(if is_mesh {
+                    "the domain height is ignored on meshes"
+                } else {
+                    "the domain height is ignored in 3D volumes, whose cells are cubes"
+                });
+            }
+            dx
+        } else {
+            dy
+        };
+        if is_hexagonal && height % 2 == 1 {
+            log("the hexagonal lattice is only periodic along y with an even height");
+        }
+        if is_hexagonal && (config.diffusion_x != 1.0 || config.diffusion_y != 1.0) {
+            log("anisotropic diffusion is ignored on the hexagonal lattice");
+        }
+        if is_mesh && (config.diffusion_x != 1.0 || config.diffusion_y != 1.0) {
+            log("anisotropic diffusion is ignored on meshes");
+        }
+        // the edges of meshes are one cell long on average
+        let mesh = mesh.map(|mut mesh| {
+            mesh.scale_edges(dx);
+            mesh
+        });
+        let laplacian = mesh
+            .as_ref()
+            .map(|mesh| mesh.laplacian(config.stencil.scale()));
+        // 3D volumes have cubic cells of side dx and isotropic diffusion
+        let volume_weights =
+            is_volume.then(|| VolumeWeights::new(config.volume_stencil, config.stencil, dx));
+        if is_volume && (config.diffusion_x != 1.0 || config.diffusion_y != 1.0) {
+            log("anisotropic diffusion is ignored in 3D volumes");
+        }
+        let slice_axis_size = match config.slice_axis {
+            Axis::X => width,
+            Axis::Y => height,
+            Axis::Z => depth,
+        };
+        let mut config_uniform = ConfigUniform {
             width,
             height,
             size,
             timestep: config.timestep,
-            diffusion_a: config.diffusion_a,
-            diffusion_b: config.diffusion_b,
             feed: config.feed,
             kill: config.kill,
+            steps_per_dispatch,
+            colormap: config.colormap as u32,
+            dx,
+            dy,
+            stochastic_noise: config.stochastic_noise,
+            noise_mode: config.noise_mode as u32,
+            seed: Self::shader_seed(seed),
+            weight_x: 0.0,
+            weight_y: 0.0,
+            weight_diagonal: 0.0,
+            weight_antidiagonal: 0.0,
+            weight_x2: 0.0,
+            weight_y2: 0.0,
+            radius,
+            has_diffusivity_map: diffusivity_map.is_some() as u32,
+            max_value: config.max_value,
+            boundary: config.boundary as u32,
+            depth,
+            weight_face: 0.0,
+            weight_edge: 0.0,
+            weight_corner: 0.0,
+            view: config.view as u32,
+            slice_axis: config.slice_axis as u32,
+            slice: config
+                .slice
+                .unwrap_or(slice_axis_size / 2)
+                .min(slice_axis_size - 1),
+            iso_level: config.iso_level,
+            camera_yaw: Self::INITIAL_CAMERA_YAW,
+            camera_pitch: Self::INITIAL_CAMERA_PITCH,
+            has_mask: mask.is_some() as u32,
+            advection: velocity.as_ref().map_or(0, |_| config.advection as u32),
+            velocity_strength: config.velocity_strength,
+            dilution: growth.map_or(0.0, |growth| growth.dilution(0.0)),
+            active_width,
+            lattice: lattice as u32,
+            has_mesh: is_mesh as u32,
+            diffusion: [0, 1, 2, 3].map(|i| {
+                system
+                    .species
+                    .get(i)
+                    .map_or(0.0, |species| species.diffusion)
+            }),
+            boundary_values: [0, 1, 2, 3].map(|i| {
+                system
+                    .species
+                    .get(i)
+                    .map_or(0.0, |species| species.background)
+            }),
+            parameters: [[0.0; 4]; Reactions::MAX_PARAMETERS / 4],
         };
+        if let Some(reactions) = &system.reactions {
+            for (i, (_, value)) in reactions.parameters.iter().enumerate() {
+                config_uniform.parameters[i / 4][i % 4] = *value;
+            }
+        }
+        if solver == Solver::Adi && tensor.has_mixed_derivative() {
+            log("the ADI solver ignores the mixed derivative of rotated anisotropic diffusion");
+        }
+        let weights = Self::operator_weights(&tensor, (config.stencil, lattice), solver, (dx, dy));
+        config_uniform.set_weights(weights);
+        if let Some(volume_weights) = volume_weights {
+            config_uniform.set_volume_weights(volume_weights);
+        }
         let buffer_uniforms = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Config"),
             contents: bytemuck::cast_slice(&[config_uniform]),
             usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
         });
 
-        let buffer_a0 = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Buffer A0"),
-            contents: bytemuck::cast_slice(&a_init_values),
-            usage: BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
-        });
-        let buffer_a1 = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Buffer A1"),
-            contents: bytemuck::cast_slice(&a_init_values),
-            usage: BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
-        });
-        let buffer_b0 = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Buffer B0"),
-            contents: bytemuck::cast_slice(&b_init_values),
-            usage: BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
-        });
-        let buffer_b1 = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Buffer B1"),
-            contents: bytemuck::cast_slice(&b_init_values),
-            usage: BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
-        });
+        // the layers of 3D volumes are stacked vertically
+        let rows = height * depth;
+        let storage_layout = if config.storage != StorageLayout::Buffers
+            && rows > device.limits().max_texture_dimension_2d
+        {
+            log(&format!(
+                "the volume needs a texture of {} rows, more than the limit of the device, \
+                 falling back to buffers",
+                rows
+            ));
+            StorageLayout::Buffers
+        } else {
+            config.storage
+        };
+        let storage = Storage::new(storage_layout, device, queue, width, rows, &init_values);
+        // the two copies of the snapshot hold the last good field and the field being checked
+        let snapshot = config
+            .rollback
+            .then(|| Storage::new(storage_layout, device, queue, width, rows, &init_values));
+        let health = Health::new(config, device, snapshot);
 
-        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-            label: Some("Diffusion BindGroupLayout"),
-            entries: &[
-                // config
-                wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::all(),
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                },
-                // A input
-                wgpu::BindGroupLayoutEntry {
-                    binding: 1,
-                    visibility: wgpu::ShaderStages::all(),
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Storage { read_only: true },
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
+        let layout_entries: Vec<_> = std::iter::once(
+            // config
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::all(),
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
                 },
-                // B input
-                wgpu::BindGroupLayoutEntry {
-                    binding: 2,
-                    visibility: wgpu::ShaderStages::all(),
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Storage { read_only: true },
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                },
-                // A output
-                wgpu::BindGroupLayoutEntry {
-                    binding: 3,
-                    visibility: wgpu::ShaderStages::COMPUTE,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Storage { read_only: false },
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                },
-                // B output
-                wgpu::BindGroupLayoutEntry {
-                    binding: 4,
-                    visibility: wgpu::ShaderStages::COMPUTE,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Storage { read_only: false },
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
+                count: None,
+            },
+        )
+        .chain(storage.layout_entries())
+        .chain(std::iter::once(
+            // mask
+            wgpu::BindGroupLayoutEntry {
+                binding: storage.next_binding(),
+                visibility: wgpu::ShaderStages::all(),
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: true },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
                 },
-            ],
-        });
+                count: None,
+            },
+        ))
+        .collect();
 
-        let compute_pipeline_layout =
-            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                label: Some("Diffusion PipelineLayout"),
-                bind_group_layouts: &[&bind_group_layout],
-                push_constant_ranges: &[],
-            });
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Diffusion BindGroupLayout"),
+            entries: &layout_entries,
+        });
 
-        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: Some("Diffusion Shader"),
-            source: wgpu::ShaderSource::Wgsl(Self::SHADER.into()),
+        let step_counter = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Step counter"),
+            size: std::mem::size_of::<[u32; 2]>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let diffusivity_map_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Diffusivity map"),
+            contents: bytemuck::cast_slice(diffusivity_map.as_deref().unwrap_or(&vec![
+                1.0f32;
+                size
+                    as usize
+            ])),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
         });
 
-        let compute_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
-            label: Some("Diffusion ComputePipeline"),
-            layout: Some(&compute_pipeline_layout),
-            module: &shader,
-            entry_point: None,
-            compilation_options: Default::default(),
-            cache: None,
+        let mask_values = mask.unwrap_or_else(|| vec![1; size as usize]);
+        let mask_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Mask"),
+            contents: bytemuck::cast_slice(&mask_values),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
         });
 
-        // Buffer A0 and B0 are inputs, A1 and B1 are outputs
-        let bind_group_a = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some("Bind group A"),
-            layout: &bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: buffer_uniforms.as_entire_binding(),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: buffer_a0.as_entire_binding(),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 2,
-                    resource: buffer_b0.as_entire_binding(),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 3,
-                    resource: buffer_a1.as_entire_binding(),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 4,
-                    resource: buffer_b1.as_entire_binding(),
-                },
-            ],
+        let (velocity, velocity_values) = velocity.unzip();
+        let velocity_max = velocity_values.as_deref().map_or([0.0; 2], |values| {
+            values.iter().fold([0.0f32; 2], |max, [u, v]| {
+                [max[0].max(u.abs()), max[1].max(v.abs())]
+            })
+        });
+        let velocity_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Velocity"),
+            contents: bytemuck::cast_slice(velocity_values.as_deref().unwrap_or(&vec![
+                [0.0f32; 2];
+                size
+                    as usize
+            ])),
+            usage: wgpu::BufferUsages::STORAGE,
+        });
+        let mesh_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Mesh Laplacian"),
+            contents: bytemuck::cast_slice(
+                laplacian
+                    .as_ref()
+                    .map_or(&[[0u32; 2]][..], |laplacian| &laplacian.entries),
+            ),
+            usage: wgpu::BufferUsages::STORAGE,
         });
 
-        // Buffer A0 and B0 are outputs, A1 and B1 are inputs
-        let bind_group_b = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some("Bind group B"),
-            layout: &bind_group_layout,
+        let aux_storage_entry = |binding, read_only| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let aux_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("Diffusion auxiliary BindGroupLayout"),
+                entries: &[
+                    // step counter
+                    aux_storage_entry(0, false),
+                    // diffusivity map
+                    aux_storage_entry(1, true),
+                    // divergence counters
+                    aux_storage_entry(2, false),
+                    // velocity field
+                    aux_storage_entry(3, true),
+                    // Laplacian of the mesh
+                    aux_storage_entry(4, true),
+                ],
+            });
+        let aux_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Diffusion auxiliary BindGroup"),
+            layout: &aux_bind_group_layout,
             entries: &[
                 wgpu::BindGroupEntry {
                     binding: 0,
-                    resource: buffer_uniforms.as_entire_binding(),
+                    resource: step_counter.as_entire_binding(),
                 },
                 wgpu::BindGroupEntry {
                     binding: 1,
-                    resource: buffer_a1.as_entire_binding(),
+                    resource: diffusivity_map_buffer.as_entire_binding(),
                 },
                 wgpu::BindGroupEntry {
                     binding: 2,
-                    resource: buffer_b1.as_entire_binding(),
+                    resource: health.counters().as_entire_binding(),
                 },
                 wgpu::BindGroupEntry {
                     binding: 3,
-                    resource: buffer_a0.as_entire_binding(),
+                    resource: velocity_buffer.as_entire_binding(),
                 },
                 wgpu::BindGroupEntry {
                     binding: 4,
-                    resource: buffer_b0.as_entire_binding(),
+                    resource: mesh_buffer.as_entire_binding(),
                 },
             ],
         });
 
-        Self {
+        let create_bind_group = |label, swapped| {
+            let entries: Vec<_> = std::iter::once(wgpu::BindGroupEntry {
+                binding: 0,
+                resource: buffer_uniforms.as_entire_binding(),
+            })
+            .chain(storage.bind_group_entries(swapped))
+            .chain(std::iter::once(wgpu::BindGroupEntry {
+                binding: storage.next_binding(),
+                resource: mask_buffer.as_entire_binding(),
+            }))
+            .collect();
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some(label),
+                layout: &bind_group_layout,
+                entries: &entries,
+            })
+        };
+
+        // Copy 0 of the field is the input, copy 1 is the output
+        let bind_group_a = create_bind_group("Bind group A", false);
+        // Copy 0 of the field is the output, copy 1 is the input
+        let bind_group_b = create_bind_group("Bind group B", true);
+
+        let pipelines = Self::create_pipelines(
+            device,
+            &format!("{}\n{}", system.shader_source(), Self::SHADER),
+            &storage,
+            [&bind_group_layout, &aux_bind_group_layout],
+            solver,
+            &config_uniform,
+            weights,
+        );
+
+        let mut diffusion = Self {
             size,
-            compute_pipeline,
+            pipelines,
             bind_group_layout,
+            aux_bind_group_layout,
             bind_group_a,
             bind_group_b,
+            step_counter,
+            diffusivity_map: diffusivity_map_buffer,
+            mask: mask_buffer,
+            mask_values,
+            velocity,
+            velocity_max,
+            advection: config.advection,
+            growth,
+            initial_dx: dx,
+            growth_length: 1.0,
+            image_size: if is_mesh {
+                (config.width, config.height)
+            } else {
+                (width, lattice.image_height(height))
+            },
+            mesh,
+            aux_bind_group,
+            health,
             step_number: 0,
+            time: 0.0,
+            swapped: false,
             uniform: config_uniform,
+            colormap: config.colormap,
+            noise_mode: config.noise_mode,
+            tensor,
+            stencil: config.stencil,
+            lattice,
+            solver,
+            boundary: config.boundary,
+            weights,
+            volume_weights,
+            volume_stencil: config.volume_stencil,
+            view: config.view,
+            slice_axis: config.slice_axis,
+            spectral_radius: laplacian.map_or_else(
+                || Self::spectral_radius(weights, volume_weights),
+                |laplacian| laplacian.spectral_radius,
+            ),
+            diffusivity_max: diffusivity_map
+                .as_deref()
+                .map_or(1.0, Self::diffusivity_max),
+            requested_timestep: config.timestep,
+            timestep_mode: config.timestep_mode,
             uniform_buffer: buffer_uniforms,
             uniform_has_changed: false,
-            buffer_a0,
-            buffer_a1,
-            buffer_b0,
-            buffer_b1,
+            weights_have_changed: false,
+            storage,
             to_be_reset: false,
-        }
+            to_be_rolled_back: false,
+            system,
+            noise: config.noise,
+            seed,
+            next_noise: config.noise,
+            next_seed: seed,
+        };
+        diffusion.update_timestep();
+        diffusion
     }
 
     pub(crate) fn bind_group_layout(&self) -> &wgpu::BindGroupLayout {
@@ -266,18 +1097,242 @@ impl Diffusion {
     }
 
     pub(crate) fn current_bind_group(&self) -> &wgpu::BindGroup {
-        if self.step_number % 2 == 0 {
-            &self.bind_group_a
-        } else {
+        self.bind_group(self.swapped)
+    }
+
+    fn bind_group(&self, swapped: bool) -> &wgpu::BindGroup {
+        if swapped {
             &self.bind_group_b
+        } else {
+            &self.bind_group_a
         }
     }
 
+    /// Prepend the storage prelude to the given shader, so that it can access the concentrations
+    /// and the mask
+    pub(crate) fn shader_source(&self, shader: &str) -> String {
+        Self::prelude(&self.storage, shader)
+    }
+
+    /// Prepend to `shader` the prelude of `storage` and the declaration of the mask, bound after
+    /// the storage in group 0
+    fn prelude(storage: &Storage, shader: &str) -> String {
+        storage.shader_source(&format!(
+            "// Whether each cell is open (1) or a wall (0), used if `has_mask` is set\n\
+             @group(0) @binding({}) var<storage, read> mask: array<u32>;\n\n{}",
+            storage.next_binding(),
+            shader
+        ))
+    }
+
     pub(crate) fn step_number(&self) -> u64 {
         self.step_number
     }
 
-    pub(crate) fn render(&mut self, queue: &mut wgpu::Queue, encoder: &mut wgpu::CommandEncoder) {
+    pub(crate) fn time(&self) -> f64 {
+        self.time
+    }
+
+    /// Seed of the noise of the current field, not of the pending one set by `set_seed`
+    pub(crate) fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Parameters needed to reproduce the current field, stored in saved images
+    pub(crate) fn metadata(&self) -> Vec<(&'static str, String)> {
+        let mut metadata = vec![
+            ("Width", self.uniform.width.to_string()),
+            ("Height", self.uniform.height.to_string()),
+            ("Depth", self.uniform.depth.to_string()),
+            ("Dx", self.uniform.dx.to_string()),
+            ("Dy", self.uniform.dy.to_string()),
+            ("Step", self.step_number.to_string()),
+            ("Time", self.time.to_string()),
+            ("Timestep", self.uniform.timestep.to_string()),
+            ("Diffusion A", self.uniform.diffusion[0].to_string()),
+            ("Diffusion B", self.uniform.diffusion[1].to_string()),
+            ("Model", self.system.name()),
+            ("Feed", self.uniform.feed.to_string()),
+            ("Kill", self.uniform.kill.to_string()),
+            ("Stencil", format!("{:?}", self.stencil)),
+            ("Lattice", format!("{:?}", self.lattice)),
+            ("Volume stencil", format!("{:?}", self.volume_stencil)),
+            ("Solver", format!("{:?}", self.solver)),
+            ("Boundary", format!("{:?}", self.boundary)),
+            ("Diffusion X", self.tensor.x.to_string()),
+            ("Diffusion Y", self.tensor.y.to_string()),
+            ("Diffusion angle", self.tensor.angle.to_string()),
+            (
+                "Diffusivity map",
+                (self.uniform.has_diffusivity_map != 0).to_string(),
+            ),
+            ("Mask", (self.uniform.has_mask != 0).to_string()),
+            (
+                "Velocity",
+                self.velocity.clone().unwrap_or_else(|| "None".to_string()),
+            ),
+            ("Noise", self.noise.to_string()),
+            (
+                "Stochastic noise",
+                self.uniform.stochastic_noise.to_string(),
+            ),
+            ("Noise mode", format!("{:?}", self.noise_mode)),
+            ("Seed", self.seed.to_string()),
+            ("Max value", self.uniform.max_value.to_string()),
+        ];
+        metadata.extend(
+            ["Diffusion C", "Diffusion D"]
+                .into_iter()
+                .zip(self.uniform.diffusion[2..].iter())
+                .take(self.system.species.len() - 2)
+                .map(|(key, diffusion)| (key, diffusion.to_string())),
+        );
+        if let Some(mesh) = &self.mesh {
+            metadata.push(("Mesh", mesh.name.clone()));
+            metadata.push(("Vertices", mesh.positions.len().to_string()));
+        }
+        if let Some(growth) = self.growth {
+            metadata.push(("Growth", format!("{:?}", growth.mode)));
+            metadata.push(("Growth rate", growth.rate.to_string()));
+            metadata.push(("Growth factor", growth.factor.to_string()));
+        }
+        if self.velocity.is_some() {
+            metadata.push((
+                "Velocity strength",
+                self.uniform.velocity_strength.to_string(),
+            ));
+            metadata.push(("Advection", format!("{:?}", self.advection)));
+        }
+        if let Some(reactions) = &self.system.reactions {
+            let parameters: Vec<_> = reactions
+                .parameters
+                .iter()
+                .enumerate()
+                .map(|(i, (name, _))| format!("{}={}", name, self.uniform.parameters[i / 4][i % 4]))
+                .collect();
+            metadata.push(("Reactions", reactions.sources.join("; ")));
+            metadata.push(("Parameters", parameters.join(", ")));
+        }
+        metadata
+    }
+
+    pub(crate) fn width(&self) -> u32 {
+        self.uniform.width
+    }
+
+    pub(crate) fn height(&self) -> u32 {
+        self.uniform.height
+    }
+
+    pub(crate) fn lattice(&self) -> Lattice {
+        self.lattice
+    }
+
+    /// Size in pixels of the images of the field, see `Config::image_size`
+    pub(crate) fn image_size(&self) -> (u32, u32) {
+        self.image_size
+    }
+
+    /// Triangle mesh whose vertices hold the field, if it is not simulated on a grid
+    pub(crate) fn mesh(&self) -> Option<&Mesh> {
+        self.mesh.as_ref()
+    }
+
+    pub(crate) fn timestep(&self) -> f32 {
+        self.uniform.timestep
+    }
+
+    /// Diffusion rate of the species `species`, in the order of `System::NAMES`
+    pub(crate) fn diffusion(&self, species: usize) -> f32 {
+        self.uniform.diffusion[species]
+    }
+
+    pub(crate) fn feed(&self) -> f32 {
+        self.uniform.feed
+    }
+
+    pub(crate) fn kill(&self) -> f32 {
+        self.uniform.kill
+    }
+
+    pub(crate) fn colormap(&self) -> Colormap {
+        self.colormap
+    }
+
+    pub(crate) fn view(&self) -> View {
+        self.view
+    }
+
+    pub(crate) fn slice_axis(&self) -> Axis {
+        self.slice_axis
+    }
+
+    pub(crate) fn slice(&self) -> u32 {
+        self.uniform.slice
+    }
+
+    /// Yaw and pitch of the orbit camera of the isosurface view and of meshes, in radians
+    pub(crate) fn camera(&self) -> (f32, f32) {
+        (self.uniform.camera_yaw, self.uniform.camera_pitch)
+    }
+
+    /// Follow the growth of the domain up to the current time. The grid of uniformly growing
+    /// domains is remapped to their length, with the spacing along x proportional to it, every
+    /// `GROWTH_REMAP` of relative growth, and apically growing domains cover one more column each
+    /// time their tip advances by one.
+    fn update_growth(&mut self) {
+        let Some(growth) = self.growth else {
+            return;
+        };
+        let dilution = growth.dilution(self.time);
+        if dilution != self.uniform.dilution {
+            self.uniform_has_changed = true;
+            self.uniform.dilution = dilution;
+        }
+        match growth.mode {
+            Growth::Uniform => {
+                let length = growth.length(self.time);
+                let is_due = length > self.growth_length * (1.0 + Self::GROWTH_REMAP)
+                    || (length == growth.factor && self.growth_length != length);
+                // the domain shrinks back after resets and rollbacks
+                if is_due || length < self.growth_length {
+                    self.growth_length = length;
+                    self.uniform.dx = self.initial_dx * length;
+                    self.update_weights();
+                }
+            }
+            Growth::Apical => {
+                let columns = growth.columns(self.uniform.width, self.time);
+                if columns != self.uniform.active_width {
+                    self.uniform_has_changed = true;
+                    self.uniform.active_width = columns;
+                }
+            }
+            Growth::None => {}
+        }
+    }
+
+    /// Upload pending changes to the parameters and pending resets
+    fn prepare(&mut self, queue: &wgpu::Queue) {
+        if self.to_be_reset {
+            self.to_be_reset = false;
+            self.noise = self.next_noise;
+            self.seed = self.next_seed;
+            let width = self.uniform.width;
+            let init_values = Self::init_values(
+                &self.system.species,
+                (width, self.uniform.height, self.uniform.depth),
+                self.growth
+                    .map_or(width, |growth| growth.columns(width, 0.0)),
+                self.mesh.as_ref(),
+                self.noise,
+                self.seed,
+            );
+            self.storage.write(queue, &init_values);
+            self.uniform.seed = Self::shader_seed(self.seed);
+            self.uniform_has_changed = true;
+        }
+
         if self.uniform_has_changed {
             self.uniform_has_changed = false;
             queue.write_buffer(
@@ -287,24 +1342,179 @@ impl Diffusion {
             )
         }
 
-        if self.to_be_reset {
-            self.to_be_reset = false;
-            let (a_init_values, b_init_values) =
-                Self::init_values(self.uniform.width, self.uniform.height);
-            queue.write_buffer(&self.buffer_a0, 0, bytemuck::cast_slice(&a_init_values));
-            queue.write_buffer(&self.buffer_a1, 0, bytemuck::cast_slice(&a_init_values));
-            queue.write_buffer(&self.buffer_b0, 0, bytemuck::cast_slice(&b_init_values));
-            queue.write_buffer(&self.buffer_b1, 0, bytemuck::cast_slice(&b_init_values));
+        if self.weights_have_changed {
+            self.weights_have_changed = false;
+            if let Some(ImplicitSolver::Spectral(spectral)) = &self.pipelines.implicit_solver {
+                spectral.set_weights(queue, self.weights);
+            }
+        }
+
+        if self.has_stochastic_noise() {
+            // the counter is only advanced on the GPU when it is needed, resynchronize it
+            let step_number = [self.step_number as u32, (self.step_number >> 32) as u32];
+            queue.write_buffer(&self.step_counter, 0, bytemuck::cast_slice(&step_number));
+        }
+    }
+
+    fn has_stochastic_noise(&self) -> bool {
+        self.uniform.stochastic_noise != 0.0
+    }
+
+    /// Record `n` simulation steps in a single compute pass.
+    ///
+    /// When `steps_per_dispatch` is greater than one, the fused kernel is used to run that many
+    /// steps in each dispatch, and the remaining steps are run one at a time. The implicit solvers
+    /// run each step with their own sequence of dispatches.
+    pub(crate) fn step_many(
+        &mut self,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        n: u32,
+    ) {
+        self.step_many_with_timestamps(queue, encoder, n, None);
+    }
+
+    /// Same as `step_many`, also writing the given timestamps at the beginning and at the end of
+    /// the compute pass
+    pub(crate) fn step_many_with_timestamps(
+        &mut self,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        n: u32,
+        timestamp_writes: Option<wgpu::ComputePassTimestampWrites>,
+    ) {
+        self.update_growth();
+        self.prepare(queue);
+        if self.to_be_rolled_back {
+            self.to_be_rolled_back = false;
+            if let (Some(snapshot), Some(state)) =
+                (self.health.snapshot(), self.health.good_state())
+            {
+                snapshot.copy_field(encoder, state.copy, &self.storage, self.swapped);
+            }
+        }
+        if n == 0 {
+            return;
+        }
+
+        let steps_per_dispatch = self.uniform.steps_per_dispatch;
+        let fused_dispatches = if self.pipelines.fused.is_some() {
+            n / steps_per_dispatch
+        } else {
+            0
+        };
+        let single_steps = n - fused_dispatches * steps_per_dispatch;
+
+        let has_stochastic_noise = self.has_stochastic_noise();
+        let mut swapped = self.swapped;
+        {
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Diffusion ComputePass"),
+                timestamp_writes,
+            });
+            compute_pass.set_bind_group(1, &self.aux_bind_group, &[]);
+
+            if let Some(implicit_solver) = &self.pipelines.implicit_solver {
+                for _ in 0..n {
+                    implicit_solver.step(&mut compute_pass, self.bind_group(swapped));
+                    swapped = !swapped;
+                    if has_stochastic_noise {
+                        compute_pass.set_pipeline(&self.pipelines.step_counter);
+                        compute_pass.dispatch_workgroups(1, 1, 1);
+                    }
+                }
+            } else {
+                if let Some(fused_compute_pipeline) = &self.pipelines.fused {
+                    let interior = Self::TILE - 2 * steps_per_dispatch * self.uniform.radius;
+                    compute_pass.set_pipeline(fused_compute_pipeline);
+                    for _ in 0..fused_dispatches {
+                        compute_pass.set_bind_group(0, self.bind_group(swapped), &[]);
+                        compute_pass.dispatch_workgroups(
+                            self.uniform.width.div_ceil(interior),
+                            self.uniform.height.div_ceil(interior),
+                            1,
+                        );
+                        swapped = !swapped;
+                        if has_stochastic_noise {
+                            compute_pass.set_pipeline(&self.pipelines.step_counter);
+                            compute_pass.dispatch_workgroups(steps_per_dispatch, 1, 1);
+                            compute_pass.set_pipeline(fused_compute_pipeline);
+                        }
+                    }
+                }
+
+                compute_pass.set_pipeline(&self.pipelines.step);
+                for _ in 0..single_steps {
+                    if let Some(advection_pipeline) = &self.pipelines.advection {
+                        compute_pass.set_pipeline(advection_pipeline);
+                        compute_pass.set_bind_group(0, self.bind_group(swapped), &[]);
+                        compute_pass.dispatch_workgroups(self.size.div_ceil(64), 1, 1);
+                        swapped = !swapped;
+                        compute_pass.set_pipeline(&self.pipelines.step);
+                    }
+                    compute_pass.set_bind_group(0, self.bind_group(swapped), &[]);
+                    compute_pass.dispatch_workgroups(self.size.div_ceil(64), 1, 1);
+                    swapped = !swapped;
+                    if has_stochastic_noise {
+                        compute_pass.set_pipeline(&self.pipelines.step_counter);
+                        compute_pass.dispatch_workgroups(1, 1, 1);
+                        compute_pass.set_pipeline(&self.pipelines.step);
+                    }
+                }
+            }
+        }
+        self.swapped = swapped;
+        self.step_number += n as u64;
+        self.time += n as f64 * self.uniform.timestep as f64;
+
+        if self.health.is_due(self.step_number) {
+            self.record_check(encoder);
         }
+    }
 
-        // prepare render pass
+    /// Record the check of the current field for divergence, and its copy to the snapshot if
+    /// rollback is enabled
+    fn record_check(&mut self, encoder: &mut wgpu::CommandEncoder) {
+        encoder.clear_buffer(self.health.counters(), 0, None);
         {
-            let mut compute_pass = encoder.begin_compute_pass(&Default::default());
-            compute_pass.set_pipeline(&self.compute_pipeline);
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Check ComputePass"),
+                timestamp_writes: None,
+            });
+            compute_pass.set_pipeline(&self.pipelines.check);
             compute_pass.set_bind_group(0, self.current_bind_group(), &[]);
+            compute_pass.set_bind_group(1, &self.aux_bind_group, &[]);
             compute_pass.dispatch_workgroups(self.size.div_ceil(64), 1, 1);
         }
-        self.step_number += 1;
+        if let Some(snapshot) = self.health.snapshot() {
+            self.storage
+                .copy_field(encoder, self.swapped, snapshot, self.health.snapshot_copy());
+        }
+        self.health.record(encoder, self.step_number, self.time);
+    }
+
+    /// Advance the divergence check, once the commands recorded by the last `step_many` have
+    /// been submitted. When the field diverged, return the cells found invalid and restore the
+    /// last good state if rollback is enabled.
+    pub(crate) fn check_health(&mut self) -> Option<Divergence> {
+        let mut divergence = self.health.poll()?;
+        if let Some(state) = self.health.good_state() {
+            self.to_be_rolled_back = true;
+            self.step_number = state.step_number;
+            self.time = state.time;
+            self.health.rewind(state.step_number);
+            divergence.rolled_back = true;
+        }
+        Some(divergence)
+    }
+
+    pub(crate) fn set_check_interval(&mut self, check_interval: u64) {
+        self.health.set_check_interval(check_interval);
+    }
+
+    pub(crate) fn set_max_value(&mut self, max_value: f32) {
+        self.uniform_has_changed = true;
+        self.uniform.max_value = max_value;
     }
 
     pub(crate) fn set_kill(&mut self, kill: f32) {
@@ -317,22 +1527,256 @@ impl Diffusion {
         self.uniform.feed = feed;
     }
 
-    pub(crate) fn set_diffusion_a(&mut self, diffusion_a: f32) {
+    /// Change the diffusion rate of the species `species`, in the order of `System::NAMES`, if
+    /// the system has it
+    pub(crate) fn set_diffusion(&mut self, species: usize, diffusion: f32) {
+        if species >= self.system.species.len() {
+            log(&format!(
+                "the {} model has no species {}",
+                self.system.name(),
+                species
+            ));
+            return;
+        }
         self.uniform_has_changed = true;
-        self.uniform.diffusion_a = diffusion_a;
+        self.uniform.diffusion[species] = diffusion;
+        self.update_timestep();
     }
 
-    pub(crate) fn set_diffusion_b(&mut self, diffusion_b: f32) {
+    /// Change the value of a named parameter of the reactions, if they have it
+    pub(crate) fn set_parameter(&mut self, name: &str, value: f32) {
+        let Some(i) = self
+            .system
+            .reactions
+            .as_ref()
+            .and_then(|reactions| reactions.parameter_index(name))
+        else {
+            log(&format!(
+                "the {} model has no parameter {}",
+                self.system.name(),
+                name
+            ));
+            return;
+        };
         self.uniform_has_changed = true;
-        self.uniform.diffusion_b = diffusion_b;
+        self.uniform.parameters[i / 4][i % 4] = value;
     }
 
     pub(crate) fn set_timestep(&mut self, timestep: f32) {
+        self.requested_timestep = timestep;
+        self.update_timestep();
+    }
+
+    pub(crate) fn set_timestep_mode(&mut self, timestep_mode: TimestepMode) {
+        self.timestep_mode = timestep_mode;
+        self.update_timestep();
+    }
+
+    pub(crate) fn set_colormap(&mut self, colormap: Colormap) {
+        self.uniform_has_changed = true;
+        self.colormap = colormap;
+        self.uniform.colormap = colormap as u32;
+    }
+
+    pub(crate) fn set_view(&mut self, view: View) {
+        self.uniform_has_changed = true;
+        self.view = view;
+        self.uniform.view = view as u32;
+    }
+
+    /// Change the axis normal to the slice, keeping the slice index within the volume
+    pub(crate) fn set_slice_axis(&mut self, slice_axis: Axis) {
         self.uniform_has_changed = true;
-        self.uniform.timestep = timestep;
+        self.slice_axis = slice_axis;
+        self.uniform.slice_axis = slice_axis as u32;
+        self.set_slice(self.uniform.slice);
+    }
+
+    /// Change the index of the slice, clamped to the size of the volume along the slice axis
+    pub(crate) fn set_slice(&mut self, slice: u32) {
+        let size = match self.slice_axis {
+            Axis::X => self.uniform.width,
+            Axis::Y => self.uniform.height,
+            Axis::Z => self.uniform.depth,
+        };
+        self.uniform_has_changed = true;
+        self.uniform.slice = slice.min(size - 1);
+    }
+
+    pub(crate) fn set_iso_level(&mut self, iso_level: f32) {
+        self.uniform_has_changed = true;
+        self.uniform.iso_level = iso_level;
+    }
+
+    /// Change the yaw and pitch of the orbit camera, in radians. The pitch is
+    /// clamped so that the camera never looks straight up or down.
+    pub(crate) fn set_camera(&mut self, yaw: f32, pitch: f32) {
+        self.uniform_has_changed = true;
+        self.uniform.camera_yaw = yaw.rem_euclid(std::f32::consts::TAU);
+        self.uniform.camera_pitch = pitch.clamp(-Self::MAX_CAMERA_PITCH, Self::MAX_CAMERA_PITCH);
+    }
+
+    pub(crate) fn set_diffusion_x(&mut self, diffusion_x: f32) {
+        self.tensor.x = diffusion_x;
+        self.update_weights();
+    }
+
+    pub(crate) fn set_diffusion_y(&mut self, diffusion_y: f32) {
+        self.tensor.y = diffusion_y;
+        self.update_weights();
+    }
+
+    pub(crate) fn set_diffusion_angle(&mut self, diffusion_angle: f32) {
+        self.tensor.angle = diffusion_angle;
+        self.update_weights();
+    }
+
+    /// Replace the diffusivity map, or remove it if `None`
+    pub(crate) fn set_diffusivity_map(&mut self, queue: &wgpu::Queue, map: Option<&[f32]>) {
+        if self.uniform.depth > 1 {
+            log("the diffusivity map is ignored in 3D volumes");
+            return;
+        }
+        if self.mesh.is_some() {
+            log("the diffusivity map is ignored on meshes");
+            return;
+        }
+        if let Some(map) = map {
+            if map.len() != self.size as usize {
+                log(&format!(
+                    "diffusivity map has {} values instead of {}",
+                    map.len(),
+                    self.size
+                ));
+                return;
+            }
+            queue.write_buffer(&self.diffusivity_map, 0, bytemuck::cast_slice(map));
+        }
+        self.uniform_has_changed = true;
+        self.uniform.has_diffusivity_map = map.is_some() as u32;
+        self.diffusivity_max = map.map_or(1.0, Self::diffusivity_max);
+        self.update_timestep();
+    }
+
+    /// Whether the species can be advected, the implicit solvers, the 3D volumes and the meshes
+    /// ignoring the velocity field
+    fn supports_advection(is_volume_or_mesh: bool, solver: Solver) -> bool {
+        !is_volume_or_mesh && solver == Solver::Explicit
+    }
+
+    /// Change the factor of the velocity field
+    pub(crate) fn set_velocity_strength(&mut self, velocity_strength: f32) {
+        if self.velocity.is_none() {
+            log("there is no velocity field to change");
+            return;
+        }
+        self.uniform_has_changed = true;
+        self.uniform.velocity_strength = velocity_strength;
+        self.update_timestep();
+    }
+
+    /// Whether walls can be simulated, the implicit solvers, the 3D volumes and the meshes
+    /// ignoring them
+    fn supports_mask(is_volume_or_mesh: bool, solver: Solver) -> bool {
+        !is_volume_or_mesh && solver == Solver::Explicit
+    }
+
+    pub(crate) fn mask_supported(&self) -> bool {
+        Self::supports_mask(self.uniform.depth > 1 || self.mesh.is_some(), self.solver)
+    }
+
+    /// Replace the mask with one value per cell, zero for the walls, or remove it if `None`
+    pub(crate) fn set_mask(&mut self, queue: &wgpu::Queue, mask: Option<&[u8]>) {
+        if !self.mask_supported() {
+            log("the mask is only supported by the explicit solver on 2D grids");
+            return;
+        }
+        if let Some(mask) = mask {
+            if mask.len() != self.size as usize {
+                log(&format!(
+                    "mask has {} values instead of {}",
+                    mask.len(),
+                    self.size
+                ));
+                return;
+            }
+        }
+        self.mask_values = match mask {
+            Some(mask) => mask.iter().map(|&value| (value != 0) as u32).collect(),
+            None => vec![1; self.size as usize],
+        };
+        queue.write_buffer(&self.mask, 0, bytemuck::cast_slice(&self.mask_values));
+        self.uniform_has_changed = true;
+        self.uniform.has_mask = mask.is_some() as u32;
+    }
+
+    /// Draw walls in a disk of `radius` cells around the cell (`x`, `y`), or erase them if
+    /// `wall` is false, see `mask::paint`
+    pub(crate) fn paint_mask(
+        &mut self,
+        queue: &wgpu::Queue,
+        x: i64,
+        y: i64,
+        radius: u32,
+        wall: bool,
+    ) {
+        if !self.mask_supported() {
+            log("the mask is only supported by the explicit solver on 2D grids");
+            return;
+        }
+        let width = self.uniform.width;
+        let Some(rows) = crate::mask::paint(
+            &mut self.mask_values,
+            (width, self.uniform.height),
+            self.lattice,
+            (x, y),
+            radius,
+            wall,
+        ) else {
+            return;
+        };
+        let cells = (rows.start * width) as usize..(rows.end * width) as usize;
+        queue.write_buffer(
+            &self.mask,
+            (cells.start * std::mem::size_of::<u32>()) as wgpu::BufferAddress,
+            bytemuck::cast_slice(&self.mask_values[cells]),
+        );
+        if self.uniform.has_mask == 0 {
+            self.uniform_has_changed = true;
+            self.uniform.has_mask = 1;
+        }
+    }
+
+    fn diffusivity_max(map: &[f32]) -> f32 {
+        map.iter().copied().fold(0.0, f32::max)
+    }
+
+    pub(crate) fn set_stochastic_noise(&mut self, stochastic_noise: f32) {
+        self.uniform_has_changed = true;
+        self.uniform.stochastic_noise = stochastic_noise;
+    }
+
+    pub(crate) fn set_noise_mode(&mut self, noise_mode: NoiseMode) {
+        self.uniform_has_changed = true;
+        self.uniform.noise_mode = noise_mode as u32;
+        self.noise_mode = noise_mode;
+    }
+
+    /// Change the initial noise, applied at the next reset
+    pub(crate) fn set_noise(&mut self, noise: f32) {
+        self.next_noise = noise;
+    }
+
+    /// Change the seed of the initial and stochastic noise, applied at the next reset
+    pub(crate) fn set_seed(&mut self, seed: u64) {
+        self.next_seed = seed;
     }
 
     pub(crate) fn reset(&mut self) {
         self.to_be_reset = true;
+        self.to_be_rolled_back = false;
+        self.health.reset();
+        self.step_number = 0;
+        self.time = 0.0;
     }
 }
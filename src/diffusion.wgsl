@@ -3,44 +3,612 @@ struct Config {
     height: u32,
     size: u32,
     timestep: f32,
-    diffusion_a: f32,
-    diffusion_b: f32,
     feed: f32,
     kill: f32,
+    steps_per_dispatch: u32,
+    colormap: u32,
+    // grid spacing
+    dx: f32,
+    dy: f32,
+    stochastic_noise: f32,
+    noise_mode: u32,
+    seed: u32,
+    // weights of the neighbours in the diffusion operator, see `tensor.rs`
+    weight_x: f32,
+    weight_y: f32,
+    weight_diagonal: f32,
+    weight_antidiagonal: f32,
+    weight_x2: f32,
+    weight_y2: f32,
+    // largest distance of the neighbours used by the stencil
+    radius: u32,
+    has_diffusivity_map: u32,
+    // largest absolute concentration accepted by `check_field`
+    max_value: f32,
+    // walls of the ADI solver, see `Boundary` in `config.rs`
+    boundary: u32,
+    // 3D volumes, see `VolumeWeights` in `tensor.rs`
+    depth: u32,
+    weight_face: f32,
+    weight_edge: f32,
+    weight_corner: f32,
+    // drawing of 3D volumes, see `View` and `Axis` in `config.rs`
+    view: u32,
+    slice_axis: u32,
+    slice: u32,
+    iso_level: f32,
+    // orbit camera of the isosurface view, in radians
+    camera_yaw: f32,
+    camera_pitch: f32,
+    has_mask: u32,
+    // advection by the velocity field, see `Advection` in `config.rs`, zero without a field
+    advection: u32,
+    velocity_strength: f32,
+    // growing domain, see `growth.rs`: dilution rate and number of columns covered by the domain
+    dilution: f32,
+    active_width: u32,
+    // arrangement of the cells, see `Lattice` in `config.rs`
+    lattice: u32,
+    // whether the field is simulated on the vertices of a mesh, see `mesh.rs`
+    has_mesh: u32,
+    // diffusion rate of each species, zero for the species missing from the system
+    diffusion: vec4<f32>,
+    // concentrations outside the walls of the ADI solver with Dirichlet boundaries, the
+    // background of each species
+    boundary_values: vec4<f32>,
+    // named parameters of the reactions, see `Reactions` in `system.rs`
+    parameters: array<vec4<f32>, 4>,
 };
 
 @group(0) @binding(0) var<uniform> config: Config;
-@group(0) @binding(1) var<storage, read> A: array<f32>;
-@group(0) @binding(2) var<storage, read> B: array<f32>;
-@group(0) @binding(3) var<storage, read_write> A_out: array<f32>;
-@group(0) @binding(4) var<storage, read_write> B_out: array<f32>;
+// `mask` is declared after the storage prelude, see `Diffusion::prelude`
+// Number of steps run since the last reset, as the low and high 32 bits
+@group(1) @binding(0) var<storage, read_write> step_counter: vec2<u32>;
+// Factor of the diffusion coefficients in each cell, used if `has_diffusivity_map` is set
+@group(1) @binding(1) var<storage, read> diffusivity_map: array<f32>;
+// Number of cells found by `check_field` with a non finite and an out of range concentration
+@group(1) @binding(2) var<storage, read_write> divergence: array<atomic<u32>, 2>;
+// Velocity in each cell, multiplied by `velocity_strength`, used if `advection` is set
+@group(1) @binding(3) var<storage, read> velocity: array<vec2<f32>>;
+// Cotangent Laplacian of the mesh, used if `has_mesh` is set: the range of the neighbours of each
+// vertex in this array, then each neighbour with the bits of its weight, see `Laplacian`
+@group(1) @binding(4) var<storage, read> mesh_laplacian: array<vec2<u32>>;
+
+// `Cell`, `load` and `store` are provided by the storage prelude (see `storage.rs`), and `rate` by
+// the reaction system (see `system.rs`)
+
+// Offsets of the cells of a neighbourhood: the centre, then pairs of opposite neighbours along x,
+// y, the diagonal and the antidiagonal, and at a distance of two along x and y
+const NEIGHBOURHOOD: i32 = 13;
+const OFFSETS = array<vec2<i32>, NEIGHBOURHOOD>(
+    vec2<i32>(0, 0),
+    vec2<i32>(1, 0),
+    vec2<i32>(-1, 0),
+    vec2<i32>(0, 1),
+    vec2<i32>(0, -1),
+    vec2<i32>(1, 1),
+    vec2<i32>(-1, -1),
+    vec2<i32>(1, -1),
+    vec2<i32>(-1, 1),
+    vec2<i32>(2, 0),
+    vec2<i32>(-2, 0),
+    vec2<i32>(0, 2),
+    vec2<i32>(0, -2),
+);
+// Size of the neighbourhood of the stencils with a radius of one
+const NEIGHBOURHOOD_NEAR: i32 = 9;
+
+// Lattices, see `Lattice` in `config.rs`
+const SQUARE: u32 = 0;
+const HEXAGONAL: u32 = 1;
+
+// Position of the neighbour at `offset` of the cell at `cell`, with periodic boundaries.
+//
+// On the hexagonal lattice, the offset is in axial coordinates, whose second axis goes down and
+// left by half a cell at each row, while the cells are stored by rows with the odd rows shifted
+// right by half a cell, so that moving to the row below shifts the column by one on odd rows and
+// moving to the row above by minus one on even rows.
+fn neighbour(cell: vec2<i32>, offset: vec2<i32>) -> vec2<i32> {
+    let size = vec2<i32>(i32(config.width), i32(config.height));
+    var p = cell + offset;
+    if config.lattice == HEXAGONAL && offset.y != 0 {
+        p.x += (cell.y & 1) - select(0, 1, offset.y < 0);
+    }
+    return (p + 2 * size) % size;
+}
+
+// Whether the domain has walls, from the mask or from the columns it does not cover yet
+fn has_walls() -> bool {
+    return config.has_mask != 0 || config.active_width < config.width;
+}
+
+// Whether the cell at `p` is open, i.e. not a wall, with periodic boundaries
+fn is_open(p: vec2<i32>) -> bool {
+    let size = vec2<i32>(i32(config.width), i32(config.height));
+    let q = (p + size) % size;
+    return q.x < i32(config.active_width)
+        && (config.has_mask == 0 || mask[q.x + q.y * size.x] != 0u);
+}
+
+// Whether `cell` and its neighbour at `offset` are connected without crossing a wall: the cells
+// along the axes must be open for the diagonal neighbours, and the cell in between for the
+// neighbours at a distance of two. The result is the same seen from the neighbour.
+fn is_connected(cell: vec2<i32>, offset: vec2<i32>) -> bool {
+    return is_open(cell + offset)
+        && is_open(cell + vec2<i32>(offset.x, 0))
+        && is_open(cell + vec2<i32>(0, offset.y))
+        && is_open(cell + offset / 2);
+}
+
+// Conductances between the cell at (x, y) and its neighbourhood, in the order of `OFFSETS`.
+//
+// Each one is the mean diffusivity of the two cells, so that the quantities are conserved in
+// heterogeneous media, or zero between cells separated by a wall, which then has no flux through
+// it.
+fn conductances(x: u32, y: u32) -> array<f32, NEIGHBOURHOOD> {
+    var result: array<f32, NEIGHBOURHOOD>;
+    for (var n = 0; n < NEIGHBOURHOOD; n++) {
+        result[n] = 1.0;
+    }
+    if config.has_diffusivity_map == 0 && !has_walls() {
+        return result;
+    }
+    let size = vec2<i32>(i32(config.width), i32(config.height));
+    let cell = vec2<i32>(i32(x), i32(y));
+    let count = select(NEIGHBOURHOOD_NEAR, NEIGHBOURHOOD, config.radius > 1);
+    for (var n = 1; n < count; n++) {
+        let p = neighbour(cell, OFFSETS[n]);
+        if config.has_diffusivity_map != 0 {
+            let centre = diffusivity_map[x + y * config.width];
+            result[n] = 0.5 * (centre + diffusivity_map[p.x + p.y * size.x]);
+        }
+        // the cells of the hexagonal lattice share an edge with all their neighbours
+        let connected = select(is_connected(cell, OFFSETS[n]), is_open(p), config.lattice == HEXAGONAL);
+        if has_walls() && !connected {
+            result[n] = 0.0;
+        }
+    }
+    return result;
+}
+
+// Whether the cell at (x, y) is a wall, whose concentrations stay constant
+fn is_wall(x: u32, y: u32) -> bool {
+    return has_walls() && !is_open(vec2<i32>(i32(x), i32(y)));
+}
+
+// Discrete diffusion operator, given the concentrations of a neighbourhood and its conductances
+// in the order of `OFFSETS`
+fn laplacian(
+    cells: array<Cell, NEIGHBOURHOOD>,
+    conductances: array<f32, NEIGHBOURHOOD>,
+) -> Cell {
+    let weights = array<f32, NEIGHBOURHOOD>(
+        0.0,
+        config.weight_x,
+        config.weight_x,
+        config.weight_y,
+        config.weight_y,
+        config.weight_diagonal,
+        config.weight_diagonal,
+        config.weight_antidiagonal,
+        config.weight_antidiagonal,
+        config.weight_x2,
+        config.weight_x2,
+        config.weight_y2,
+        config.weight_y2,
+    );
+    let count = select(NEIGHBOURHOOD_NEAR, NEIGHBOURHOOD, config.radius > 1);
+    var sum = Cell(0.0);
+    for (var n = 1; n < count; n++) {
+        sum += weights[n] * conductances[n] * (cells[n] - cells[0]);
+    }
+    return sum;
+}
+
+// Advection schemes, see `Advection` in `config.rs`
+const NO_ADVECTION: u32 = 0;
+const UPWIND: u32 = 1;
+const SEMI_LAGRANGIAN: u32 = 2;
+
+// Advection term -u·∇c of the cell at (x, y), with first order differences on the upwind side,
+// given its neighbourhood in the order of `OFFSETS`. The walls take the concentrations of the cell,
+// so that nothing flows out of them.
+fn upwind(x: u32, y: u32, cells: array<Cell, NEIGHBOURHOOD>) -> Cell {
+    let u = config.velocity_strength * velocity[x + y * config.width];
+    var neighbours = cells;
+    if has_walls() {
+        let cell = vec2<i32>(i32(x), i32(y));
+        for (var n = 1; n < 5; n++) {
+            if !is_open(cell + OFFSETS[n]) {
+                neighbours[n] = cells[0];
+            }
+        }
+    }
+    let gradient_x = select(cells[0] - neighbours[2], neighbours[1] - cells[0], u.x < 0.0);
+    let gradient_y = select(cells[0] - neighbours[4], neighbours[3] - cells[0], u.y < 0.0);
+    return -(u.x / config.dx * gradient_x + u.y / config.dy * gradient_y);
+}
+
+// Concentrations at the point the flow carries to the cell at (x, y) in one timestep, bilinearly
+// interpolated from the four cells around it with periodic boundaries. The walls are left out of
+// the interpolation, and the concentrations `c` of the cell kept if all four are walls.
+fn semi_lagrangian(x: u32, y: u32, c: Cell) -> Cell {
+    let u = config.velocity_strength * velocity[x + y * config.width];
+    let departure = vec2<f32>(f32(x), f32(y)) - config.timestep * u / vec2<f32>(config.dx, config.dy);
+    let corner = floor(departure);
+    let fraction = departure - corner;
+    let size = vec2<i32>(i32(config.width), i32(config.height));
+    var sum = Cell(0.0);
+    var total = 0.0;
+    for (var n = 0; n < 4; n++) {
+        let offset = vec2<i32>(n % 2, n / 2);
+        let p = ((vec2<i32>(corner) + offset) % size + size) % size;
+        let weights = select(1.0 - fraction, fraction, offset == vec2<i32>(1));
+        var weight = weights.x * weights.y;
+        if has_walls() && !is_open(p) {
+            weight = 0.0;
+        }
+        sum += weight * load(u32(p.x), u32(p.y));
+        total += weight;
+    }
+    if total == 0.0 {
+        return c;
+    }
+    return sum / total;
+}
+
+// Diffusion rates of the species
+fn diffusion_rates() -> Cell {
+    return concentrations(config.diffusion);
+}
+
+// Advance the concentrations of a cell by one timestep, diluted by the growth of the domain
+fn update(c: Cell, laplacian: Cell) -> Cell {
+    return c + (rate(c, diffusion_rates() * laplacian) - config.dilution * c) * config.timestep;
+}
+
+// Noise modes, see `NoiseMode` in `config.rs`
+const ADDITIVE: u32 = 0;
+const MULTIPLICATIVE: u32 = 1;
+
+// PCG hash, from "Hash Functions for GPU Rendering" by Jarzynski and Olano
+fn pcg(n: u32) -> u32 {
+    let state = n * 747796405u + 2891336453u;
+    let word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+    return (word >> 22u) ^ word;
+}
+
+// `step` advanced by `n` steps
+fn step_add(step: vec2<u32>, n: u32) -> vec2<u32> {
+    let low = step.x + n;
+    return vec2<u32>(low, step.y + select(0u, 1u, low < step.x));
+}
+
+// Pair of independent standard normal samples from the hashes `h1` and `h2`
+fn box_muller(h1: u32, h2: u32) -> vec2<f32> {
+    // u1 in (0, 1] so that the logarithm is finite
+    let u1 = f32((h1 >> 8u) + 1u) / 16777216.0;
+    let u2 = f32(h2 >> 8u) / 16777216.0;
+    let radius = sqrt(-2.0 * log(u1));
+    let angle = 6.2831855 * u2;
+    return radius * vec2<f32>(cos(angle), sin(angle));
+}
+
+// Independent standard normal samples for each species, depending only on the seed, the cell and
+// the step
+fn gaussian(cell: u32, step: vec2<u32>) -> Cell {
+    let h1 = pcg(cell ^ pcg(step.x ^ pcg(step.y ^ pcg(config.seed))));
+    let h2 = pcg(h1);
+    let h3 = pcg(h2);
+    let h4 = pcg(h3);
+    return concentrations(vec4<f32>(box_muller(h1, h2), box_muller(h3, h4)));
+}
+
+// Stochastic increment of the concentrations `ab` of `cell` at `step`, following the
+// Euler-Maruyama scheme for a noise white in space and time
+fn noise(ab: Cell, cell: u32, step: vec2<u32>) -> Cell {
+    if config.stochastic_noise == 0.0 {
+        return Cell(0.0);
+    }
+    let scale = sqrt(config.timestep / (config.dx * config.dy));
+    let increment = config.stochastic_noise * scale * gaussian(cell, step);
+    if config.noise_mode == MULTIPLICATIVE {
+        return increment * ab;
+    }
+    return increment;
+}
+
+// Advance `step_counter` by one step per workgroup of the dispatch, so that the following
+// dispatches see the right step number
+@compute @workgroup_size(1)
+fn advance_step_counter(
+    @builtin(num_workgroups) num_workgroups: vec3<u32>,
+    @builtin(workgroup_id) workgroup_id: vec3<u32>,
+) {
+    if workgroup_id.x == 0 {
+        step_counter = step_add(step_counter, num_workgroups.x);
+    }
+}
 
 @compute @workgroup_size(64)
 fn diffusion_step(@builtin(global_invocation_id) global_invocation_id: vec3<u32>) {
     let width = config.width;
     let height = config.height;
-    let timestep = config.timestep;
-    let dA = config.diffusion_a;
-    let dB = config.diffusion_b;
-    let f = config.feed;
-    let k = config.kill;
 
     let i = global_invocation_id.x;
 
-    if i > config.size {
+    if i >= config.size {
+        return;
+    }
+
+    let x = i % width;
+    let y = i / width;
+
+    if is_wall(x, y) {
+        store(x, y, load(x, y));
+        return;
+    }
+
+    // periodic boundaries
+    let xm = (x + width - 1) % width;
+    let xp = (x + 1) % width;
+    let ym = (y + height - 1) % height;
+    let yp = (y + 1) % height;
+
+    let ab = load(x, y);
+    var cells: array<Cell, NEIGHBOURHOOD>;
+    cells[0] = ab;
+    if config.lattice == HEXAGONAL {
+        let cell = vec2<i32>(i32(x), i32(y));
+        for (var n = 1; n < NEIGHBOURHOOD_NEAR; n++) {
+            let p = neighbour(cell, OFFSETS[n]);
+            cells[n] = load(u32(p.x), u32(p.y));
+        }
+    } else {
+        cells[1] = load(xp, y);
+        cells[2] = load(xm, y);
+        cells[3] = load(x, yp);
+        cells[4] = load(x, ym);
+        cells[5] = load(xp, yp);
+        cells[6] = load(xm, ym);
+        cells[7] = load(xp, ym);
+        cells[8] = load(xm, yp);
+    }
+    if config.radius > 1 {
+        cells[9] = load((x + 2) % width, y);
+        cells[10] = load((x + width - 2) % width, y);
+        cells[11] = load(x, (y + 2) % height);
+        cells[12] = load(x, (y + height - 2) % height);
+    }
+    let diffused = laplacian(cells, conductances(x, y));
+    switch config.advection {
+        case UPWIND: {
+            let advected = upwind(x, y, cells) * config.timestep;
+            store(x, y, update(ab, diffused) + advected + noise(ab, i, step_counter));
+        }
+        // the semi-Lagrangian advection has its own pass before the step, see `advection_step`
+        case NO_ADVECTION, SEMI_LAGRANGIAN, default: {
+            store(x, y, update(ab, diffused) + noise(ab, i, step_counter));
+        }
+    }
+}
+
+// Carry the concentrations of the whole field along the flow for one timestep, before the
+// reaction and the diffusion of `diffusion_step`, so that these see the advected field
+@compute @workgroup_size(64)
+fn advection_step(@builtin(global_invocation_id) global_invocation_id: vec3<u32>) {
+    let i = global_invocation_id.x;
+    if i >= config.size {
+        return;
+    }
+
+    let x = i % config.width;
+    let y = i / config.width;
+    let c = load(x, y);
+    if is_wall(x, y) {
+        store(x, y, c);
         return;
     }
+    store(x, y, semi_lagrangian(x, y, c));
+}
 
-    let conv_A = -A[i] + // current cell
-        (A[i - 1] + A[i + 1] + A[i - width] + A[i + width]) * 0.2 + // neighbours
-        (A[i - width - 1] + A[i - width + 1] + A[i + width - 1] + A[i + width + 1]) * 0.05; //corners
+// Concentrations of the cell at (x, y, z) of a 3D volume, stored as a field of `height * depth`
+// rows
+fn load_volume(x: u32, y: u32, z: u32) -> Cell {
+    return load(x, y + z * config.height);
+}
 
-    let conv_B = -B[i] + // current cell
-        (B[i - 1] + B[i + 1] + B[i - width] + B[i + width]) * 0.2 + // neighbours
-        (B[i - width - 1] + B[i - width + 1] + B[i + width - 1] + B[i + width + 1]) * 0.05; // corners
+// Advance the concentrations of a 3D volume by one timestep, with the neighbours sharing a face,
+// an edge or a corner with each cell
+@compute @workgroup_size(64)
+fn diffusion_step_volume(@builtin(global_invocation_id) global_invocation_id: vec3<u32>) {
+    let i = global_invocation_id.x;
+    if i >= config.size {
+        return;
+    }
+
+    let size = vec3<i32>(i32(config.width), i32(config.height), i32(config.depth));
+    let x = i % config.width;
+    let y = i / config.width % config.height;
+    let z = i / (config.width * config.height);
+    let cell = vec3<i32>(i32(x), i32(y), i32(z));
+    let weights = array<f32, 3>(config.weight_face, config.weight_edge, config.weight_corner);
+
+    let ab = load_volume(x, y, z);
+    var diffused = Cell(0.0);
+    for (var dz = -1; dz <= 1; dz++) {
+        for (var dy = -1; dy <= 1; dy++) {
+            for (var dx = -1; dx <= 1; dx++) {
+                let distance = abs(dx) + abs(dy) + abs(dz);
+                if distance == 0 || weights[distance - 1] == 0.0 {
+                    continue;
+                }
+                // periodic boundaries
+                let p = vec3<u32>((cell + vec3<i32>(dx, dy, dz) + size) % size);
+                diffused += weights[distance - 1] * (load_volume(p.x, p.y, p.z) - ab);
+            }
+        }
+    }
+    store(x, y + z * config.height, update(ab, diffused) + noise(ab, i, step_counter));
+}
+
+// Advance the concentrations of the vertices of a mesh by one timestep, stored row by row in the
+// field
+@compute @workgroup_size(64)
+fn diffusion_step_mesh(@builtin(global_invocation_id) global_invocation_id: vec3<u32>) {
+    let i = global_invocation_id.x;
+    if i >= config.size {
+        return;
+    }
 
-    let a = A[i];
-    let b = B[i];
-    A_out[i] = a + ((dA * conv_A) - (a * b * b) + (f * (1.0 - a))) * timestep;
-    B_out[i] = b + ((dB * conv_B) + (a * b * b) - (k * b)) * timestep;
-}
\ No newline at end of file
+    let ab = load(i % config.width, i / config.width);
+    var diffused = Cell(0.0);
+    let neighbours = mesh_laplacian[i];
+    for (var n = neighbours.x; n < neighbours.y; n++) {
+        let j = mesh_laplacian[n].x;
+        let weight = bitcast<f32>(mesh_laplacian[n].y);
+        diffused += weight * (load(j % config.width, j / config.width) - ab);
+    }
+    store(i % config.width, i / config.width, update(ab, diffused) + noise(ab, i, step_counter));
+}
+
+// Side of the square tile loaded in workgroup memory by `diffusion_steps`
+const TILE: u32 = 32;
+const TILE_CELLS: u32 = TILE * TILE;
+const WORKGROUP_CELLS: u32 = 64;
+
+// Two copies of the tile, one is read and the other one is written at each step. The tile only
+// fits in workgroup memory with two species.
+var<workgroup> tile: array<array<Cell, TILE_CELLS>, 2>;
+
+// Advance the simulation by `steps_per_dispatch` timesteps.
+//
+// Each workgroup loads a tile of cells in workgroup memory, including a halo of
+// `steps_per_dispatch * radius` cells on each side, and iterates on it without accessing the
+// storage. At every step the `radius` outermost rings of valid cells are lost, so that at the end
+// only the interior of the tile, of side `TILE - 2 * steps_per_dispatch * radius`, is stored.
+@compute @workgroup_size(8, 8)
+fn diffusion_steps(
+    @builtin(workgroup_id) workgroup_id: vec3<u32>,
+    @builtin(local_invocation_index) local_index: u32,
+) {
+    let width = config.width;
+    let height = config.height;
+    let steps = config.steps_per_dispatch;
+    let radius = config.radius;
+    let halo = steps * radius;
+    let interior = TILE - 2 * halo;
+    let origin_x = workgroup_id.x * interior;
+    let origin_y = workgroup_id.y * interior;
+
+    for (var c = local_index; c < TILE_CELLS; c += WORKGROUP_CELLS) {
+        // periodic boundaries
+        let x = (origin_x + c % TILE + width - halo) % width;
+        let y = (origin_y + c / TILE + height - halo) % height;
+        tile[0][c] = load(x, y);
+    }
+    workgroupBarrier();
+
+    for (var step = 0u; step < steps; step++) {
+        let src = step % 2;
+        let dst = 1 - src;
+        let step_number = step_add(step_counter, step);
+        for (var c = local_index; c < TILE_CELLS; c += WORKGROUP_CELLS) {
+            let tx = c % TILE;
+            let ty = c / TILE;
+            if tx < radius || ty < radius || tx >= TILE - radius || ty >= TILE - radius {
+                continue;
+            }
+            // the conductances, the walls and the noise depend on the position in the field, so
+            // that they are the same in the overlapping halos of neighbouring tiles
+            let x = (origin_x + tx + width - halo) % width;
+            let y = (origin_y + ty + height - halo) % height;
+            let ab = tile[src][c];
+            if is_wall(x, y) {
+                tile[dst][c] = ab;
+                continue;
+            }
+            var cells = array<Cell, NEIGHBOURHOOD>(
+                ab,
+                tile[src][c + 1],
+                tile[src][c - 1],
+                tile[src][c + TILE],
+                tile[src][c - TILE],
+                tile[src][c + TILE + 1],
+                tile[src][c - TILE - 1],
+                tile[src][c - TILE + 1],
+                tile[src][c + TILE - 1],
+                Cell(0.0),
+                Cell(0.0),
+                Cell(0.0),
+                Cell(0.0),
+            );
+            if radius > 1 {
+                cells[9] = tile[src][c + 2];
+                cells[10] = tile[src][c - 2];
+                cells[11] = tile[src][c + 2 * TILE];
+                cells[12] = tile[src][c - 2 * TILE];
+            }
+            let diffused = laplacian(cells, conductances(x, y));
+            tile[dst][c] = update(ab, diffused) + noise(ab, x + y * width, step_number);
+        }
+        workgroupBarrier();
+    }
+
+    let result = steps % 2;
+    for (var c = local_index; c < TILE_CELLS; c += WORKGROUP_CELLS) {
+        let tx = c % TILE;
+        let ty = c / TILE;
+        if tx < halo || ty < halo || tx >= TILE - halo || ty >= TILE - halo {
+            continue;
+        }
+        let x = origin_x + tx - halo;
+        let y = origin_y + ty - halo;
+        if x < width && y < height {
+            store(x, y, tile[result][c]);
+        }
+    }
+}
+
+// Whether `value` is NaN or infinite, from its bits since the comparisons may be optimized away
+fn is_non_finite(value: f32) -> bool {
+    return (bitcast<u32>(value) & 0x7f800000u) == 0x7f800000u;
+}
+
+var<workgroup> workgroup_divergence: array<atomic<u32>, 2>;
+
+// Count the cells of the field with a non finite or an out of range concentration, first in
+// workgroup memory then in `divergence`, which must be cleared before the dispatch
+@compute @workgroup_size(64)
+fn check_field(
+    @builtin(global_invocation_id) global_invocation_id: vec3<u32>,
+    @builtin(local_invocation_index) local_index: u32,
+) {
+    let i = global_invocation_id.x;
+    if i < config.size {
+        let c = load(i % config.width, i / config.width);
+        var non_finite = false;
+        var largest = 0.0;
+        for (var s = 0u; s < SPECIES; s++) {
+            non_finite = non_finite || is_non_finite(c[s]);
+            largest = max(largest, abs(c[s]));
+        }
+        if non_finite {
+            atomicAdd(&workgroup_divergence[0], 1u);
+        } else if largest > config.max_value {
+            atomicAdd(&workgroup_divergence[1], 1u);
+        }
+    }
+    workgroupBarrier();
+
+    if local_index == 0 {
+        let non_finite = atomicLoad(&workgroup_divergence[0]);
+        let out_of_range = atomicLoad(&workgroup_divergence[1]);
+        if non_finite > 0 {
+            atomicAdd(&divergence[0], non_finite);
+        }
+        if out_of_range > 0 {
+            atomicAdd(&divergence[1], out_of_range);
+        }
+    }
+}
@@ -0,0 +1,32 @@
+/// Load a per-cell diffusivity map from the bytes of a PNG image, resampled to the size of the
+/// grid.
+///
+/// The luminance of each pixel, between 0 and 1, multiplies the diffusion coefficients.
+pub(crate) fn load(bytes: &[u8], width: u32, height: u32) -> Result<Vec<f32>, String> {
+    let mut decoder = png::Decoder::new(bytes);
+    decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+    let mut reader = decoder.read_info().map_err(|e| e.to_string())?;
+    let mut pixels = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut pixels).map_err(|e| e.to_string())?;
+
+    let channels = info.color_type.samples();
+    let luminance = |pixel: &[u8]| match pixel {
+        [r, g, b, ..] if channels >= 3 => {
+            (0.2126 * *r as f32 + 0.7152 * *g as f32 + 0.0722 * *b as f32) / 255.0
+        }
+        [l, ..] => *l as f32 / 255.0,
+        [] => 0.0,
+    };
+
+    // nearest neighbour resampling
+    let mut map = Vec::with_capacity((width * height) as usize);
+    for y in 0..height {
+        let row = (y as u64 * info.height as u64 / height as u64) as usize;
+        for x in 0..width {
+            let column = (x as u64 * info.width as u64 / width as u64) as usize;
+            let offset = row * info.line_size + column * channels;
+            map.push(luminance(&pixels[offset..offset + channels]));
+        }
+    }
+    Ok(map)
+}
@@ -1,12 +1,58 @@
+use crate::config::{Axis, Colormap, NoiseMode, TimestepMode, View};
+
 #[derive(Debug)]
 pub enum Event {
-    SetDiffusionA(f32),
-    SetDiffusionB(f32),
+    /// Change the diffusion rate of a species, given by its index in `System::NAMES`
+    SetDiffusion(usize, f32),
     SetFeed(f32),
     SetKill(f32),
+    /// Change a named parameter of the reactions given as expressions
+    SetParameter(String, f32),
     SetStepsPerFrame(u32),
     SetTimestep(f32),
+    SetTimestepMode(TimestepMode),
+    SetColormap(Colormap),
+    SetDiffusionX(f32),
+    SetDiffusionY(f32),
+    SetDiffusionAngle(f32),
+    /// Replace the diffusivity map with one value per cell, or remove it
+    SetDiffusivityMap(Option<Vec<f32>>),
+    /// Replace the mask with one value per cell, zero for walls, or remove it
+    SetMask(Option<Vec<u8>>),
+    /// Turn the cells within `radius` of a cell into walls, or open them if `wall` is false
+    PaintMask {
+        x: i64,
+        y: i64,
+        radius: u32,
+        wall: bool,
+    },
+    /// Change the factor of the velocity field advecting the species
+    SetVelocityStrength(f32),
+    SetNoise(f32),
+    SetSeed(u64),
+    SetStochasticNoise(f32),
+    SetNoiseMode(NoiseMode),
     Reset,
     Start,
     Pause,
+    Step(u32),
+    SetMaxSteps(Option<u64>),
+    SetMaxTime(Option<f64>),
+    /// Register a callback called with the `Stats` each time the target is reached
+    #[cfg(target_arch = "wasm32")]
+    OnTargetReached(js_sys::Function),
+    SetCheckInterval(u64),
+    SetMaxValue(f32),
+    /// Register a callback called with the `Divergence` each time the simulation diverges
+    #[cfg(target_arch = "wasm32")]
+    OnDivergence(js_sys::Function),
+    SetView(View),
+    SetSliceAxis(Axis),
+    SetSlice(u32),
+    SetIsoLevel(f32),
+    /// Orient the camera of the isosurface view, with the yaw and pitch in radians
+    SetCamera(f32, f32),
+    Screenshot,
+    ToggleHud,
+    ToggleHelp,
 }
@@ -0,0 +1,463 @@
+/// Arithmetic expression of a reaction term, with its variables resolved
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Expression {
+    Number(f32),
+    /// Concentration of the species with this index
+    Species(usize),
+    /// Named parameter with this index
+    Parameter(usize),
+    Negate(Box<Expression>),
+    Binary(Box<Expression>, Operator, Box<Expression>),
+    Call(Function, Vec<Expression>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Operator {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Power,
+}
+
+/// Functions available in the expressions, with the same name and meaning as in WGSL
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Function {
+    Exp,
+    Log,
+    Sqrt,
+    Abs,
+    Sin,
+    Cos,
+    Tanh,
+    Min,
+    Max,
+    Pow,
+}
+
+impl Function {
+    const ALL: [Function; 10] = [
+        Function::Exp,
+        Function::Log,
+        Function::Sqrt,
+        Function::Abs,
+        Function::Sin,
+        Function::Cos,
+        Function::Tanh,
+        Function::Min,
+        Function::Max,
+        Function::Pow,
+    ];
+
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Function::Exp => "exp",
+            Function::Log => "log",
+            Function::Sqrt => "sqrt",
+            Function::Abs => "abs",
+            Function::Sin => "sin",
+            Function::Cos => "cos",
+            Function::Tanh => "tanh",
+            Function::Min => "min",
+            Function::Max => "max",
+            Function::Pow => "pow",
+        }
+    }
+
+    pub(crate) fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|function| function.name() == name)
+    }
+
+    fn arity(self) -> usize {
+        match self {
+            Function::Min | Function::Max | Function::Pow => 2,
+            _ => 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f32),
+    Identifier(String),
+    Operator(char),
+    LeftParenthesis,
+    RightParenthesis,
+    Comma,
+}
+
+/// Names that the variables of an expression can refer to
+pub(crate) struct Variables<'a> {
+    pub(crate) species: &'a [String],
+    pub(crate) parameters: &'a [String],
+}
+
+impl Expression {
+    /// Parse `source`, in which the variables are the names of the species and of the
+    /// parameters in `variables`
+    pub(crate) fn parse(source: &str, variables: &Variables) -> Result<Self, String> {
+        let tokens = tokenize(source)?;
+        let mut parser = Parser {
+            tokens: &tokens,
+            position: 0,
+            variables,
+        };
+        let expression = parser.sum()?;
+        match parser.peek() {
+            None => Ok(expression),
+            Some(token) => Err(format!("unexpected {}", describe(token))),
+        }
+    }
+
+    /// Translate the expression to WGSL, with the concentrations in `c` and the parameters in
+    /// `config.parameters`
+    pub(crate) fn to_wgsl(&self) -> String {
+        match self {
+            Expression::Number(value) => format!("{:?}", value),
+            Expression::Species(index) => format!("c.{}", ["x", "y", "z", "w"][*index]),
+            Expression::Parameter(index) => {
+                format!("config.parameters[{}][{}]", index / 4, index % 4)
+            }
+            Expression::Negate(operand) => format!("(-{})", operand.to_wgsl()),
+            Expression::Binary(base, Operator::Power, exponent) => match **exponent {
+                // small integer powers are expanded, `pow` being undefined for negative bases
+                Expression::Number(n) if n.fract() == 0.0 && (1.0..=4.0).contains(&n) => {
+                    format!("({})", vec![base.to_wgsl(); n as usize].join(" * "))
+                }
+                _ => format!("pow({}, {})", base.to_wgsl(), exponent.to_wgsl()),
+            },
+            Expression::Binary(left, operator, right) => {
+                let operator = match operator {
+                    Operator::Add => "+",
+                    Operator::Subtract => "-",
+                    Operator::Multiply => "*",
+                    Operator::Divide => "/",
+                    Operator::Power => unreachable!(),
+                };
+                format!("({} {} {})", left.to_wgsl(), operator, right.to_wgsl())
+            }
+            Expression::Call(function, arguments) => {
+                let arguments: Vec<_> = arguments.iter().map(Expression::to_wgsl).collect();
+                format!("{}({})", function.name(), arguments.join(", "))
+            }
+        }
+    }
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = source.char_indices().peekable();
+    while let Some(&(start, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c.is_ascii_digit() || c == '.' {
+            let mut end = start;
+            let mut previous = c;
+            while let Some(&(i, c)) = chars.peek() {
+                // the sign of an exponent belongs to the number
+                let exponent_sign = (c == '+' || c == '-') && (previous == 'e' || previous == 'E');
+                if !(c.is_ascii_digit() || c == '.' || c == 'e' || c == 'E' || exponent_sign) {
+                    break;
+                }
+                end = i + c.len_utf8();
+                previous = c;
+                chars.next();
+            }
+            let number = &source[start..end];
+            let value = number
+                .parse()
+                .ok()
+                .filter(|value: &f32| value.is_finite())
+                .ok_or_else(|| format!("invalid number \"{}\"", number))?;
+            tokens.push(Token::Number(value));
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let mut end = start;
+            while let Some(&(i, c)) = chars.peek() {
+                if !(c.is_ascii_alphanumeric() || c == '_') {
+                    break;
+                }
+                end = i + c.len_utf8();
+                chars.next();
+            }
+            tokens.push(Token::Identifier(source[start..end].to_string()));
+        } else {
+            tokens.push(match c {
+                '+' | '-' | '*' | '/' | '^' => Token::Operator(c),
+                '(' => Token::LeftParenthesis,
+                ')' => Token::RightParenthesis,
+                ',' => Token::Comma,
+                _ => return Err(format!("unexpected character '{}'", c)),
+            });
+            chars.next();
+        }
+    }
+    Ok(tokens)
+}
+
+fn describe(token: &Token) -> String {
+    match token {
+        Token::Number(value) => format!("number {}", value),
+        Token::Identifier(name) => format!("\"{}\"", name),
+        Token::Operator(c) => format!("'{}'", c),
+        Token::LeftParenthesis => "'('".to_string(),
+        Token::RightParenthesis => "')'".to_string(),
+        Token::Comma => "','".to_string(),
+    }
+}
+
+/// Recursive descent parser, from the lowest precedence to the highest one
+struct Parser<'a> {
+    tokens: &'a [Token],
+    position: usize,
+    variables: &'a Variables<'a>,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Result<&Token, String> {
+        let token = self
+            .tokens
+            .get(self.position)
+            .ok_or("unexpected end of the expression")?;
+        self.position += 1;
+        Ok(token)
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<(), String> {
+        let token = self.next()?;
+        if *token != expected {
+            return Err(format!(
+                "expected {}, found {}",
+                describe(&expected),
+                describe(token)
+            ));
+        }
+        Ok(())
+    }
+
+    /// Terms separated by `+` and `-`
+    fn sum(&mut self) -> Result<Expression, String> {
+        let mut expression = self.product()?;
+        while let Some(Token::Operator(c @ ('+' | '-'))) = self.peek() {
+            let operator = if *c == '+' {
+                Operator::Add
+            } else {
+                Operator::Subtract
+            };
+            self.position += 1;
+            let right = self.product()?;
+            expression = Expression::Binary(Box::new(expression), operator, Box::new(right));
+        }
+        Ok(expression)
+    }
+
+    /// Factors separated by `*` and `/`
+    fn product(&mut self) -> Result<Expression, String> {
+        let mut expression = self.unary()?;
+        while let Some(Token::Operator(c @ ('*' | '/'))) = self.peek() {
+            let operator = if *c == '*' {
+                Operator::Multiply
+            } else {
+                Operator::Divide
+            };
+            self.position += 1;
+            let right = self.unary()?;
+            expression = Expression::Binary(Box::new(expression), operator, Box::new(right));
+        }
+        Ok(expression)
+    }
+
+    /// Negation, binding less tightly than `^` so that `-a^2` is `-(a^2)`
+    fn unary(&mut self) -> Result<Expression, String> {
+        if let Some(Token::Operator('-')) = self.peek() {
+            self.position += 1;
+            return Ok(Expression::Negate(Box::new(self.unary()?)));
+        }
+        self.power()
+    }
+
+    /// Right associative `^`
+    fn power(&mut self) -> Result<Expression, String> {
+        let base = self.atom()?;
+        if let Some(Token::Operator('^')) = self.peek() {
+            self.position += 1;
+            let exponent = self.unary()?;
+            return Ok(Expression::Binary(
+                Box::new(base),
+                Operator::Power,
+                Box::new(exponent),
+            ));
+        }
+        Ok(base)
+    }
+
+    fn atom(&mut self) -> Result<Expression, String> {
+        match self.next()?.clone() {
+            Token::Number(value) => Ok(Expression::Number(value)),
+            Token::LeftParenthesis => {
+                let expression = self.sum()?;
+                self.expect(Token::RightParenthesis)?;
+                Ok(expression)
+            }
+            Token::Identifier(name) if self.peek() == Some(&Token::LeftParenthesis) => {
+                let function = Function::from_name(&name)
+                    .ok_or_else(|| format!("unknown function \"{}\"", name))?;
+                self.position += 1;
+                let mut arguments = vec![self.sum()?];
+                while self.peek() == Some(&Token::Comma) {
+                    self.position += 1;
+                    arguments.push(self.sum()?);
+                }
+                self.expect(Token::RightParenthesis)?;
+                if arguments.len() != function.arity() {
+                    return Err(format!(
+                        "\"{}\" takes {} argument(s), found {}",
+                        name,
+                        function.arity(),
+                        arguments.len()
+                    ));
+                }
+                Ok(Expression::Call(function, arguments))
+            }
+            Token::Identifier(name) => {
+                if let Some(index) = self.variables.species.iter().position(|s| *s == name) {
+                    Ok(Expression::Species(index))
+                } else if let Some(index) =
+                    self.variables.parameters.iter().position(|p| *p == name)
+                {
+                    Ok(Expression::Parameter(index))
+                } else {
+                    Err(format!("unknown variable \"{}\"", name))
+                }
+            }
+            token => Err(format!("unexpected {}", describe(&token))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{tokenize, Expression, Function, Operator, Token, Variables};
+
+    fn parse(source: &str) -> Result<Expression, String> {
+        let species = ["a", "b"].map(String::from);
+        let parameters: Vec<_> = (0..6).map(|i| format!("p{}", i)).collect();
+        Expression::parse(
+            source,
+            &Variables {
+                species: &species,
+                parameters: &parameters,
+            },
+        )
+    }
+
+    fn wgsl(source: &str) -> String {
+        parse(source).unwrap().to_wgsl()
+    }
+
+    #[test]
+    fn tokenize_numbers_and_operators() {
+        assert_eq!(
+            tokenize("2.5e-3*a_1 -(b)").unwrap(),
+            vec![
+                Token::Number(2.5e-3),
+                Token::Operator('*'),
+                Token::Identifier("a_1".to_string()),
+                Token::Operator('-'),
+                Token::LeftParenthesis,
+                Token::Identifier("b".to_string()),
+                Token::RightParenthesis,
+            ]
+        );
+        assert_eq!(tokenize("1e5").unwrap(), vec![Token::Number(1e5)]);
+        assert_eq!(
+            tokenize("a # b"),
+            Err("unexpected character '#'".to_string())
+        );
+        assert_eq!(
+            tokenize("1.2.3"),
+            Err("invalid number \"1.2.3\"".to_string())
+        );
+        assert_eq!(tokenize("1e99"), Err("invalid number \"1e99\"".to_string()));
+    }
+
+    #[test]
+    fn precedence_and_associativity() {
+        assert_eq!(wgsl("a + b * 2.0"), "(c.x + (c.y * 2.0))");
+        assert_eq!(wgsl("(a + b) * 2"), "((c.x + c.y) * 2.0)");
+        assert_eq!(wgsl("a - b - 1"), "((c.x - c.y) - 1.0)");
+        assert_eq!(wgsl("a / b / 2"), "((c.x / c.y) / 2.0)");
+        // `^` is right associative
+        assert_eq!(wgsl("a ^ b ^ 0.5"), "pow(c.x, pow(c.y, 0.5))");
+    }
+
+    #[test]
+    fn negation_binds_less_tightly_than_power() {
+        assert_eq!(
+            parse("-a^2").unwrap(),
+            Expression::Negate(Box::new(Expression::Binary(
+                Box::new(Expression::Species(0)),
+                Operator::Power,
+                Box::new(Expression::Number(2.0)),
+            )))
+        );
+        assert_eq!(wgsl("-a^2"), "(-(c.x * c.x))");
+        assert_eq!(wgsl("(-a)^2"), "((-c.x) * (-c.x))");
+        assert_eq!(wgsl("a^-1"), "pow(c.x, (-1.0))");
+        assert_eq!(wgsl("--a"), "(-(-c.x))");
+    }
+
+    #[test]
+    fn integer_powers_are_expanded() {
+        assert_eq!(wgsl("a^1"), "(c.x)");
+        assert_eq!(wgsl("b^3"), "(c.y * c.y * c.y)");
+        assert_eq!(wgsl("(a + b)^2"), "((c.x + c.y) * (c.x + c.y))");
+        assert_eq!(wgsl("a^4"), "(c.x * c.x * c.x * c.x)");
+        assert_eq!(wgsl("a^5"), "pow(c.x, 5.0)");
+        assert_eq!(wgsl("a^2.5"), "pow(c.x, 2.5)");
+        assert_eq!(wgsl("a^0"), "pow(c.x, 0.0)");
+    }
+
+    #[test]
+    fn function_calls() {
+        assert_eq!(
+            parse("max(a, 0)").unwrap(),
+            Expression::Call(
+                Function::Max,
+                vec![Expression::Species(0), Expression::Number(0.0)]
+            )
+        );
+        assert_eq!(wgsl("exp(-a) * tanh(b)"), "(exp((-c.x)) * tanh(c.y))");
+        assert_eq!(wgsl("pow(a, b + 1)"), "pow(c.x, (c.y + 1.0))");
+        assert_eq!(wgsl("sqrt(abs(a))"), "sqrt(abs(c.x))");
+    }
+
+    #[test]
+    fn parameters_are_indexed_by_vector() {
+        assert_eq!(parse("p5").unwrap(), Expression::Parameter(5));
+        assert_eq!(wgsl("p0"), "config.parameters[0][0]");
+        assert_eq!(wgsl("p3"), "config.parameters[0][3]");
+        assert_eq!(wgsl("p4"), "config.parameters[1][0]");
+        assert_eq!(wgsl("p5 * a"), "(config.parameters[1][1] * c.x)");
+    }
+
+    #[test]
+    fn errors() {
+        let error = |source| parse(source).unwrap_err();
+        assert_eq!(error("a +"), "unexpected end of the expression");
+        assert_eq!(error("a b"), "unexpected \"b\"");
+        assert_eq!(error("(a + b"), "unexpected end of the expression");
+        assert_eq!(error("(a + b,"), "expected ')', found ','");
+        assert_eq!(error("a)"), "unexpected ')'");
+        assert_eq!(error("* a"), "unexpected '*'");
+        assert_eq!(error("c"), "unknown variable \"c\"");
+        assert_eq!(error("foo(a)"), "unknown function \"foo\"");
+        assert_eq!(error("min(a)"), "\"min\" takes 2 argument(s), found 1");
+        assert_eq!(error("exp(a, b)"), "\"exp\" takes 1 argument(s), found 2");
+    }
+}
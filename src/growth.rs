@@ -0,0 +1,55 @@
+use crate::config::{Config, Growth};
+
+/// Growth of the domain along x over time, see `Growth`
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct DomainGrowth {
+    pub(crate) mode: Growth,
+    pub(crate) rate: f32,
+    /// Final length of the domain relative to the initial one
+    pub(crate) factor: f32,
+}
+
+impl DomainGrowth {
+    pub(crate) fn new(config: &Config) -> Option<Self> {
+        (config.growth != Growth::None && config.growth_rate > 0.0 && config.growth_factor > 1.0)
+            .then_some(Self {
+                mode: config.growth,
+                rate: config.growth_rate,
+                factor: config.growth_factor,
+            })
+    }
+
+    /// Length of the domain at `time`, relative to the initial one. Uniform growth is
+    /// exponential, each part of the domain growing at `rate`, and apical growth is linear, the
+    /// tip advancing by `rate` times the initial length per unit of time.
+    pub(crate) fn length(&self, time: f64) -> f32 {
+        let length = match self.mode {
+            Growth::None => 1.0,
+            Growth::Uniform => (self.rate as f64 * time).exp(),
+            Growth::Apical => 1.0 + self.rate as f64 * time,
+        };
+        (length as f32).min(self.factor)
+    }
+
+    /// Rate at which the growth dilutes the concentrations at `time`, the relative rate of
+    /// growth of uniformly growing domains
+    pub(crate) fn dilution(&self, time: f64) -> f32 {
+        if self.mode == Growth::Uniform && self.length(time) < self.factor {
+            self.rate
+        } else {
+            0.0
+        }
+    }
+
+    /// Number of columns of a grid of `width` columns covered by the domain at `time`. Apically
+    /// growing domains never cover the last column, which stays a wall between their two ends
+    /// across the periodic boundary of the grid.
+    pub(crate) fn columns(&self, width: u32, time: f64) -> u32 {
+        if self.mode != Growth::Apical {
+            return width;
+        }
+        let last = width.saturating_sub(1).max(1);
+        let columns = (last as f32 * self.length(time) / self.factor).round() as u32;
+        columns.clamp(1, last)
+    }
+}
@@ -0,0 +1,118 @@
+use std::path::PathBuf;
+
+use clap::Args;
+
+use crate::config::Config;
+use crate::device;
+use crate::diffusion::Diffusion;
+use crate::renderer::FieldRenderer;
+use crate::shader_dir::ShaderDir;
+use crate::storage::Storage;
+use crate::system::System;
+use crate::target::Target;
+
+/// Run the simulation without opening a window until `--max-steps` or `--max-time` is reached,
+/// then save the field to a PNG image
+#[derive(Debug, Clone, Args)]
+pub struct Headless {
+    /// Path of the PNG image saved at the end of the run
+    #[arg(long, default_value = "output.png")]
+    pub output: PathBuf,
+}
+
+impl Headless {
+    /// Format of the saved image, matching the colors of the window surface
+    const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+    pub fn run(&self, config: &Config) -> Result<(), String> {
+        pollster::block_on(self.run_async(config))
+    }
+
+    async fn run_async(&self, config: &Config) -> Result<(), String> {
+        let target = Target::new(config);
+        if !target.is_set() {
+            return Err("a target is required, set --max-steps or --max-time".to_string());
+        }
+
+        let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor::default());
+        let adapter = instance
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::HighPerformance,
+                ..Default::default()
+            })
+            .await
+            .ok_or("no suitable GPU adapter found")?;
+        let (device, queue) = device::request_device(&adapter, None, wgpu::Features::empty())
+            .await
+            .map_err(|e| e.to_string())?;
+
+        let config = Config {
+            storage: Storage::supported_layout(
+                config.storage,
+                System::species_count(config),
+                &adapter,
+            ),
+            ..config.clone()
+        };
+        let mut diffusion = Diffusion::new(&config, &device, &queue);
+        let (width, height) = diffusion.image_size();
+        let mut field_renderer =
+            FieldRenderer::new(&device, &diffusion, Self::FORMAT, width, height);
+        if let Some(path) = &config.shader_dir {
+            let shaders = ShaderDir::new(path.clone()).changed();
+            if let Some(shader) = shaders.diffusion {
+                diffusion
+                    .reload_shader(&device, &shader)
+                    .map_err(|e| format!("error in diffusion.wgsl: {}", e))?;
+            }
+            if let Some(shader) = shaders.render {
+                field_renderer
+                    .reload_shader(&device, &diffusion, &shader)
+                    .map_err(|e| format!("error in shader.wgsl: {}", e))?;
+            }
+        }
+
+        while !target.is_reached(&diffusion) {
+            if target.remaining_steps(&diffusion).is_none() {
+                return Err("the target can never be reached with a zero timestep".to_string());
+            }
+            let steps = target.clamp_steps(&diffusion, config.steps_per_frame.max(1));
+            let mut encoder = device.create_command_encoder(&Default::default());
+            diffusion.step_many(&queue, &mut encoder, steps);
+            queue.submit(std::iter::once(encoder.finish()));
+            // do not let the submissions pile up
+            device.poll(wgpu::Maintain::Wait);
+            Self::check_health(&mut diffusion)?;
+        }
+        // wait for the last check, started after the last submission
+        Self::check_health(&mut diffusion)?;
+        device.poll(wgpu::Maintain::Wait);
+        Self::check_health(&mut diffusion)?;
+        eprintln!(
+            "target reached at step {}, time {}",
+            diffusion.step_number(),
+            diffusion.time()
+        );
+
+        crate::screenshot::save(
+            &self.output,
+            &device,
+            &queue,
+            Self::FORMAT,
+            width,
+            height,
+            &diffusion.metadata(),
+            |encoder, view| field_renderer.render(encoder, view, &diffusion),
+        )?;
+        eprintln!("field saved to {}", self.output.display());
+        Ok(())
+    }
+
+    /// Fail if the simulation diverged, with the parameters that caused it
+    fn check_health(diffusion: &mut Diffusion) -> Result<(), String> {
+        match diffusion.check_health() {
+            Some(divergence) => Err(divergence.message(&diffusion.metadata())),
+            None => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,223 @@
+use std::sync::{Arc, Mutex};
+
+use wasm_bindgen::prelude::*;
+
+use crate::config::Config;
+use crate::log::log;
+use crate::storage::Storage;
+
+/// Cells found invalid by a check of the field, reported when the simulation diverges
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Divergence {
+    /// Step at which the field was checked
+    pub step_number: u64,
+    /// Simulated time at which the field was checked
+    pub time: f64,
+    /// Number of cells with a NaN or infinite concentration
+    pub non_finite: u32,
+    /// Number of cells with a concentration larger than `max_value` in absolute value
+    pub out_of_range: u32,
+    /// Whether the field was restored to the last state that passed a check
+    pub rolled_back: bool,
+}
+
+impl Divergence {
+    /// Description of the divergence, followed by the parameters of the simulation
+    pub(crate) fn message(&self, parameters: &[(&str, String)]) -> String {
+        let parameters: Vec<_> = parameters
+            .iter()
+            .map(|(key, value)| format!("{}: {}", key, value))
+            .collect();
+        format!(
+            "simulation diverged at step {}, time {}: {} non finite and {} out of range cells, \
+             with {}",
+            self.step_number,
+            self.time,
+            self.non_finite,
+            self.out_of_range,
+            parameters.join(", ")
+        )
+    }
+}
+
+/// Check copied to the readback buffer, whose result is not known yet
+struct PendingCheck {
+    step_number: u64,
+    time: f64,
+    /// Copy of the snapshot holding the checked field, if rollback is enabled
+    snapshot_copy: bool,
+    /// Whether `map_async` has been called on the readback buffer
+    mapping: bool,
+    /// Set when the field is reset before the result is known, which is then ignored
+    stale: bool,
+}
+
+/// Field that passed a check, kept in one of the copies of the snapshot storage
+#[derive(Clone, Copy)]
+pub(crate) struct GoodState {
+    pub(crate) copy: bool,
+    pub(crate) step_number: u64,
+    pub(crate) time: f64,
+}
+
+/// Periodic check of the field for NaN, infinite or out of range values.
+///
+/// Every `check_interval` steps a reduction kernel counts the invalid cells on the GPU, and the
+/// counts are read back asynchronously so that the simulation is never stalled. When rollback is
+/// enabled, the checked field is also copied to a snapshot, which becomes the last good state
+/// once the check passes.
+pub(crate) struct Health {
+    check_interval: u64,
+    /// Invalid cells counted by `check_field`, see `diffusion.wgsl`
+    counters: wgpu::Buffer,
+    readback: wgpu::Buffer,
+    pending: Option<PendingCheck>,
+    result: Arc<Mutex<Option<Result<(), wgpu::BufferAsyncError>>>>,
+    /// Step number of the last recorded check
+    last_check: u64,
+    snapshot: Option<Storage>,
+    good_state: Option<GoodState>,
+}
+
+impl Health {
+    const COUNTERS_SIZE: wgpu::BufferAddress = std::mem::size_of::<[u32; 2]>() as _;
+
+    pub(crate) fn new(config: &Config, device: &wgpu::Device, snapshot: Option<Storage>) -> Self {
+        Self {
+            check_interval: config.check_interval,
+            counters: device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("Divergence counters"),
+                size: Self::COUNTERS_SIZE,
+                usage: wgpu::BufferUsages::STORAGE
+                    | wgpu::BufferUsages::COPY_SRC
+                    | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }),
+            readback: device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("Divergence readback"),
+                size: Self::COUNTERS_SIZE,
+                usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }),
+            pending: None,
+            result: Default::default(),
+            last_check: 0,
+            snapshot,
+            good_state: None,
+        }
+    }
+
+    pub(crate) fn counters(&self) -> &wgpu::Buffer {
+        &self.counters
+    }
+
+    pub(crate) fn snapshot(&self) -> Option<&Storage> {
+        self.snapshot.as_ref()
+    }
+
+    pub(crate) fn good_state(&self) -> Option<GoodState> {
+        self.good_state
+    }
+
+    pub(crate) fn set_check_interval(&mut self, check_interval: u64) {
+        self.check_interval = check_interval;
+    }
+
+    /// Whether a check should be recorded at `step_number`
+    pub(crate) fn is_due(&self, step_number: u64) -> bool {
+        self.check_interval > 0
+            && self.pending.is_none()
+            && step_number >= self.last_check + self.check_interval
+    }
+
+    /// Copy of the snapshot that the field checked next should be copied to
+    pub(crate) fn snapshot_copy(&self) -> bool {
+        self.good_state.is_some_and(|state| !state.copy)
+    }
+
+    /// Record the readback of the counters, after the check kernel has been dispatched
+    pub(crate) fn record(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        step_number: u64,
+        time: f64,
+    ) {
+        encoder.copy_buffer_to_buffer(&self.counters, 0, &self.readback, 0, Self::COUNTERS_SIZE);
+        self.last_check = step_number;
+        self.pending = Some(PendingCheck {
+            step_number,
+            time,
+            snapshot_copy: self.snapshot_copy(),
+            mapping: false,
+            stale: false,
+        });
+    }
+
+    /// Advance the pending check, which must have been submitted. Return the divergence if the
+    /// check failed.
+    pub(crate) fn poll(&mut self) -> Option<Divergence> {
+        let pending = self.pending.as_mut()?;
+        if !pending.mapping {
+            pending.mapping = true;
+            let result = self.result.clone();
+            self.readback
+                .slice(..)
+                .map_async(wgpu::MapMode::Read, move |r| {
+                    *result.lock().unwrap() = Some(r);
+                });
+            return None;
+        }
+
+        let result = self.result.lock().unwrap().take()?;
+        let pending = self.pending.take()?;
+        if let Err(e) = result {
+            log(&format!("error while reading the divergence check: {}", e));
+            return None;
+        }
+        let counters: [u32; 2] = {
+            let view = self.readback.slice(..).get_mapped_range();
+            *bytemuck::from_bytes(&view)
+        };
+        self.readback.unmap();
+
+        if pending.stale {
+            return None;
+        }
+        let [non_finite, out_of_range] = counters;
+        if non_finite == 0 && out_of_range == 0 {
+            if self.snapshot.is_some() {
+                self.good_state = Some(GoodState {
+                    copy: pending.snapshot_copy,
+                    step_number: pending.step_number,
+                    time: pending.time,
+                });
+            }
+            return None;
+        }
+        Some(Divergence {
+            step_number: pending.step_number,
+            time: pending.time,
+            non_finite,
+            out_of_range,
+            rolled_back: false,
+        })
+    }
+
+    /// Forget the checks of the previous field, after a reset
+    pub(crate) fn reset(&mut self) {
+        self.last_check = 0;
+        self.good_state = None;
+        if let Some(pending) = &mut self.pending {
+            pending.stale = true;
+        }
+    }
+
+    /// Forget the checks after `step_number`, after a rollback to that step
+    pub(crate) fn rewind(&mut self, step_number: u64) {
+        self.last_check = step_number;
+        if let Some(pending) = &mut self.pending {
+            pending.stale = true;
+        }
+    }
+}
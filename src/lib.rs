@@ -1,14 +1,50 @@
 #[cfg(target_arch = "wasm32")]
 use wasm_bindgen::prelude::wasm_bindgen;
 
+mod adi;
 mod app;
+#[cfg(not(target_arch = "wasm32"))]
+mod bench;
 mod config;
+mod device;
 mod diffusion;
+mod diffusivity;
 mod event;
+mod expression;
+mod font;
+mod growth;
+#[cfg(not(target_arch = "wasm32"))]
+mod headless;
+mod health;
 mod log;
+mod mask;
+mod mesh;
+mod overlay;
+mod random;
+mod renderer;
+#[cfg(not(target_arch = "wasm32"))]
+mod screenshot;
+#[cfg(not(target_arch = "wasm32"))]
+mod shader_dir;
+mod spectral;
+mod stats;
+mod storage;
+mod system;
+mod target;
+mod tensor;
+mod velocity;
 
 pub use crate::app::App;
-pub use crate::config::Config;
+#[cfg(not(target_arch = "wasm32"))]
+pub use crate::bench::{Bench, OutputFormat};
+pub use crate::config::{
+    Advection, Axis, Boundary, Colormap, Config, Growth, Lattice, Model, NoiseMode, Solver,
+    Stencil, StorageLayout, TimestepMode, VelocityField, View, VolumeStencil,
+};
+#[cfg(not(target_arch = "wasm32"))]
+pub use crate::headless::Headless;
+pub use crate::health::Divergence;
+pub use crate::stats::Stats;
 
 #[cfg_attr(target_arch = "wasm32", wasm_bindgen(start))]
 pub async fn web_init() {
@@ -1,9 +1,35 @@
-use clap::Parser;
-use reaction_diffusion::{App, Config};
+use clap::{Parser, Subcommand};
+use reaction_diffusion::{App, Bench, Config, Headless};
+
+#[derive(Debug, Parser)]
+#[command(version, about, long_about = None)]
+struct Cli {
+    #[command(flatten)]
+    config: Config,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    Bench(Bench),
+    Headless(Headless),
+}
 
 fn main() {
     env_logger::init();
-    let config = Config::parse();
-    let app = App::new(config);
-    pollster::block_on(app.run());
+    let cli = Cli::parse();
+    match cli.command {
+        Some(Command::Bench(bench)) => bench.run(&cli.config),
+        Some(Command::Headless(headless)) => {
+            if let Err(e) = headless.run(&cli.config) {
+                eprintln!("error: {}", e);
+                std::process::exit(1);
+            }
+        }
+        None => {
+            let app = App::new(cli.config);
+            pollster::block_on(app.run());
+        }
+    }
 }
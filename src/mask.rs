@@ -0,0 +1,90 @@
+use crate::config::Lattice;
+
+/// Load a mask of the domain from the bytes of a PNG image, resampled to the size of the grid.
+///
+/// The cells whose luminance is below one half are walls, with the value 0, and the other ones
+/// are open, with the value 1.
+pub(crate) fn load(bytes: &[u8], width: u32, height: u32) -> Result<Vec<u32>, String> {
+    let luminance = crate::diffusivity::load(bytes, width, height)?;
+    Ok(luminance
+        .into_iter()
+        .map(|luminance| (luminance >= 0.5) as u32)
+        .collect())
+}
+
+/// Set the cells of `mask` whose centre is within `radius` of that of the cell (`x`, `y`) on
+/// `lattice` to walls, or open them if `wall` is false. Return the range of rows that changed, if
+/// any.
+pub(crate) fn paint(
+    mask: &mut [u32],
+    (width, height): (u32, u32),
+    lattice: Lattice,
+    (x, y): (i64, i64),
+    radius: u32,
+    wall: bool,
+) -> Option<std::ops::Range<u32>> {
+    // the rows of the hexagonal lattice are closer than its columns, and shifted by half a cell
+    let row_radius = (radius as f64 / lattice.row_spacing()).floor() as i64;
+    let column_radius = radius as i64 + 1;
+    let rows = (y - row_radius).max(0)..(y + row_radius + 1).min(height as i64);
+    let columns = (x - column_radius).max(0)..(x + column_radius + 1).min(width as i64);
+    if rows.is_empty() || columns.is_empty() {
+        return None;
+    }
+    let centre = lattice.centre((x, y));
+    for row in rows.clone() {
+        for column in columns.clone() {
+            let (cx, cy) = lattice.centre((column, row));
+            // with some tolerance for the rounding of the distances on the hexagonal lattice
+            if (cx - centre.0).hypot(cy - centre.1) <= radius as f64 + 1e-9 {
+                mask[(column + row * width as i64) as usize] = !wall as u32;
+            }
+        }
+    }
+    Some(rows.start as u32..rows.end as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::paint;
+    use crate::config::Lattice;
+
+    /// Cells painted by a brush of `radius` around (5, 5) on a 12 × 12 grid
+    fn painted(lattice: Lattice, radius: u32) -> Vec<(u32, u32)> {
+        let mut mask = vec![1; 144];
+        paint(&mut mask, (12, 12), lattice, (5, 5), radius, true);
+        (0..144)
+            .filter(|&i| mask[i as usize] == 0)
+            .map(|i| (i % 12, i / 12))
+            .collect()
+    }
+
+    #[test]
+    fn paint_disk_of_neighbours() {
+        assert_eq!(
+            painted(Lattice::Square, 1),
+            vec![(5, 4), (4, 5), (5, 5), (6, 5), (5, 6)]
+        );
+        // the odd rows are shifted right, so the neighbours above and below are on the right
+        assert_eq!(
+            painted(Lattice::Hexagonal, 1),
+            vec![(5, 4), (6, 4), (4, 5), (5, 5), (6, 5), (5, 6), (6, 6)]
+        );
+        assert_eq!(painted(Lattice::Hexagonal, 0), vec![(5, 5)]);
+    }
+
+    #[test]
+    fn cell_under_the_centre_of_each_cell() {
+        let rows = 12;
+        for lattice in [Lattice::Square, Lattice::Hexagonal] {
+            let scale = lattice.image_height(rows) as f64 / rows as f64;
+            for row in 0..rows as i64 {
+                for column in 0..12 {
+                    let (x, _) = lattice.centre((column, row));
+                    let pixel = (x + 0.5, (row as f64 + 0.5) * scale);
+                    assert_eq!(lattice.cell(pixel, rows), (column, row), "{:?}", lattice);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,734 @@
+use std::path::Path;
+
+use crate::config::read_file;
+
+/// Triangle mesh on whose vertices the species react and diffuse, instead of the cells of a grid
+pub(crate) struct Mesh {
+    /// Path of the file the mesh was loaded from
+    pub(crate) name: String,
+    pub(crate) positions: Vec<[f32; 3]>,
+    pub(crate) triangles: Vec<[u32; 3]>,
+}
+
+/// Cotangent Laplacian of a mesh, packed in a single buffer for `diffusion.wgsl`
+pub(crate) struct Laplacian {
+    /// The range of the neighbours of each vertex in this same array, followed by the neighbours
+    /// of all the vertices, each one with the bits of its weight
+    pub(crate) entries: Vec<[u32; 2]>,
+    /// Upper bound of the largest decay rate of the operator, from the Gershgorin circle theorem
+    pub(crate) spectral_radius: f32,
+}
+
+impl Mesh {
+    /// Load a triangle mesh from an OBJ or PLY file, the polygons being split into triangles
+    pub(crate) fn load(path: &Path) -> Result<Self, String> {
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(str::to_ascii_lowercase);
+        let bytes = read_file(path)?;
+        Self::from_bytes(&bytes, extension.as_deref(), path.display().to_string())
+    }
+
+    /// Triangle mesh of the bytes of a file with the given `extension`, `obj` or `ply`, see
+    /// `load`
+    fn from_bytes(bytes: &[u8], extension: Option<&str>, name: String) -> Result<Self, String> {
+        let (positions, triangles) = match extension {
+            Some("obj") => load_obj(&String::from_utf8_lossy(bytes))?,
+            Some("ply") => load_ply(bytes)?,
+            _ => return Err("expected an .obj or a .ply file".to_string()),
+        };
+        if triangles.is_empty() {
+            return Err("the mesh has no triangles".to_string());
+        }
+        if let Some(index) = triangles
+            .iter()
+            .flatten()
+            .find(|&&index| index as usize >= positions.len())
+        {
+            return Err(format!(
+                "vertex index {} out of range, the mesh has {} vertices",
+                index,
+                positions.len()
+            ));
+        }
+        if positions.iter().flatten().any(|value| !value.is_finite()) {
+            return Err("the mesh has non finite coordinates".to_string());
+        }
+        Ok(Self {
+            name,
+            positions,
+            triangles,
+        })
+    }
+
+    /// Size of the grid holding the field of the vertices, row by row, as square as possible so
+    /// that it fits in the storage textures
+    pub(crate) fn grid_size(&self) -> (u32, u32) {
+        let vertices = self.positions.len() as u32;
+        let width = (vertices as f64).sqrt().ceil().max(1.0) as u32;
+        (width, vertices.div_ceil(width))
+    }
+
+    /// Scale the mesh so that the mean length of the edges of its triangles is `length`
+    pub(crate) fn scale_edges(&mut self, length: f32) {
+        let total: f64 = self
+            .triangles
+            .iter()
+            .flat_map(|triangle| {
+                [0, 1, 2].map(|k| {
+                    let p = self.positions[triangle[k] as usize];
+                    let q = self.positions[triangle[(k + 1) % 3] as usize];
+                    norm(sub(q, p)) as f64
+                })
+            })
+            .sum();
+        let mean = total / (3 * self.triangles.len()) as f64;
+        if mean > 0.0 {
+            let scale = (length as f64 / mean) as f32;
+            for position in &mut self.positions {
+                *position = position.map(|value| value * scale);
+            }
+        }
+    }
+
+    /// Smallest and largest coordinates of the vertices
+    pub(crate) fn bounds(&self) -> ([f32; 3], [f32; 3]) {
+        self.positions.iter().fold(
+            ([f32::INFINITY; 3], [f32::NEG_INFINITY; 3]),
+            |(min, max), p| {
+                (
+                    [0, 1, 2].map(|k| min[k].min(p[k])),
+                    [0, 1, 2].map(|k| max[k].max(p[k])),
+                )
+            },
+        )
+    }
+
+    /// Whether each vertex is seeded, within a tenth of the diagonal of the bounding box of the
+    /// vertex nearest to its centre
+    pub(crate) fn seeded(&self) -> Vec<bool> {
+        let (min, max) = self.bounds();
+        let centre = [0, 1, 2].map(|k| 0.5 * (min[k] + max[k]));
+        let radius = 0.1 * norm(sub(max, min));
+        let distance = |p: &[f32; 3], q: [f32; 3]| norm(sub(*p, q));
+        let seed = self
+            .positions
+            .iter()
+            .min_by(|p, q| distance(p, centre).total_cmp(&distance(q, centre)))
+            .copied()
+            .unwrap_or(centre);
+        self.positions
+            .iter()
+            .map(|p| distance(p, seed) <= radius)
+            .collect()
+    }
+
+    /// Unit normal of each vertex, the mean of those of its triangles weighted by their area
+    pub(crate) fn normals(&self) -> Vec<[f32; 3]> {
+        let mut normals = vec![[0.0f32; 3]; self.positions.len()];
+        for triangle in &self.triangles {
+            let [p0, p1, p2] = triangle.map(|vertex| self.positions[vertex as usize]);
+            let normal = cross(sub(p1, p0), sub(p2, p0));
+            for &vertex in triangle {
+                let sum = &mut normals[vertex as usize];
+                *sum = [0, 1, 2].map(|k| sum[k] + normal[k]);
+            }
+        }
+        for normal in &mut normals {
+            let length = norm(*normal);
+            *normal = if length > 0.0 {
+                normal.map(|value| value / length)
+            } else {
+                [0.0, 0.0, 1.0]
+            };
+        }
+        normals
+    }
+
+    /// Cotangent Laplacian of the mesh with lumped masses, multiplied by `scale`.
+    ///
+    /// At vertex `i` it is the sum over the neighbours `j` of `(cot α + cot β) / (2 A)` times the
+    /// difference of the concentrations, where α and β are the angles opposite to the edge in
+    /// its two triangles and `A` is a third of the area of the triangles around the vertex.
+    /// Degenerate triangles are skipped, and the boundaries of open meshes are impermeable.
+    pub(crate) fn laplacian(&self, scale: f32) -> Laplacian {
+        let vertices = self.positions.len();
+        let mut areas = vec![0.0f32; vertices];
+        let mut weights = Vec::with_capacity(6 * self.triangles.len());
+        for triangle in &self.triangles {
+            let p = triangle.map(|vertex| self.positions[vertex as usize]);
+            let double_area = norm(cross(sub(p[1], p[0]), sub(p[2], p[0])));
+            if double_area == 0.0 {
+                continue;
+            }
+            for k in 0..3 {
+                let (i, j) = ((k + 1) % 3, (k + 2) % 3);
+                // cotangent of the angle at k, opposite to the edge (i, j)
+                let cot = dot(sub(p[i], p[k]), sub(p[j], p[k])) / double_area;
+                weights.push((triangle[i], triangle[j], 0.5 * cot));
+                weights.push((triangle[j], triangle[i], 0.5 * cot));
+                areas[triangle[k] as usize] += double_area / 6.0;
+            }
+        }
+
+        // sum the weights of the edges shared by two triangles
+        weights.sort_unstable_by_key(|&(i, j, _)| (i, j));
+        let mut edges: Vec<(u32, u32, f32)> = Vec::with_capacity(weights.len());
+        for (i, j, weight) in weights {
+            match edges.last_mut() {
+                Some(last) if (last.0, last.1) == (i, j) => last.2 += weight,
+                _ => edges.push((i, j, weight)),
+            }
+        }
+
+        let mut entries = Vec::with_capacity(vertices + edges.len());
+        let mut spectral_radius = 0.0f32;
+        let mut start = 0;
+        for (i, area) in areas.iter().enumerate() {
+            let count = edges[start..]
+                .iter()
+                .take_while(|edge| edge.0 as usize == i)
+                .count();
+            let row = &edges[start..start + count];
+            entries.push([(vertices + start) as u32, (vertices + start + count) as u32]);
+            start += count;
+            if *area > 0.0 {
+                let total: f32 = row.iter().map(|edge| edge.2).sum();
+                let absolute: f32 = row.iter().map(|edge| edge.2.abs()).sum();
+                spectral_radius = spectral_radius.max(scale * (total.abs() + absolute) / area);
+            }
+        }
+        entries.extend(
+            edges
+                .iter()
+                .map(|&(i, j, weight)| [j, (scale * weight / areas[i as usize]).to_bits()]),
+        );
+        Laplacian {
+            entries,
+            spectral_radius,
+        }
+    }
+}
+
+fn sub(p: [f32; 3], q: [f32; 3]) -> [f32; 3] {
+    [p[0] - q[0], p[1] - q[1], p[2] - q[2]]
+}
+
+fn dot(p: [f32; 3], q: [f32; 3]) -> f32 {
+    p[0] * q[0] + p[1] * q[1] + p[2] * q[2]
+}
+
+fn cross(p: [f32; 3], q: [f32; 3]) -> [f32; 3] {
+    [
+        p[1] * q[2] - p[2] * q[1],
+        p[2] * q[0] - p[0] * q[2],
+        p[0] * q[1] - p[1] * q[0],
+    ]
+}
+
+fn norm(p: [f32; 3]) -> f32 {
+    dot(p, p).sqrt()
+}
+
+/// Positions and triangles read from a mesh file
+type Geometry = (Vec<[f32; 3]>, Vec<[u32; 3]>);
+
+/// Split a polygon into a fan of triangles around its first vertex
+fn triangulate(polygon: &[u32], triangles: &mut Vec<[u32; 3]>) {
+    for k in 1..polygon.len().saturating_sub(1) {
+        triangles.push([polygon[0], polygon[k], polygon[k + 1]]);
+    }
+}
+
+/// Positions and triangles of a Wavefront OBJ file, from its vertices and faces
+fn load_obj(text: &str) -> Result<Geometry, String> {
+    let mut positions = Vec::new();
+    let mut triangles = Vec::new();
+    for (number, line) in (1..).zip(text.lines()) {
+        let mut tokens = line.split_whitespace();
+        match tokens.next() {
+            Some("v") => {
+                let coordinates: Vec<f32> = tokens
+                    .take(3)
+                    .map(|token| token.parse())
+                    .collect::<Result<_, _>>()
+                    .map_err(|e| format!("line {}: {}", number, e))?;
+                let [x, y, z] = coordinates[..] else {
+                    return Err(format!("line {}: expected three coordinates", number));
+                };
+                positions.push([x, y, z]);
+            }
+            Some("f") => {
+                // `v`, `v/vt`, `v//vn` or `v/vt/vn`, negative indices counting from the end
+                let polygon: Vec<u32> = tokens
+                    .map(|token| {
+                        let index: i64 = token
+                            .split('/')
+                            .next()
+                            .and_then(|index| index.parse().ok())
+                            .ok_or_else(|| format!("line {}: invalid vertex {}", number, token))?;
+                        let index = if index < 0 {
+                            positions.len() as i64 + index
+                        } else {
+                            index - 1
+                        };
+                        u32::try_from(index)
+                            .map_err(|_| format!("line {}: invalid vertex {}", number, token))
+                    })
+                    .collect::<Result<_, _>>()?;
+                triangulate(&polygon, &mut triangles);
+            }
+            _ => {}
+        }
+    }
+    Ok((positions, triangles))
+}
+
+/// Encoding of the elements of a PLY file
+#[derive(Clone, Copy, PartialEq)]
+enum PlyFormat {
+    Ascii,
+    LittleEndian,
+    BigEndian,
+}
+
+/// Type of a property of a PLY file
+#[derive(Clone, Copy)]
+enum PlyType {
+    Int8,
+    UInt8,
+    Int16,
+    UInt16,
+    Int32,
+    UInt32,
+    Float32,
+    Float64,
+}
+
+impl PlyType {
+    fn parse(name: &str) -> Result<Self, String> {
+        Ok(match name {
+            "char" | "int8" => Self::Int8,
+            "uchar" | "uint8" => Self::UInt8,
+            "short" | "int16" => Self::Int16,
+            "ushort" | "uint16" => Self::UInt16,
+            "int" | "int32" => Self::Int32,
+            "uint" | "uint32" => Self::UInt32,
+            "float" | "float32" => Self::Float32,
+            "double" | "float64" => Self::Float64,
+            _ => return Err(format!("unknown property type {}", name)),
+        })
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Self::Int8 | Self::UInt8 => 1,
+            Self::Int16 | Self::UInt16 => 2,
+            Self::Int32 | Self::UInt32 | Self::Float32 => 4,
+            Self::Float64 => 8,
+        }
+    }
+
+    /// Value of the little endian `bytes`
+    fn value(self, bytes: [u8; 8]) -> f64 {
+        let [b0, b1, b2, b3, ..] = bytes;
+        match self {
+            Self::Int8 => b0 as i8 as f64,
+            Self::UInt8 => b0 as f64,
+            Self::Int16 => i16::from_le_bytes([b0, b1]) as f64,
+            Self::UInt16 => u16::from_le_bytes([b0, b1]) as f64,
+            Self::Int32 => i32::from_le_bytes([b0, b1, b2, b3]) as f64,
+            Self::UInt32 => u32::from_le_bytes([b0, b1, b2, b3]) as f64,
+            Self::Float32 => f32::from_le_bytes([b0, b1, b2, b3]) as f64,
+            Self::Float64 => f64::from_le_bytes(bytes),
+        }
+    }
+}
+
+/// Property of the elements of a PLY file, with the type of its length if it is a list
+struct PlyProperty {
+    name: String,
+    value_type: PlyType,
+    length_type: Option<PlyType>,
+}
+
+struct PlyElement {
+    name: String,
+    count: usize,
+    properties: Vec<PlyProperty>,
+}
+
+/// Reader of the values of the elements of a PLY file, after its header
+struct PlyReader<'a> {
+    format: PlyFormat,
+    data: &'a [u8],
+    tokens: std::str::SplitAsciiWhitespace<'a>,
+}
+
+impl PlyReader<'_> {
+    fn read(&mut self, value_type: PlyType) -> Result<f64, String> {
+        if self.format == PlyFormat::Ascii {
+            let token = self.tokens.next().ok_or("the .ply file is truncated")?;
+            return token
+                .parse()
+                .map_err(|_| format!("invalid value {} in the .ply file", token));
+        }
+        let size = value_type.size();
+        let (value, data) = self
+            .data
+            .split_at_checked(size)
+            .ok_or("the .ply file is truncated")?;
+        self.data = data;
+        let mut bytes = [0; 8];
+        bytes[..size].copy_from_slice(value);
+        if self.format == PlyFormat::BigEndian {
+            bytes[..size].reverse();
+        }
+        Ok(value_type.value(bytes))
+    }
+}
+
+/// Positions and triangles of a PLY file, in ASCII or binary, from the `x`, `y` and `z`
+/// properties of its vertices and the first list of its faces
+fn load_ply(bytes: &[u8]) -> Result<Geometry, String> {
+    const END_HEADER: &[u8] = b"end_header";
+    let header_length = bytes
+        .windows(END_HEADER.len())
+        .position(|window| window == END_HEADER)
+        .ok_or("not a valid .ply file, the header has no end")?;
+    let header = std::str::from_utf8(&bytes[..header_length])
+        .map_err(|_| "not a valid .ply file, the header is not text")?;
+    // the data follows the line ending the header
+    let data_start = bytes[header_length..]
+        .iter()
+        .position(|&byte| byte == b'\n')
+        .map_or(bytes.len(), |end| header_length + end + 1);
+
+    let mut lines = header.lines().map(str::trim);
+    if lines.next() != Some("ply") {
+        return Err("not a valid .ply file".to_string());
+    }
+    let mut format = None;
+    let mut elements: Vec<PlyElement> = Vec::new();
+    for line in lines {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        match tokens[..] {
+            ["format", name, _] => {
+                format = Some(match name {
+                    "ascii" => PlyFormat::Ascii,
+                    "binary_little_endian" => PlyFormat::LittleEndian,
+                    "binary_big_endian" => PlyFormat::BigEndian,
+                    _ => return Err(format!("unknown .ply format {}", name)),
+                })
+            }
+            ["element", name, count] => elements.push(PlyElement {
+                name: name.to_string(),
+                count: count
+                    .parse()
+                    .map_err(|_| format!("invalid count of {} in the .ply header", name))?,
+                properties: Vec::new(),
+            }),
+            ["property", "list", length_type, value_type, name] => elements
+                .last_mut()
+                .ok_or("property before any element in the .ply header")?
+                .properties
+                .push(PlyProperty {
+                    name: name.to_string(),
+                    value_type: PlyType::parse(value_type)?,
+                    length_type: Some(PlyType::parse(length_type)?),
+                }),
+            ["property", value_type, name] => elements
+                .last_mut()
+                .ok_or("property before any element in the .ply header")?
+                .properties
+                .push(PlyProperty {
+                    name: name.to_string(),
+                    value_type: PlyType::parse(value_type)?,
+                    length_type: None,
+                }),
+            _ => {}
+        }
+    }
+    let format = format.ok_or("missing format in the .ply header")?;
+    let data = &bytes[data_start..];
+    let text = if format == PlyFormat::Ascii {
+        std::str::from_utf8(data).map_err(|_| "the data of the ASCII .ply file is not text")?
+    } else {
+        ""
+    };
+    let mut reader = PlyReader {
+        format,
+        data,
+        tokens: text.split_ascii_whitespace(),
+    };
+
+    let mut positions = Vec::new();
+    let mut triangles = Vec::new();
+    for element in &elements {
+        let property = |name: &str| element.properties.iter().position(|p| p.name == name);
+        let coordinates = [property("x"), property("y"), property("z")];
+        let faces = element
+            .properties
+            .iter()
+            .position(|p| p.length_type.is_some());
+        let is_vertex = element.name == "vertex";
+        if is_vertex && coordinates.contains(&None) {
+            return Err("the vertices of the .ply file have no x, y and z".to_string());
+        }
+        let mut values = Vec::new();
+        let mut polygon = Vec::new();
+        for _ in 0..element.count {
+            values.clear();
+            for (p, property) in element.properties.iter().enumerate() {
+                let Some(length_type) = property.length_type else {
+                    values.push(reader.read(property.value_type)?);
+                    continue;
+                };
+                let length = reader.read(length_type)? as usize;
+                polygon.clear();
+                for _ in 0..length {
+                    polygon.push(reader.read(property.value_type)? as u32);
+                }
+                if element.name == "face" && faces == Some(p) {
+                    triangulate(&polygon, &mut triangles);
+                }
+                values.push(0.0);
+            }
+            if is_vertex {
+                positions.push(coordinates.map(|k| values[k.unwrap()] as f32));
+            }
+        }
+    }
+    Ok((positions, triangles))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{cross, norm, sub, Mesh};
+
+    const TETRAHEDRON_OBJ: &str = "\
+# unit tetrahedron
+v 0 0 0
+v 1 0 0
+v 0 1 0
+v 0 0 1
+f 1 3 2
+f 1 2 4
+f 1 4 3
+f 2 3 4
+";
+
+    fn parse(bytes: &[u8], extension: &str) -> Result<Mesh, String> {
+        Mesh::from_bytes(bytes, Some(extension), "test".to_string())
+    }
+
+    /// Header of a PLY file with `vertices` vertices and `faces` faces
+    fn ply_header(format: &str, vertices: usize, faces: usize) -> String {
+        format!(
+            "ply\nformat {} 1.0\ncomment test\nelement vertex {}\nproperty float x\n\
+             property float y\nproperty float z\nelement face {}\n\
+             property list uchar int vertex_indices\nend_header\n",
+            format, vertices, faces
+        )
+    }
+
+    /// Binary PLY file of the tetrahedron
+    fn tetrahedron_ply() -> Vec<u8> {
+        let mesh = parse(TETRAHEDRON_OBJ.as_bytes(), "obj").unwrap();
+        let mut bytes = ply_header("binary_little_endian", 4, 4).into_bytes();
+        for position in &mesh.positions {
+            for value in position {
+                bytes.extend(value.to_le_bytes());
+            }
+        }
+        for triangle in &mesh.triangles {
+            bytes.push(3);
+            for index in triangle {
+                bytes.extend((*index as i32).to_le_bytes());
+            }
+        }
+        bytes
+    }
+
+    /// `laplacian` applied to the field `values` of the vertices
+    fn apply(mesh: &Mesh, values: &[f32]) -> Vec<f32> {
+        let entries = mesh.laplacian(1.0).entries;
+        (0..values.len())
+            .map(|i| {
+                let [start, end] = entries[i];
+                entries[start as usize..end as usize]
+                    .iter()
+                    .map(|&[j, weight]| f32::from_bits(weight) * (values[j as usize] - values[i]))
+                    .sum()
+            })
+            .collect()
+    }
+
+    /// Third of the area of the triangles around each vertex, the mass lumped on it
+    fn areas(mesh: &Mesh) -> Vec<f32> {
+        let mut areas = vec![0.0; mesh.positions.len()];
+        for triangle in &mesh.triangles {
+            let p = triangle.map(|vertex| mesh.positions[vertex as usize]);
+            let area = 0.5 * norm(cross(sub(p[1], p[0]), sub(p[2], p[0])));
+            for vertex in triangle {
+                areas[*vertex as usize] += area / 3.0;
+            }
+        }
+        areas
+    }
+
+    #[test]
+    fn load_obj_tetrahedron() {
+        let mesh = parse(TETRAHEDRON_OBJ.as_bytes(), "obj").unwrap();
+        assert_eq!(mesh.positions.len(), 4);
+        assert_eq!(mesh.positions[3], [0.0, 0.0, 1.0]);
+        assert_eq!(
+            mesh.triangles,
+            vec![[0, 2, 1], [0, 1, 3], [0, 3, 2], [1, 2, 3]]
+        );
+    }
+
+    #[test]
+    fn load_obj_face_formats_and_negative_indices() {
+        let obj = "\
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+vt 0 0
+vn 0 0 1
+f 1/1/1 2/1/1 3/1/1
+f 1//1 3//1 4//1
+f -4/1 -2/1 -1/1
+";
+        let mesh = parse(obj.as_bytes(), "obj").unwrap();
+        assert_eq!(mesh.triangles, vec![[0, 1, 2], [0, 2, 3], [0, 2, 3]]);
+    }
+
+    #[test]
+    fn load_obj_splits_polygons() {
+        let obj = "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nv -1 1 0\nf 1 2 3 4 5\n";
+        let mesh = parse(obj.as_bytes(), "obj").unwrap();
+        assert_eq!(mesh.triangles, vec![[0, 1, 2], [0, 2, 3], [0, 3, 4]]);
+    }
+
+    #[test]
+    fn load_obj_errors() {
+        let error = |obj: &str| parse(obj.as_bytes(), "obj").err().unwrap();
+        assert_eq!(error("v 0 0 0\n"), "the mesh has no triangles");
+        assert_eq!(
+            error("v 0 0\nf 1 1 1\n"),
+            "line 1: expected three coordinates"
+        );
+        assert_eq!(error("v 0 0 0\nf 1 x 1\n"), "line 2: invalid vertex x");
+        assert_eq!(error("v 0 0 0\nf -2 1 1\n"), "line 2: invalid vertex -2");
+        assert_eq!(
+            error("v 0 0 0\nf 1 2 1\n"),
+            "vertex index 1 out of range, the mesh has 1 vertices"
+        );
+        assert_eq!(
+            parse(b"", "stl").err().unwrap(),
+            "expected an .obj or a .ply file"
+        );
+    }
+
+    #[test]
+    fn load_ply_cube() {
+        let mut ply = ply_header("ascii", 8, 6);
+        for z in 0..2 {
+            for y in 0..2 {
+                for x in 0..2 {
+                    ply += &format!("{} {} {}\n", x, y, z);
+                }
+            }
+        }
+        for face in [
+            [0, 2, 3, 1],
+            [4, 5, 7, 6],
+            [0, 1, 5, 4],
+            [2, 6, 7, 3],
+            [0, 4, 6, 2],
+            [1, 3, 7, 5],
+        ] {
+            ply += &format!("4 {} {} {} {}\n", face[0], face[1], face[2], face[3]);
+        }
+        let mesh = parse(ply.as_bytes(), "ply").unwrap();
+        assert_eq!(mesh.positions.len(), 8);
+        assert_eq!(mesh.positions[6], [0.0, 1.0, 1.0]);
+        assert_eq!(mesh.triangles.len(), 12);
+        assert_eq!(mesh.triangles[..2], [[0, 2, 3], [0, 3, 1]]);
+    }
+
+    #[test]
+    fn load_binary_ply_tetrahedron() {
+        let obj = parse(TETRAHEDRON_OBJ.as_bytes(), "obj").unwrap();
+        let ply = parse(&tetrahedron_ply(), "ply").unwrap();
+        assert_eq!(ply.positions, obj.positions);
+        assert_eq!(ply.triangles, obj.triangles);
+    }
+
+    #[test]
+    fn load_truncated_binary_ply() {
+        let bytes = tetrahedron_ply();
+        assert_eq!(
+            parse(&bytes[..bytes.len() - 1], "ply").err().unwrap(),
+            "the .ply file is truncated"
+        );
+        let header = ply_header("binary_little_endian", 4, 4);
+        assert_eq!(
+            parse(&bytes[..header.len() + 10], "ply").err().unwrap(),
+            "the .ply file is truncated"
+        );
+        assert_eq!(
+            parse(&bytes[..20], "ply").err().unwrap(),
+            "not a valid .ply file, the header has no end"
+        );
+    }
+
+    #[test]
+    fn laplacian_conserves_the_total_quantity() {
+        let mesh = parse(TETRAHEDRON_OBJ.as_bytes(), "obj").unwrap();
+        let values = [1.0, 3.0, -2.0, 0.5];
+        // the rows weighted by the areas are symmetric, so their columns sum to zero as well
+        let total: f32 = apply(&mesh, &values)
+            .iter()
+            .zip(areas(&mesh))
+            .map(|(laplacian, area)| laplacian * area)
+            .sum();
+        assert!(total.abs() < 1e-5, "{}", total);
+        // and a uniform field does not change
+        assert!(apply(&mesh, &[2.0; 4]).iter().all(|&value| value == 0.0));
+    }
+
+    #[test]
+    fn laplacian_of_a_regular_fan() {
+        // a vertex surrounded by six equilateral triangles with sides of one
+        let mut obj = "v 0 0 0\n".to_string();
+        for k in 0..6 {
+            let angle = std::f32::consts::PI * k as f32 / 3.0;
+            obj += &format!("v {} {} 0\n", angle.cos(), angle.sin());
+        }
+        for k in 0..6 {
+            obj += &format!("f 1 {} {}\n", k + 2, (k + 1) % 6 + 2);
+        }
+        let mesh = parse(obj.as_bytes(), "obj").unwrap();
+
+        // the cotangents of the angles of 60° are 1 / sqrt(3), and the area of the vertex is
+        // sqrt(3) / 2, so that the weight of each neighbour is 2 / 3 times the scale
+        let laplacian = mesh.laplacian(1.5);
+        let [start, end] = laplacian.entries[0];
+        assert_eq!(end - start, 6);
+        for &[_, weight] in &laplacian.entries[start as usize..end as usize] {
+            assert!((f32::from_bits(weight) - 1.0).abs() < 1e-5);
+        }
+
+        // exact for the polynomials of degree two at the centre
+        let field =
+            |f: fn([f32; 3]) -> f32| -> Vec<f32> { mesh.positions.iter().map(|&p| f(p)).collect() };
+        let linear = apply(&mesh, &field(|p| 2.0 * p[0] - p[1] + 1.0));
+        assert!(linear[0].abs() < 1e-5, "{}", linear[0]);
+        let quadratic = apply(&mesh, &field(|p| p[0] * p[0] + p[1] * p[1]));
+        assert!((quadratic[0] - 4.0).abs() < 1e-5, "{}", quadratic[0]);
+    }
+}
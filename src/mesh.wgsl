@@ -0,0 +1,54 @@
+// Drawing of the field on the triangles of a mesh, appended to `shader.wgsl` for its colormaps
+// and its orbit camera
+
+struct MeshView {
+    // size of the render target, in pixels
+    screen: vec2<f32>,
+};
+
+@group(1) @binding(0) var<uniform> mesh_view: MeshView;
+
+// Depth range of the camera
+const NEAR: f32 = 0.01;
+const FAR: f32 = 10.0;
+
+struct MeshVertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) normal: vec3<f32>,
+    // concentrations of the vertex, interpolated over the triangles
+    @location(1) concentrations: Cell,
+};
+
+// Project the vertex seen from the orbit camera, the mesh being centred on the origin with its
+// largest side of length 1
+@vertex
+fn vs_mesh(
+    @builtin(vertex_index) vertex_index: u32,
+    @location(0) position: vec3<f32>,
+    @location(1) normal: vec3<f32>,
+) -> MeshVertexOutput {
+    let camera = orbit_camera();
+    let view = position - camera.eye;
+    let depth = dot(view, camera.forward);
+    let focal = 1.0 / tan(0.5 * FIELD_OF_VIEW);
+    let aspect = mesh_view.screen.x / mesh_view.screen.y;
+
+    var out: MeshVertexOutput;
+    out.clip_position = vec4<f32>(
+        dot(view, camera.right) * focal / aspect,
+        dot(view, camera.up) * focal,
+        (depth - NEAR) * FAR / (FAR - NEAR),
+        depth,
+    );
+    out.normal = normal;
+    out.concentrations = load(vertex_index % config.width, vertex_index / config.width);
+    return out;
+}
+
+// Color of the concentrations, shaded by the angle between the surface and the view, on both
+// sides of the surface
+@fragment
+fn fs_mesh(in: MeshVertexOutput) -> @location(0) vec4<f32> {
+    let light = 0.3 + 0.7 * abs(dot(normalize(in.normal), orbit_camera().forward));
+    return vec4<f32>(shade(in.concentrations).rgb * light, 1.0);
+}
@@ -0,0 +1,208 @@
+use wgpu::util::DeviceExt;
+
+use crate::font::{glyph_index, FONT};
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Zeroable, bytemuck::Pod)]
+struct OverlayUniform {
+    screen: [f32; 2],
+    origin: [f32; 2],
+    columns: u32,
+    rows: u32,
+    scale: u32,
+    _padding: u32,
+}
+
+/// Corner of the surface where the overlay is placed
+pub(crate) enum Corner {
+    TopLeft,
+    TopRight,
+}
+
+/// Block of monospaced text drawn on top of the simulation
+pub(crate) struct Overlay {
+    render_pipeline: wgpu::RenderPipeline,
+    bind_group: wgpu::BindGroup,
+    columns: u32,
+    rows: u32,
+    text_buffer: wgpu::Buffer,
+    text: String,
+}
+
+impl Overlay {
+    const SHADER: &'static str = include_str!("overlay.wgsl");
+    /// Distance of the overlay from the corner of the surface, in pixels
+    const OFFSET: f32 = 8.0;
+    /// Size of each pixel of the font, in screen pixels
+    const SCALE: u32 = 2;
+    /// Width of each character, including spacing, in font pixels (see `overlay.wgsl`)
+    const CELL_WIDTH: u32 = 6;
+    /// Margin around the text, in font pixels (see `overlay.wgsl`)
+    const MARGIN: u32 = 2;
+
+    pub(crate) fn new(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        width: u32,
+        height: u32,
+        columns: u32,
+        rows: u32,
+        corner: Corner,
+    ) -> Self {
+        let overlay_width = ((columns * Self::CELL_WIDTH + 2 * Self::MARGIN) * Self::SCALE) as f32;
+        let origin = match corner {
+            Corner::TopLeft => [Self::OFFSET, Self::OFFSET],
+            Corner::TopRight => [width as f32 - overlay_width - Self::OFFSET, Self::OFFSET],
+        };
+        let uniform = OverlayUniform {
+            screen: [width as f32, height as f32],
+            origin,
+            columns,
+            rows,
+            scale: Self::SCALE,
+            _padding: 0,
+        };
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Overlay uniform"),
+            contents: bytemuck::cast_slice(&[uniform]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let font: Vec<u32> = FONT.iter().flatten().map(|&row| row as u32).collect();
+        let font_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Overlay font"),
+            contents: bytemuck::cast_slice(&font),
+            usage: wgpu::BufferUsages::STORAGE,
+        });
+
+        let text_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Overlay text"),
+            contents: bytemuck::cast_slice(&Self::glyphs("", columns, rows)),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let storage_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only: true },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Overlay BindGroupLayout"),
+            entries: &[
+                // overlay
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                // font
+                storage_entry(1),
+                // text
+                storage_entry(2),
+            ],
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Overlay BindGroup"),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: font_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: text_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Overlay Shader"),
+            source: wgpu::ShaderSource::Wgsl(Self::SHADER.into()),
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Overlay PipelineLayout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Overlay RenderPipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: None,
+                buffers: &[],
+                compilation_options: Default::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: None,
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: Default::default(),
+            }),
+            primitive: Default::default(),
+            depth_stencil: None,
+            multisample: Default::default(),
+            multiview: None,
+            cache: None,
+        });
+
+        Self {
+            render_pipeline,
+            bind_group,
+            columns,
+            rows,
+            text_buffer,
+            text: String::new(),
+        }
+    }
+
+    /// Glyph indices of `text`, padded with spaces and truncated to fit the overlay
+    fn glyphs(text: &str, columns: u32, rows: u32) -> Vec<u32> {
+        let blank = glyph_index(' ');
+        let mut glyphs = vec![blank; (columns * rows) as usize];
+        for (row, line) in text.lines().take(rows as usize).enumerate() {
+            for (column, c) in line.chars().take(columns as usize).enumerate() {
+                glyphs[column + row * columns as usize] = glyph_index(c);
+            }
+        }
+        glyphs
+    }
+
+    /// Replace the text shown in the overlay, one row per line
+    pub(crate) fn set_text(&mut self, queue: &wgpu::Queue, text: &str) {
+        if self.text == text {
+            return;
+        }
+        self.text = text.to_string();
+        let glyphs = Self::glyphs(text, self.columns, self.rows);
+        queue.write_buffer(&self.text_buffer, 0, bytemuck::cast_slice(&glyphs));
+    }
+
+    pub(crate) fn draw(&self, render_pass: &mut wgpu::RenderPass) {
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.draw(0..6, 0..1);
+    }
+}
@@ -0,0 +1,83 @@
+struct Overlay {
+    // size of the surface, in pixels
+    screen: vec2<f32>,
+    // top-left corner of the overlay, in pixels
+    origin: vec2<f32>,
+    columns: u32,
+    rows: u32,
+    // size of each pixel of the font, in screen pixels
+    scale: u32,
+};
+
+@group(0) @binding(0) var<uniform> overlay: Overlay;
+// rows of each glyph, see `font.rs`
+@group(0) @binding(1) var<storage, read> font: array<u32>;
+// glyph index of each character, row by row
+@group(0) @binding(2) var<storage, read> text: array<u32>;
+
+const GLYPH_WIDTH: u32 = 5;
+const GLYPH_HEIGHT: u32 = 7;
+// glyphs are separated by one pixel horizontally and two pixels vertically
+const CELL_WIDTH: u32 = 6;
+const CELL_HEIGHT: u32 = 9;
+// margin around the text, in font pixels
+const MARGIN: u32 = 2;
+
+const FOREGROUND: vec4<f32> = vec4<f32>(1.0, 1.0, 1.0, 1.0);
+const BACKGROUND: vec4<f32> = vec4<f32>(0.0, 0.0, 0.0, 0.6);
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+};
+
+// Size of the overlay, in pixels
+fn overlay_size() -> vec2<f32> {
+    let cells = vec2<u32>(overlay.columns * CELL_WIDTH, overlay.rows * CELL_HEIGHT);
+    return vec2<f32>((cells + 2 * MARGIN) * overlay.scale);
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+    // two triangles covering the overlay
+    var corners = array<vec2<f32>, 6>(
+        vec2<f32>(0.0, 0.0),
+        vec2<f32>(0.0, 1.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(0.0, 0.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(1.0, 0.0),
+    );
+    let pixel = overlay.origin + corners[vertex_index] * overlay_size();
+    let ndc = pixel / overlay.screen * vec2<f32>(2.0, -2.0) + vec2<f32>(-1.0, 1.0);
+
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(ndc, 0.0, 1.0);
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let pixel = vec2<u32>((in.clip_position.xy - overlay.origin) / f32(overlay.scale));
+    if pixel.x < MARGIN || pixel.y < MARGIN {
+        return BACKGROUND;
+    }
+    let p = pixel - MARGIN;
+    let column = p.x / CELL_WIDTH;
+    let row = p.y / CELL_HEIGHT;
+    if column >= overlay.columns || row >= overlay.rows {
+        return BACKGROUND;
+    }
+
+    let x = p.x % CELL_WIDTH;
+    let y = p.y % CELL_HEIGHT;
+    if x >= GLYPH_WIDTH || y >= GLYPH_HEIGHT {
+        return BACKGROUND;
+    }
+
+    let glyph = text[column + row * overlay.columns];
+    let bits = font[glyph * GLYPH_HEIGHT + y];
+    if ((bits >> (GLYPH_WIDTH - 1 - x)) & 1) == 1 {
+        return FOREGROUND;
+    }
+    return BACKGROUND;
+}
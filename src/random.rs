@@ -0,0 +1,37 @@
+use web_time::{SystemTime, UNIX_EPOCH};
+
+/// Small seedable pseudo-random number generator (SplitMix64), so that runs can be reproduced
+/// exactly from their seed on every platform
+pub(crate) struct Random {
+    state: u64,
+}
+
+impl Random {
+    pub(crate) fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    pub(crate) fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniformly distributed number in `[-1, 1)`
+    pub(crate) fn next_signed(&mut self) -> f32 {
+        // the 24 high bits fit exactly in the mantissa of an `f32`
+        let unit = (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32;
+        2.0 * unit - 1.0
+    }
+}
+
+/// Seed used when none is given, derived from the current time
+pub(crate) fn random_seed() -> u64 {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_nanos() as u64)
+        .unwrap_or_default();
+    Random::new(nanos).next_u64()
+}
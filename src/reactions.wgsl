@@ -0,0 +1,54 @@
+// Kinetics of the reaction systems, see `Model` in `config.rs`. Each function returns the rate
+// of change of the concentrations `c`, given their diffusion, and is used through `rate` (see
+// `system.rs`).
+
+fn gray_scott(c: vec2<f32>, diffusion: vec2<f32>) -> vec2<f32> {
+    let f = config.feed;
+    let k = config.kill;
+
+    let a = c.x;
+    let b = c.y;
+    return vec2<f32>(
+        diffusion.x - (a * b * b) + (f * (1.0 - a)),
+        diffusion.y + (a * b * b) - (k * b),
+    );
+}
+
+// Strength of the inhibition of B by C
+const INHIBITION: f32 = 0.05;
+// Rate at which C relaxes towards the concentration of B
+const INHIBITOR_RATE: f32 = 0.01;
+
+// Gray-Scott model where B produces an inhibitor C, which diffuses faster and slows down the
+// growth of B
+fn gray_scott_inhibitor(c: vec3<f32>, diffusion: vec3<f32>) -> vec3<f32> {
+    let f = config.feed;
+    let k = config.kill;
+
+    let a = c.x;
+    let b = c.y;
+    let inhibitor = c.z;
+    return vec3<f32>(
+        diffusion.x - (a * b * b) + (f * (1.0 - a)),
+        diffusion.y + (a * b * b) - (k * b) - (INHIBITION * b * inhibitor),
+        diffusion.z + INHIBITOR_RATE * (b - inhibitor),
+    );
+}
+
+// Competition of each species with the next one, the previous one and the opposite one
+const COMPETITION_NEXT: f32 = 0.8;
+const COMPETITION_PREVIOUS: f32 = 1.3;
+const COMPETITION_OPPOSITE: f32 = 1.2;
+// Growth rate of the species, slow enough for explicit steps of the default timestep
+const GROWTH_RATE: f32 = 0.1;
+
+// Lotka-Volterra competition of four species in a cycle: each species is harmed less by the
+// next one than it harms it, so that every species outcompetes the next and is outcompeted by
+// the previous one
+fn lotka_volterra(c: vec4<f32>, diffusion: vec4<f32>) -> vec4<f32> {
+    let competition = c
+        + COMPETITION_NEXT * c.yzwx
+        + COMPETITION_PREVIOUS * c.wxyz
+        + COMPETITION_OPPOSITE * c.zwxy;
+    return diffusion + GROWTH_RATE * c * (1.0 - competition);
+}
@@ -0,0 +1,320 @@
+use wgpu::util::DeviceExt;
+
+use crate::diffusion::Diffusion;
+use crate::mesh::Mesh;
+
+/// Pipeline drawing the field of a `Diffusion` over the whole render target, or on the triangles
+/// of its mesh
+pub(crate) struct FieldRenderer {
+    vertex_buffer: wgpu::Buffer,
+    render_pipeline: wgpu::RenderPipeline,
+    /// Format of the render target
+    format: wgpu::TextureFormat,
+    /// Triangles of the mesh of the `Diffusion`, if it is simulated on one
+    mesh: Option<MeshGeometry>,
+}
+
+/// Buffers drawing the triangles of a mesh seen from the orbit camera, see `mesh.wgsl`
+struct MeshGeometry {
+    index_buffer: wgpu::Buffer,
+    index_count: u32,
+    /// Layout of `bind_group`, the group 1 of the pipeline
+    bind_group_layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    depth_view: wgpu::TextureView,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Zeroable, bytemuck::Pod)]
+struct MeshViewUniform {
+    screen: [f32; 2],
+}
+
+impl MeshGeometry {
+    const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+
+    /// Upload the triangles of `mesh` and create the depth buffer of a render target of
+    /// `width` × `height` pixels. Return the geometry and the vertex buffer, with the position
+    /// and the normal of each vertex, the mesh being centred with its largest side of length 1.
+    fn new(device: &wgpu::Device, mesh: &Mesh, width: u32, height: u32) -> (Self, wgpu::Buffer) {
+        let (min, max) = mesh.bounds();
+        let centre = [0, 1, 2].map(|k| 0.5 * (min[k] + max[k]));
+        let extent = (0..3).map(|k| max[k] - min[k]).fold(0.0, f32::max);
+        let scale = if extent > 0.0 { 1.0 / extent } else { 1.0 };
+        let vertices: Vec<[[f32; 3]; 2]> = mesh
+            .positions
+            .iter()
+            .zip(mesh.normals())
+            .map(|(position, normal)| {
+                [[0, 1, 2].map(|k| (position[k] - centre[k]) * scale), normal]
+            })
+            .collect();
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Mesh vertex buffer"),
+            contents: bytemuck::cast_slice(&vertices),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Mesh index buffer"),
+            contents: bytemuck::cast_slice(&mesh.triangles),
+            usage: wgpu::BufferUsages::INDEX,
+        });
+
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Mesh view uniform"),
+            contents: bytemuck::cast_slice(&[MeshViewUniform {
+                screen: [width as f32, height as f32],
+            }]),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Mesh BindGroupLayout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Mesh BindGroup"),
+            layout: &bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform_buffer.as_entire_binding(),
+            }],
+        });
+
+        let depth_texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Mesh depth texture"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: Self::DEPTH_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        });
+
+        let geometry = Self {
+            index_buffer,
+            index_count: 3 * mesh.triangles.len() as u32,
+            bind_group_layout,
+            bind_group,
+            depth_view: depth_texture.create_view(&Default::default()),
+        };
+        (geometry, vertex_buffer)
+    }
+}
+
+impl FieldRenderer {
+    const SHADER: &'static str = include_str!("shader.wgsl");
+    /// Shader drawing meshes, appended to `SHADER`
+    const MESH_SHADER: &'static str = include_str!("mesh.wgsl");
+    /// Color around the field, only visible around meshes
+    const BACKGROUND: wgpu::Color = wgpu::Color {
+        r: 0.2,
+        g: 0.2,
+        b: 0.2,
+        a: 1.0,
+    };
+
+    /// Create the pipeline drawing to render targets of `width` × `height` pixels with `format`
+    pub(crate) fn new(
+        device: &wgpu::Device,
+        diffusion: &Diffusion,
+        format: wgpu::TextureFormat,
+        width: u32,
+        height: u32,
+    ) -> Self {
+        let (mesh, vertex_buffer) = match diffusion.mesh() {
+            Some(mesh) => {
+                let (geometry, vertex_buffer) = MeshGeometry::new(device, mesh, width, height);
+                (Some(geometry), vertex_buffer)
+            }
+            None => {
+                let vertices = &[
+                    [-1.0f32, -1.0, 0.0],
+                    [1.0, -1.0, 0.0],
+                    [1.0, 1.0, 0.0],
+                    [-1.0, -1.0, 0.0],
+                    [1.0, 1.0, 0.0],
+                    [-1.0, 1.0, 0.0],
+                ];
+                let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("Vertex buffer"),
+                    contents: bytemuck::cast_slice(vertices),
+                    usage: wgpu::BufferUsages::VERTEX,
+                });
+                (None, vertex_buffer)
+            }
+        };
+
+        Self {
+            vertex_buffer,
+            render_pipeline: Self::create_render_pipeline(
+                device,
+                diffusion,
+                format,
+                Self::SHADER,
+                mesh.as_ref(),
+            ),
+            format,
+            mesh,
+        }
+    }
+
+    /// Create the pipeline drawing the field with `shader`, the source of `shader.wgsl`, on the
+    /// triangles of `mesh` if there is one
+    fn create_render_pipeline(
+        device: &wgpu::Device,
+        diffusion: &Diffusion,
+        format: wgpu::TextureFormat,
+        shader: &str,
+        mesh: Option<&MeshGeometry>,
+    ) -> wgpu::RenderPipeline {
+        // the position, then the normal of the vertices of meshes
+        let attributes = &wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x3];
+        let (array_stride, attributes) = match mesh {
+            Some(_) => (std::mem::size_of::<[[f32; 3]; 2]>(), &attributes[..]),
+            None => (std::mem::size_of::<[f32; 3]>(), &attributes[..1]),
+        };
+        let vertex_buffer_layout = wgpu::VertexBufferLayout {
+            array_stride: array_stride as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes,
+        };
+
+        let (source, vertex_entry_point, fragment_entry_point) = match mesh {
+            Some(_) => (
+                format!("{}\n{}", shader, Self::MESH_SHADER),
+                Some("vs_mesh"),
+                Some("fs_mesh"),
+            ),
+            None => (shader.to_string(), None, None),
+        };
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Shader"),
+            source: wgpu::ShaderSource::Wgsl(diffusion.shader_source(&source).into()),
+        });
+
+        let bind_group_layouts: Vec<_> = std::iter::once(diffusion.bind_group_layout())
+            .chain(mesh.map(|mesh| &mesh.bind_group_layout))
+            .collect();
+        let render_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Render Pipeline Layout"),
+                bind_group_layouts: &bind_group_layouts,
+                push_constant_ranges: &[],
+            });
+
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Render Pipeline"),
+            layout: Some(&render_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: vertex_entry_point,
+                buffers: &[vertex_buffer_layout],
+                compilation_options: Default::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: fragment_entry_point,
+                targets: &[Some(format.into())],
+                compilation_options: Default::default(),
+            }),
+            primitive: wgpu::PrimitiveState {
+                // both sides of meshes are drawn, since they may be open or inconsistently
+                // oriented
+                cull_mode: mesh.is_none().then_some(wgpu::Face::Back),
+                ..Default::default()
+            },
+            depth_stencil: mesh.map(|_| wgpu::DepthStencilState {
+                format: MeshGeometry::DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil: Default::default(),
+                bias: Default::default(),
+            }),
+            multisample: Default::default(),
+            multiview: None,
+            cache: None,
+        })
+    }
+
+    /// Rebuild the pipeline from `shader`, a new source of `shader.wgsl`. On error the current
+    /// pipeline is kept and the error is returned.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn reload_shader(
+        &mut self,
+        device: &wgpu::Device,
+        diffusion: &Diffusion,
+        shader: &str,
+    ) -> Result<(), String> {
+        device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let render_pipeline = Self::create_render_pipeline(
+            device,
+            diffusion,
+            self.format,
+            shader,
+            self.mesh.as_ref(),
+        );
+        if let Some(error) = pollster::block_on(device.pop_error_scope()) {
+            return Err(error.to_string());
+        }
+        self.render_pipeline = render_pipeline;
+        Ok(())
+    }
+
+    /// Record a render pass drawing the field to `view`
+    pub(crate) fn render(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        diffusion: &Diffusion,
+    ) {
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Field Render Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(Self::BACKGROUND),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: self.mesh.as_ref().map(|mesh| {
+                wgpu::RenderPassDepthStencilAttachment {
+                    view: &mesh.depth_view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: wgpu::StoreOp::Discard,
+                    }),
+                    stencil_ops: None,
+                }
+            }),
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_bind_group(0, diffusion.current_bind_group(), &[]);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        match &self.mesh {
+            Some(mesh) => {
+                render_pass.set_bind_group(1, &mesh.bind_group, &[]);
+                render_pass
+                    .set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+                render_pass.draw_indexed(0..mesh.index_count, 0, 0..1);
+            }
+            None => render_pass.draw(0..6, 0..1),
+        }
+    }
+}
@@ -0,0 +1,99 @@
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+/// Record the commands of `draw` rendering to an offscreen texture, and save it as a PNG image
+/// with `metadata` as text chunks
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn save(
+    path: &Path,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    format: wgpu::TextureFormat,
+    width: u32,
+    height: u32,
+    metadata: &[(&str, String)],
+    draw: impl FnOnce(&mut wgpu::CommandEncoder, &wgpu::TextureView),
+) -> Result<(), String> {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Screenshot texture"),
+        size: wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+        view_formats: &[],
+    });
+    let view = texture.create_view(&Default::default());
+
+    let bytes_per_pixel = format
+        .block_copy_size(None)
+        .filter(|&size| size == 4)
+        .ok_or_else(|| format!("unsupported surface format {:?}", format))?;
+    let bytes_per_row =
+        (width * bytes_per_pixel).next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+    let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Screenshot buffer"),
+        size: (bytes_per_row * height) as u64,
+        usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("Screenshot Encoder"),
+    });
+    draw(&mut encoder, &view);
+    encoder.copy_texture_to_buffer(
+        texture.as_image_copy(),
+        wgpu::TexelCopyBufferInfo {
+            buffer: &buffer,
+            layout: wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(bytes_per_row),
+                rows_per_image: None,
+            },
+        },
+        texture.size(),
+    );
+    queue.submit(std::iter::once(encoder.finish()));
+
+    let slice = buffer.slice(..);
+    slice.map_async(wgpu::MapMode::Read, |result| result.unwrap());
+    device.poll(wgpu::Maintain::Wait);
+
+    let swap_red_blue = matches!(
+        format,
+        wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb
+    );
+    let mut pixels = Vec::with_capacity((width * height * 4) as usize);
+    {
+        let data = slice.get_mapped_range();
+        for row in data.chunks_exact(bytes_per_row as usize) {
+            for pixel in row[..(width * 4) as usize].chunks_exact(4) {
+                if swap_red_blue {
+                    pixels.extend_from_slice(&[pixel[2], pixel[1], pixel[0], 255]);
+                } else {
+                    pixels.extend_from_slice(&[pixel[0], pixel[1], pixel[2], 255]);
+                }
+            }
+        }
+    }
+    buffer.unmap();
+
+    let file = File::create(path).map_err(|e| e.to_string())?;
+    let mut encoder = png::Encoder::new(BufWriter::new(file), width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    for (keyword, text) in metadata {
+        encoder
+            .add_text_chunk(keyword.to_string(), text.clone())
+            .map_err(|e| e.to_string())?;
+    }
+    let mut writer = encoder.write_header().map_err(|e| e.to_string())?;
+    writer.write_image_data(&pixels).map_err(|e| e.to_string())
+}
@@ -3,15 +3,64 @@ struct Config {
     height: u32,
     size: u32,
     timestep: f32,
-    dA: f32,
-    dB: f32,
     f: f32,
     k: f32,
+    steps_per_dispatch: u32,
+    colormap: u32,
+    dx: f32,
+    dy: f32,
+    stochastic_noise: f32,
+    noise_mode: u32,
+    seed: u32,
+    weight_x: f32,
+    weight_y: f32,
+    weight_diagonal: f32,
+    weight_antidiagonal: f32,
+    weight_x2: f32,
+    weight_y2: f32,
+    radius: u32,
+    has_diffusivity_map: u32,
+    // largest absolute concentration accepted by `check_field`
+    max_value: f32,
+    // walls of the ADI solver, see `Boundary` in `config.rs`
+    boundary: u32,
+    // 3D volumes, see `VolumeWeights` in `tensor.rs`
+    depth: u32,
+    weight_face: f32,
+    weight_edge: f32,
+    weight_corner: f32,
+    // drawing of 3D volumes, see `View` and `Axis` in `config.rs`
+    view: u32,
+    slice_axis: u32,
+    slice: u32,
+    iso_level: f32,
+    // orbit camera of the isosurface view, in radians
+    camera_yaw: f32,
+    camera_pitch: f32,
+    has_mask: u32,
+    // advection by the velocity field, see `Advection` in `config.rs`, zero without a field
+    advection: u32,
+    velocity_strength: f32,
+    // growing domain, see `growth.rs`: dilution rate and number of columns covered by the domain
+    dilution: f32,
+    active_width: u32,
+    // arrangement of the cells, see `Lattice` in `config.rs`
+    lattice: u32,
+    // whether the field is simulated on the vertices of a mesh, see `mesh.rs`
+    has_mesh: u32,
+    // diffusion rate of each species, zero for the species missing from the system
+    diffusion: vec4<f32>,
+    // concentrations outside the walls of the ADI solver with Dirichlet boundaries, the
+    // background of each species
+    boundary_values: vec4<f32>,
+    // named parameters of the reactions, see `Reactions` in `system.rs`
+    parameters: array<vec4<f32>, 4>,
 };
 
 @group(0) @binding(0) var<uniform> config: Config;
-@group(0) @binding(1) var<storage, read> A: array<f32>;
-@group(0) @binding(2) var<storage, read> B: array<f32>;
+// `mask` is declared after the storage prelude, see `Diffusion::prelude`
+
+// `Cell`, `load` and `load_interpolated` are provided by the storage prelude (see `storage.rs`)
 
 // Vertex shader
 
@@ -28,22 +77,272 @@ fn vs_main(
     return out;
 }
 
+// Colormaps, see `Colormap` in `config.rs`
+
+const GRAYSCALE: u32 = 0;
+const VIRIDIS: u32 = 1;
+const MAGMA: u32 = 2;
+const INFERNO: u32 = 3;
+
+// Evaluate the polynomial with coefficients `c` (from degree 0 to 6) at `t`
+fn polynomial(t: f32, c: array<vec3<f32>, 7>) -> vec3<f32> {
+    return c[0] + t * (c[1] + t * (c[2] + t * (c[3] + t * (c[4] + t * (c[5] + t * c[6])))));
+}
+
+// Polynomial fits of the matplotlib colormaps, by Matt Zucker
+fn viridis(t: f32) -> vec3<f32> {
+    return polynomial(t, array<vec3<f32>, 7>(
+        vec3<f32>(0.2777273272234177, 0.005407344544966578, 0.3340998053353061),
+        vec3<f32>(0.1050930431085774, 1.404613529898575, 1.384590162594685),
+        vec3<f32>(-0.3308618287255563, 0.214847559468213, 0.09509516302823659),
+        vec3<f32>(-4.634230498983486, -5.799100973351585, -19.33244095627987),
+        vec3<f32>(6.228269936347081, 14.17993336680509, 56.69055260068105),
+        vec3<f32>(4.776384997670288, -13.74514537774601, -65.35303263337234),
+        vec3<f32>(-5.435455855934631, 4.645852612178535, 26.3124352495832),
+    ));
+}
+
+fn magma(t: f32) -> vec3<f32> {
+    return polynomial(t, array<vec3<f32>, 7>(
+        vec3<f32>(-0.002136485053939582, -0.000749655052795221, -0.005386127855323933),
+        vec3<f32>(0.2516605407371642, 0.6775232436837668, 2.494026599312351),
+        vec3<f32>(8.353717279216625, -3.577719514958484, 0.3144679030132573),
+        vec3<f32>(-27.66873308576866, 14.26473078096533, -13.64921318813922),
+        vec3<f32>(52.17613981234068, -27.94360607168351, 12.94416944238394),
+        vec3<f32>(-50.76852536473588, 29.04658282127291, 4.23415299384598),
+        vec3<f32>(18.65570506591883, -11.48977351997711, -5.601961508734096),
+    ));
+}
+
+fn inferno(t: f32) -> vec3<f32> {
+    return polynomial(t, array<vec3<f32>, 7>(
+        vec3<f32>(0.0002189403691192265, 0.001651004631001012, -0.01948089843709184),
+        vec3<f32>(0.1065134194856116, 0.5639564367884091, 3.932712388889277),
+        vec3<f32>(11.60249308247187, -3.972853965665698, -15.9423941062914),
+        vec3<f32>(-41.70399613139459, 17.43639888205313, 44.35414519872813),
+        vec3<f32>(77.162935699427, -33.40235894210092, -81.80730925738993),
+        vec3<f32>(-71.31942824499214, 32.62606426397723, 73.20951985803202),
+        vec3<f32>(25.13112622477341, -12.24266895238567, -23.07032500287172),
+    ));
+}
+
+fn colormap(t: f32) -> vec3<f32> {
+    switch config.colormap {
+        case VIRIDIS: {
+            return viridis(t);
+        }
+        case MAGMA: {
+            return magma(t);
+        }
+        case INFERNO: {
+            return inferno(t);
+        }
+        default: {
+            return vec3<f32>(t, t, t);
+        }
+    }
+}
+
+// Views of 3D volumes, see `View` and `Axis` in `config.rs`
+
+const SLICE: u32 = 0;
+const ISOSURFACE: u32 = 1;
+
+const AXIS_X: u32 = 0;
+const AXIS_Y: u32 = 1;
+
+// Vertical field of view of the isosurface view and of meshes, in radians
+const FIELD_OF_VIEW: f32 = 0.785398;
+// Distance of the camera to the centre of the volume or of the mesh, whose largest side is 1
+const CAMERA_DISTANCE: f32 = 2.0;
+const RAYMARCH_STEPS: u32 = 256;
+
+// Position and axes of the camera orbiting the origin
+struct Camera {
+    eye: vec3<f32>,
+    forward: vec3<f32>,
+    right: vec3<f32>,
+    up: vec3<f32>,
+};
+
+// Camera orbiting the origin at `CAMERA_DISTANCE`, oriented by `camera_yaw` and `camera_pitch`
+fn orbit_camera() -> Camera {
+    let yaw = config.camera_yaw;
+    let pitch = config.camera_pitch;
+    let forward = -vec3<f32>(cos(pitch) * sin(yaw), sin(pitch), cos(pitch) * cos(yaw));
+    let right = normalize(cross(forward, vec3<f32>(0.0, 1.0, 0.0)));
+    return Camera(-CAMERA_DISTANCE * forward, forward, right, cross(right, forward));
+}
+
+// Concentrations of the cell at (x, y, z) of a 3D volume, stored as a field of `height * depth`
+// rows
+fn load_volume(x: u32, y: u32, z: u32) -> Cell {
+    return load(x, y + z * config.height);
+}
+
+fn volume_size() -> vec3<f32> {
+    return vec3<f32>(f32(config.width), f32(config.height), f32(config.depth));
+}
+
+// Concentrations of the cell of the slice under the pixel at `pixel`
+fn slice(pixel: vec2<f32>) -> Cell {
+    // position in the slice, from 0 to 1
+    let uv = pixel / vec2<f32>(f32(config.width), f32(config.height));
+    let size = volume_size();
+    switch config.slice_axis {
+        case AXIS_X: {
+            let cell = vec2<u32>(uv * size.yz);
+            return load_volume(config.slice, cell.x, cell.y);
+        }
+        case AXIS_Y: {
+            let cell = vec2<u32>(uv * size.xz);
+            return load_volume(cell.x, config.slice, cell.y);
+        }
+        default: {
+            let cell = vec2<u32>(uv * size.xy);
+            return load_volume(cell.x, cell.y, config.slice);
+        }
+    }
+}
+
+// Concentration of B at the position `p` of the volume, in cells, interpolated trilinearly: in
+// the layers by `load_interpolated`, then between the layers
+fn sample_b(p: vec3<f32>) -> f32 {
+    let size = volume_size();
+    // the position is kept between the centres of the cells, so as not to interpolate between
+    // the last row of a layer and the first row of the next one
+    let q = clamp(p, vec3<f32>(0.5), size - 0.5);
+    let z0 = floor(q.z - 0.5);
+    let z1 = min(z0 + 1.0, size.z - 1.0);
+    let b0 = load_interpolated(vec2<f32>(q.x, q.y + z0 * size.y)).y;
+    let b1 = load_interpolated(vec2<f32>(q.x, q.y + z1 * size.y)).y;
+    return mix(b0, b1, q.z - 0.5 - z0);
+}
+
+// Color of the isosurface of B at `iso_level` under the pixel at `pixel`, seen from the orbit
+// camera, shaded by the angle between the surface and the ray
+fn isosurface(pixel: vec2<f32>) -> vec4<f32> {
+    let background = vec4<f32>(clamp(colormap(0.0), vec3<f32>(0.0), vec3<f32>(1.0)), 1.0);
+    let size = volume_size();
+    // the volume is centred on the origin, with its largest side of length 1
+    let extent = size / max(size.x, max(size.y, size.z));
+
+    let camera = orbit_camera();
+    let eye = camera.eye;
+    let aspect = f32(config.width) / f32(config.height);
+    let ndc = vec2<f32>(
+        2.0 * pixel.x / f32(config.width) - 1.0,
+        1.0 - 2.0 * pixel.y / f32(config.height),
+    );
+    let focal = 1.0 / tan(0.5 * FIELD_OF_VIEW);
+    let direction = normalize(
+        camera.forward * focal + camera.right * ndc.x * aspect + camera.up * ndc.y
+    );
+
+    // intersection of the ray with the box of the volume
+    let t0 = (-0.5 * extent - eye) / direction;
+    let t1 = (0.5 * extent - eye) / direction;
+    let t_near = max(max(min(t0.x, t1.x), min(t0.y, t1.y)), min(t0.z, t1.z));
+    let t_far = min(min(max(t0.x, t1.x), max(t0.y, t1.y)), max(t0.z, t1.z));
+    let t_start = max(t_near, 0.0);
+    if t_far <= t_start {
+        return background;
+    }
+
+    let dt = (t_far - t_start) / f32(RAYMARCH_STEPS);
+    var previous = 0.0;
+    for (var s = 0u; s <= RAYMARCH_STEPS; s++) {
+        let t = t_start + dt * f32(s);
+        let value = sample_b((eye + direction * t) / extent * size + 0.5 * size) - config.iso_level;
+        if value >= 0.0 {
+            // interpolate the crossing between the last two samples
+            var t_hit = t;
+            if s > 0 {
+                t_hit = t - dt * value / (value - previous);
+            }
+            let p = (eye + direction * t_hit) / extent * size + 0.5 * size;
+            let gradient = vec3<f32>(
+                sample_b(p + vec3<f32>(1.0, 0.0, 0.0)) - sample_b(p - vec3<f32>(1.0, 0.0, 0.0)),
+                sample_b(p + vec3<f32>(0.0, 1.0, 0.0)) - sample_b(p - vec3<f32>(0.0, 1.0, 0.0)),
+                sample_b(p + vec3<f32>(0.0, 0.0, 1.0)) - sample_b(p - vec3<f32>(0.0, 0.0, 1.0)),
+            );
+            // B increases towards the inside of the surface
+            var normal = -direction;
+            if length(gradient) > 1e-6 {
+                normal = -normalize(gradient);
+            }
+            let light = 0.2 + 0.8 * max(dot(normal, -direction), 0.0);
+            return vec4<f32>(clamp(colormap(light), vec3<f32>(0.0), vec3<f32>(1.0)), 1.0);
+        }
+        previous = value;
+    }
+    return background;
+}
+
 // Fragment shader
 
-@fragment
-fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    let width = config.width;
-    let height = config.height;
+// Color of a cell with the concentrations `ab`, from the difference of the first two species
+fn shade(ab: Cell) -> vec4<f32> {
+    let diff = ab.x - ab.y;
+
+    // map the difference of concentrations from [0.3, 0.7] to [0, 1]
+    let t = clamp((diff - 0.3) / 0.4, 0.0, 1.0);
+    return vec4<f32>(clamp(colormap(t), vec3<f32>(0.0), vec3<f32>(1.0)), 1.0);
+}
+
+// Lattices, see `Lattice` in `config.rs`
+const HEXAGONAL: u32 = 1;
+// Distance between the rows of the hexagonal lattice, in cells
+const ROW_SPACING: f32 = 0.8660254;
+
+// Cell of the hexagonal lattice under the pixel at `pixel`, the one whose centre is the nearest.
+// The image is `ROW_SPACING` times as high as the lattice has rows, and the odd rows are shifted
+// right by half a cell.
+fn hexagonal_cell(pixel: vec2<f32>) -> vec2<u32> {
+    let image_height = max(round(f32(config.height) * ROW_SPACING), 1.0);
+    // position in cells along x and in rows along y
+    let p = vec2<f32>(pixel.x, pixel.y * f32(config.height) / image_height);
+    var nearest = vec2<i32>(0);
+    var nearest_distance = 1e9;
+    // the nearest centre is on one of the two rows around the pixel
+    let first_row = i32(floor(p.y - 0.5));
+    for (var row = first_row; row <= first_row + 1; row++) {
+        let shift = select(0.0, 0.5, (row & 1) == 1);
+        let column = i32(floor(p.x - shift));
+        let centre = vec2<f32>(f32(column) + shift + 0.5, f32(row) + 0.5);
+        let d = (p - centre) * vec2<f32>(1.0, ROW_SPACING);
+        if dot(d, d) < nearest_distance {
+            nearest_distance = dot(d, d);
+            nearest = vec2<i32>(column, row);
+        }
+    }
+    let size = vec2<i32>(i32(config.width), i32(config.height));
+    return vec2<u32>((nearest + size) % size);
+}
 
-    let i = u32(in.clip_position.x) + u32(in.clip_position.y) * width;
-    let diff = A[i] - B[i];
+// Color of the walls and of the cells not yet covered by a growing domain, distinct from those
+// of the colormaps
+const WALL_COLOR = vec4<f32>(0.35, 0.2, 0.15, 1.0);
 
-    if diff > 0.7 {
-        return vec4<f32>(1.0, 1.0, 1.0, 1.0);
-    } else if diff < 0.3 {
-        return vec4<f32>(0.0, 0.0, 0.0, 1.0);
-    } else {
-        let c = (diff - 0.3) / 0.4;
-        return vec4<f32>(c, c, c, 1.0);
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    if config.depth > 1 {
+        if config.view == ISOSURFACE {
+            return isosurface(in.clip_position.xy);
+        }
+        return shade(slice(in.clip_position.xy));
+    }
+    var cell = vec2<u32>(in.clip_position.xy);
+    if config.lattice == HEXAGONAL {
+        cell = hexagonal_cell(in.clip_position.xy);
+    }
+    let x = cell.x;
+    let y = cell.y;
+    if x >= config.active_width || (config.has_mask != 0 && mask[x + y * config.width] == 0u) {
+        return WALL_COLOR;
+    }
+    if config.lattice == HEXAGONAL {
+        return shade(load(x, y));
     }
+    return shade(load_interpolated(in.clip_position.xy));
 }
@@ -0,0 +1,79 @@
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::log::log;
+
+/// Directory of shaders loaded instead of the built-in ones, whose files are watched for changes
+/// during development
+pub(crate) struct ShaderDir {
+    path: PathBuf,
+    /// Modification time of each file when it was last read
+    modified: [Option<SystemTime>; 2],
+    last_check: Option<Instant>,
+}
+
+/// Sources of the shaders whose file changed, `None` for the other ones
+pub(crate) struct ChangedShaders {
+    /// Source of `diffusion.wgsl`
+    pub(crate) diffusion: Option<String>,
+    /// Source of `shader.wgsl`
+    pub(crate) render: Option<String>,
+}
+
+impl ShaderDir {
+    const FILES: [&'static str; 2] = ["diffusion.wgsl", "shader.wgsl"];
+    /// Minimum time between two checks of the files
+    pub(crate) const CHECK_INTERVAL: Duration = Duration::from_millis(500);
+
+    pub(crate) fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            modified: [None; 2],
+            last_check: None,
+        }
+    }
+
+    /// Read the shaders whose file changed since the last call, all of them on the first call.
+    /// The files are checked at most every `CHECK_INTERVAL`, and missing ones are skipped, the
+    /// built-in shader being kept.
+    pub(crate) fn changed(&mut self) -> ChangedShaders {
+        if self
+            .last_check
+            .is_some_and(|last_check| last_check.elapsed() < Self::CHECK_INTERVAL)
+        {
+            return ChangedShaders {
+                diffusion: None,
+                render: None,
+            };
+        }
+        let is_first_check = self.last_check.is_none();
+        self.last_check = Some(Instant::now());
+        let [diffusion, render] = [0, 1].map(|i| self.read_if_changed(i, is_first_check));
+        ChangedShaders { diffusion, render }
+    }
+
+    fn read_if_changed(&mut self, i: usize, is_first_check: bool) -> Option<String> {
+        let path = self.path.join(Self::FILES[i]);
+        let modified = match std::fs::metadata(&path).and_then(|metadata| metadata.modified()) {
+            Ok(modified) => modified,
+            Err(e) => {
+                if is_first_check {
+                    log(&format!(
+                        "using the built-in {}, {} cannot be read: {}",
+                        Self::FILES[i],
+                        path.display(),
+                        e
+                    ));
+                }
+                return None;
+            }
+        };
+        if self.modified[i] == Some(modified) {
+            return None;
+        }
+        self.modified[i] = Some(modified);
+        std::fs::read_to_string(&path)
+            .inspect_err(|e| log(&format!("error while reading {}: {}", path.display(), e)))
+            .ok()
+    }
+}
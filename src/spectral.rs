@@ -0,0 +1,285 @@
+use wgpu::util::DeviceExt;
+
+use crate::tensor::Weights;
+
+/// Parameters of a butterfly pass of the FFT, see `spectral.wgsl`
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Zeroable, bytemuck::Pod)]
+struct FftPass {
+    half_size: u32,
+    axis: u32,
+    inverse: u32,
+}
+
+/// Semi-implicit solver, treating the diffusion implicitly in Fourier space and the reaction
+/// explicitly.
+///
+/// Each step runs the reaction into a complex spectrum buffer, transforms it with an FFT over the
+/// rows then the columns, divides each mode by its implicit decay factor, and transforms it back
+/// into the output copy of the field. The spectrum is ping-ponged between two buffers, and the
+/// parameters of the FFT passes are selected with dynamic offsets in a uniform buffer.
+pub(crate) struct Spectral {
+    reaction_pipeline: wgpu::ComputePipeline,
+    fft_pipeline: wgpu::ComputePipeline,
+    diffusion_pipeline: wgpu::ComputePipeline,
+    store_pipeline: wgpu::ComputePipeline,
+    /// Bind group `i` reads spectrum buffer `i` and writes the other one
+    bind_groups: [wgpu::BindGroup; 2],
+    decay_rates: wgpu::Buffer,
+    /// Distance between the parameters of consecutive FFT passes
+    pass_stride: u32,
+    width: u32,
+    height: u32,
+    size: u32,
+}
+
+impl Spectral {
+    pub(crate) const SHADER: &'static str = include_str!("spectral.wgsl");
+
+    /// Whether the solver supports a grid of the given size, the FFT needing powers of two
+    pub(crate) fn supports(width: u32, height: u32) -> bool {
+        width.is_power_of_two() && height.is_power_of_two() && width >= 2 && height >= 2
+    }
+
+    /// Create the solver. `shader` is the source of the diffusion shader with `SHADER`
+    /// appended, and the layouts are those of the groups 0 and 1 of the diffusion pipelines.
+    pub(crate) fn new(
+        device: &wgpu::Device,
+        shader: String,
+        diffusion_layout: &wgpu::BindGroupLayout,
+        aux_layout: &wgpu::BindGroupLayout,
+        width: u32,
+        height: u32,
+        weights: Weights,
+    ) -> Self {
+        let size = width * height;
+        let pass_stride = device.limits().min_uniform_buffer_offset_alignment;
+
+        let passes = Self::passes(width, height);
+        let mut pass_data = vec![0u8; passes.len() * pass_stride as usize];
+        for (pass, chunk) in passes
+            .iter()
+            .zip(pass_data.chunks_mut(pass_stride as usize))
+        {
+            chunk[..std::mem::size_of::<FftPass>()].copy_from_slice(bytemuck::bytes_of(pass));
+        }
+        let pass_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("FFT passes"),
+            contents: &pass_data,
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+
+        let create_spectrum = |label| {
+            device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some(label),
+                size: size as wgpu::BufferAddress * std::mem::size_of::<[f32; 2]>() as u64,
+                usage: wgpu::BufferUsages::STORAGE,
+                mapped_at_creation: false,
+            })
+        };
+        let spectra = [create_spectrum("Spectrum 0"), create_spectrum("Spectrum 1")];
+        let decay_rates = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Decay rates"),
+            contents: bytemuck::cast_slice(&Self::decay_rates(width, height, weights)),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let storage_entry = |binding, read_only| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Spectral BindGroupLayout"),
+            entries: &[
+                // FFT pass
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: true,
+                        min_binding_size: wgpu::BufferSize::new(
+                            std::mem::size_of::<FftPass>() as u64
+                        ),
+                    },
+                    count: None,
+                },
+                // spectrum in
+                storage_entry(1, true),
+                // spectrum out
+                storage_entry(2, false),
+                // decay rates
+                storage_entry(3, true),
+            ],
+        });
+        let create_bind_group = |label, input: &wgpu::Buffer, output: &wgpu::Buffer| {
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some(label),
+                layout: &bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                            buffer: &pass_buffer,
+                            offset: 0,
+                            size: wgpu::BufferSize::new(std::mem::size_of::<FftPass>() as u64),
+                        }),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: input.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: output.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 3,
+                        resource: decay_rates.as_entire_binding(),
+                    },
+                ],
+            })
+        };
+        let bind_groups = [
+            create_bind_group("Spectral BindGroup 0", &spectra[0], &spectra[1]),
+            create_bind_group("Spectral BindGroup 1", &spectra[1], &spectra[0]),
+        ];
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Spectral PipelineLayout"),
+            bind_group_layouts: &[diffusion_layout, aux_layout, &bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Spectral Shader"),
+            source: wgpu::ShaderSource::Wgsl(shader.into()),
+        });
+        let create_compute_pipeline = |label, entry_point| {
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some(label),
+                layout: Some(&pipeline_layout),
+                module: &shader,
+                entry_point: Some(entry_point),
+                compilation_options: Default::default(),
+                cache: None,
+            })
+        };
+
+        Self {
+            reaction_pipeline: create_compute_pipeline(
+                "Spectral reaction ComputePipeline",
+                "spectral_reaction",
+            ),
+            fft_pipeline: create_compute_pipeline("FFT ComputePipeline", "spectral_fft"),
+            diffusion_pipeline: create_compute_pipeline(
+                "Spectral diffusion ComputePipeline",
+                "spectral_diffusion",
+            ),
+            store_pipeline: create_compute_pipeline(
+                "Spectral store ComputePipeline",
+                "spectral_store",
+            ),
+            bind_groups,
+            decay_rates,
+            pass_stride,
+            width,
+            height,
+            size,
+        }
+    }
+
+    /// Butterfly passes of the forward FFT over the rows then the columns, followed by those of
+    /// the inverse FFT
+    fn passes(width: u32, height: u32) -> Vec<FftPass> {
+        let axis_passes = |n: u32, axis, inverse| {
+            (0..n.trailing_zeros()).map(move |p| FftPass {
+                half_size: 1 << p,
+                axis,
+                inverse,
+            })
+        };
+        [0, 1]
+            .into_iter()
+            .flat_map(|inverse| {
+                axis_passes(width, 0, inverse).chain(axis_passes(height, 1, inverse))
+            })
+            .collect()
+    }
+
+    /// Decay rate of each mode, in the order of the FFT output
+    fn decay_rates(width: u32, height: u32, weights: Weights) -> Vec<f32> {
+        let tau = std::f32::consts::TAU;
+        (0..height)
+            .flat_map(|y| {
+                (0..width).map(move |x| {
+                    weights.decay_rate(
+                        tau * x as f32 / width as f32,
+                        tau * y as f32 / height as f32,
+                    )
+                })
+            })
+            .collect()
+    }
+
+    /// Upload the decay rates of the modes after a change of the diffusion operator
+    pub(crate) fn set_weights(&self, queue: &wgpu::Queue, weights: Weights) {
+        queue.write_buffer(
+            &self.decay_rates,
+            0,
+            bytemuck::cast_slice(&Self::decay_rates(self.width, self.height, weights)),
+        );
+    }
+
+    /// Record one step, reading the field bound in `field_bind_group` and writing its other copy.
+    /// The bind group 1 of the diffusion pipelines must already be set.
+    pub(crate) fn step(
+        &self,
+        compute_pass: &mut wgpu::ComputePass,
+        field_bind_group: &wgpu::BindGroup,
+    ) {
+        let workgroups = self.size.div_ceil(64);
+        compute_pass.set_bind_group(0, field_bind_group, &[]);
+
+        // the reaction writes the spectrum buffer 0
+        compute_pass.set_pipeline(&self.reaction_pipeline);
+        compute_pass.set_bind_group(2, &self.bind_groups[1], &[0]);
+        compute_pass.dispatch_workgroups(workgroups, 1, 1);
+        let mut current = 0;
+
+        let log2_size = self.width.trailing_zeros() + self.height.trailing_zeros();
+        self.fft(compute_pass, 0..log2_size, &mut current);
+
+        compute_pass.set_pipeline(&self.diffusion_pipeline);
+        compute_pass.set_bind_group(2, &self.bind_groups[current], &[0]);
+        compute_pass.dispatch_workgroups(workgroups, 1, 1);
+        current = 1 - current;
+
+        self.fft(compute_pass, log2_size..2 * log2_size, &mut current);
+
+        compute_pass.set_pipeline(&self.store_pipeline);
+        compute_pass.set_bind_group(2, &self.bind_groups[current], &[0]);
+        compute_pass.dispatch_workgroups(workgroups, 1, 1);
+    }
+
+    /// Record the given butterfly passes, starting from the spectrum buffer `current`, which is
+    /// updated to the one holding the result
+    fn fft(
+        &self,
+        compute_pass: &mut wgpu::ComputePass,
+        passes: std::ops::Range<u32>,
+        current: &mut usize,
+    ) {
+        compute_pass.set_pipeline(&self.fft_pipeline);
+        for pass in passes {
+            compute_pass.set_bind_group(2, &self.bind_groups[*current], &[pass * self.pass_stride]);
+            compute_pass.dispatch_workgroups((self.size / 2).div_ceil(64), 1, 1);
+            *current = 1 - *current;
+        }
+    }
+}
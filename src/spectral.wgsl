@@ -0,0 +1,108 @@
+// Semi-implicit solver: the reaction is integrated explicitly, then the diffusion implicitly in
+// Fourier space. This file is appended to `diffusion.wgsl`, whose helpers it uses.
+//
+// A and B are packed in a single complex field `A + iB`, whose spectrum is computed with one FFT
+// since the spectra of A and B can be separated using their symmetry.
+
+// Butterfly pass of the FFT, see `Spectral` in `spectral.rs`
+struct FftPass {
+    // half the size of the sub-transforms combined by the pass
+    half_size: u32,
+    // 0 for the rows, 1 for the columns
+    axis: u32,
+    inverse: u32,
+};
+
+@group(2) @binding(0) var<uniform> fft_pass: FftPass;
+@group(2) @binding(1) var<storage, read> spectrum_in: array<vec2<f32>>;
+@group(2) @binding(2) var<storage, read_write> spectrum_out: array<vec2<f32>>;
+// Decay rate of each Fourier mode under the discrete diffusion operator, without the diffusion
+// coefficients
+@group(2) @binding(3) var<storage, read> decay_rates: array<f32>;
+
+const PI: f32 = 3.14159265;
+
+fn complex_mul(a: vec2<f32>, b: vec2<f32>) -> vec2<f32> {
+    return vec2<f32>(a.x * b.x - a.y * b.y, a.x * b.y + a.y * b.x);
+}
+
+fn conjugate(a: vec2<f32>) -> vec2<f32> {
+    return vec2<f32>(a.x, -a.y);
+}
+
+// Concentrations after the reaction and the stochastic noise, packed as `A + iB`
+@compute @workgroup_size(64)
+fn spectral_reaction(@builtin(global_invocation_id) global_invocation_id: vec3<u32>) {
+    let i = global_invocation_id.x;
+    if i >= config.size {
+        return;
+    }
+    let ab = load(i % config.width, i / config.width);
+    spectrum_out[i] = update(ab, vec2<f32>(0.0)) + noise(ab, i, step_counter);
+}
+
+// Radix-2 pass of a Stockham FFT along the rows or the columns, one butterfly per thread. After
+// the passes with `half_size` 1, 2, 4, ..., n / 2 the spectrum is in natural order.
+@compute @workgroup_size(64)
+fn spectral_fft(@builtin(global_invocation_id) global_invocation_id: vec3<u32>) {
+    let rows = fft_pass.axis == 0;
+    let n = select(config.height, config.width, rows);
+    let half = n / 2;
+    let i = global_invocation_id.x;
+    if i >= config.size / 2 {
+        return;
+    }
+    let j = i % half;
+    let line = i / half;
+    // index of the element `p` of the line
+    var start = line * config.width;
+    var stride = 1u;
+    if !rows {
+        start = line;
+        stride = config.width;
+    }
+
+    let half_size = fft_pass.half_size;
+    let k = j % half_size;
+    let sign = select(-1.0, 1.0, fft_pass.inverse != 0);
+    let angle = sign * PI * f32(k) / f32(half_size);
+    let twiddle = vec2<f32>(cos(angle), sin(angle));
+    let v0 = spectrum_in[start + j * stride];
+    let v1 = complex_mul(spectrum_in[start + (j + half) * stride], twiddle);
+    let p = (j - k) * 2 + k;
+    spectrum_out[start + p * stride] = v0 + v1;
+    spectrum_out[start + (p + half_size) * stride] = v0 - v1;
+}
+
+// Implicit Euler step of the diffusion of each Fourier mode
+@compute @workgroup_size(64)
+fn spectral_diffusion(@builtin(global_invocation_id) global_invocation_id: vec3<u32>) {
+    let i = global_invocation_id.x;
+    if i >= config.size {
+        return;
+    }
+    let x = i % config.width;
+    let y = i / config.width;
+    // mode of opposite wave vector
+    let opposite = (config.width - x) % config.width + (config.height - y) % config.height * config.width;
+    let z = spectrum_in[i];
+    let z_opposite = conjugate(spectrum_in[opposite]);
+    // the spectra of real fields are Hermitian
+    let a = 0.5 * (z + z_opposite);
+    let b_times_i = 0.5 * (z - z_opposite);
+
+    let decay = decay_rates[i] * config.timestep;
+    let a_out = a / (1.0 + config.diffusion.x * decay);
+    let b_times_i_out = b_times_i / (1.0 + config.diffusion.y * decay);
+    spectrum_out[i] = a_out + b_times_i_out;
+}
+
+// Unpack the inverse FFT, which is not normalized, into the output field
+@compute @workgroup_size(64)
+fn spectral_store(@builtin(global_invocation_id) global_invocation_id: vec3<u32>) {
+    let i = global_invocation_id.x;
+    if i >= config.size {
+        return;
+    }
+    store(i % config.width, i / config.width, spectrum_in[i] / f32(config.size));
+}
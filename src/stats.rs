@@ -0,0 +1,113 @@
+use wasm_bindgen::prelude::*;
+use web_time::{Duration, Instant};
+
+/// Performance and state of the simulation
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Stats {
+    pub fps: f32,
+    pub steps_per_second: f32,
+    pub step_number: u64,
+    /// Simulated time
+    pub time: f64,
+    pub width: u32,
+    pub height: u32,
+    pub steps_per_frame: u32,
+    pub timestep: f32,
+    /// Largest stable timestep of the explicit solver, from the diffusion and the Courant limit of
+    /// the upwind advection, ignoring the reactions. Infinite with the implicit solvers.
+    pub stable_timestep: f32,
+    pub diffusion_a: f32,
+    pub diffusion_b: f32,
+    pub feed: f32,
+    pub kill: f32,
+    /// Seed of the initial noise
+    pub seed: u64,
+    pub paused: bool,
+}
+
+impl Stats {
+    /// Text shown in the HUD
+    pub(crate) fn hud_text(&self) -> String {
+        format!(
+            "FPS: {:.1}\n\
+             Steps/s: {:.0}\n\
+             Step: {}\n\
+             Time: {:.1}\n\
+             Grid: {}x{}\n\
+             Steps/frame: {}\n\
+             Timestep: {:.4}\n\
+             Diffusion A: {:.4}\n\
+             Diffusion B: {:.4}\n\
+             Feed: {:.4}\n\
+             Kill: {:.4}\n\
+             Seed: {}\n\
+             {}",
+            self.fps,
+            self.steps_per_second,
+            self.step_number,
+            self.time,
+            self.width,
+            self.height,
+            self.steps_per_frame,
+            self.timestep,
+            self.diffusion_a,
+            self.diffusion_b,
+            self.feed,
+            self.kill,
+            self.seed,
+            if self.paused { "Paused" } else { "Running" },
+        )
+    }
+}
+
+/// Measure frames and steps per second over fixed intervals
+pub(crate) struct RateCounter {
+    start: Instant,
+    frames: u32,
+    steps: u64,
+    fps: f32,
+    steps_per_second: f32,
+}
+
+impl RateCounter {
+    const INTERVAL: Duration = Duration::from_millis(500);
+
+    pub(crate) fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            frames: 0,
+            steps: 0,
+            fps: 0.0,
+            steps_per_second: 0.0,
+        }
+    }
+
+    pub(crate) fn record_frame(&mut self, steps: u32) {
+        self.frames += 1;
+        self.steps += steps as u64;
+
+        let elapsed = self.start.elapsed();
+        if elapsed >= Self::INTERVAL {
+            let seconds = elapsed.as_secs_f32();
+            self.fps = self.frames as f32 / seconds;
+            self.steps_per_second = self.steps as f32 / seconds;
+            self.start = Instant::now();
+            self.frames = 0;
+            self.steps = 0;
+        }
+    }
+
+    /// Discard the current measurement, e.g. when the simulation is paused
+    pub(crate) fn reset(&mut self) {
+        *self = Self::new();
+    }
+
+    pub(crate) fn fps(&self) -> f32 {
+        self.fps
+    }
+
+    pub(crate) fn steps_per_second(&self) -> f32 {
+        self.steps_per_second
+    }
+}
@@ -0,0 +1,524 @@
+use wgpu::{util::DeviceExt, BufferUsages, TextureUsages};
+
+use crate::config::StorageLayout;
+use crate::log::log;
+
+/// Concentrations of the species on the GPU.
+///
+/// Every layout keeps two copies of the field: at each step one copy is read and the other one
+/// is written, then the roles are swapped.
+pub(crate) enum Storage {
+    /// The two copies of the field, each holding the species one after the other
+    Buffers {
+        buffers: [wgpu::Buffer; 2],
+        species: usize,
+        /// Number of cells, the stride between the species
+        cells: usize,
+    },
+    Texture {
+        textures: [wgpu::Texture; 2],
+        views: [wgpu::TextureView; 2],
+        species: usize,
+        /// Sampler filtering the texels when the field is drawn, for the formats that support it
+        sampler: Option<wgpu::Sampler>,
+    },
+}
+
+impl Storage {
+    /// WGSL interpolating the concentrations between the centres of the cells, for the layouts
+    /// whose texels are not filtered by the hardware
+    const INTERPOLATED_LOAD: &'static str = "
+// Concentrations at `p`, in cells, interpolated bilinearly between the centres of the cells
+fn load_interpolated(p: vec2<f32>) -> Cell {
+    let last = vec2<f32>(f32(config.width), f32(config.height * max(config.depth, 1u))) - 1.0;
+    let q = clamp(p - 0.5, vec2<f32>(0.0), last);
+    let c0 = vec2<u32>(q);
+    let c1 = vec2<u32>(min(q + 1.0, last));
+    let f = q - floor(q);
+    let top = mix(load(c0.x, c0.y), load(c1.x, c0.y), f.x);
+    let bottom = mix(load(c0.x, c1.y), load(c1.x, c1.y), f.x);
+    return mix(top, bottom, f.y);
+}
+";
+
+    /// Create the storage of a field of `width` × `height` cells, with the initial
+    /// concentrations of each species in `values`
+    pub(crate) fn new(
+        layout: StorageLayout,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        width: u32,
+        height: u32,
+        values: &[Vec<f32>],
+    ) -> Self {
+        match layout {
+            StorageLayout::Buffers => {
+                let contents = values.concat();
+                let buffers = [0, 1].map(|copy| {
+                    device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                        label: Some(&format!("Buffer {}", copy)),
+                        contents: bytemuck::cast_slice(&contents),
+                        usage: BufferUsages::STORAGE
+                            | BufferUsages::COPY_SRC
+                            | BufferUsages::COPY_DST,
+                    })
+                });
+                Self::Buffers {
+                    buffers,
+                    species: values.len(),
+                    cells: values[0].len(),
+                }
+            }
+            StorageLayout::Texture | StorageLayout::HalfTexture => {
+                let format = Self::texture_format(layout, values.len());
+                let texels = Self::interleave(format, values);
+                let textures = [0, 1].map(|copy| {
+                    device.create_texture_with_data(
+                        queue,
+                        &wgpu::TextureDescriptor {
+                            label: Some(&format!("Texture {}", copy)),
+                            size: wgpu::Extent3d {
+                                width,
+                                height,
+                                depth_or_array_layers: 1,
+                            },
+                            mip_level_count: 1,
+                            sample_count: 1,
+                            dimension: wgpu::TextureDimension::D2,
+                            format,
+                            usage: TextureUsages::TEXTURE_BINDING
+                                | TextureUsages::STORAGE_BINDING
+                                | TextureUsages::COPY_SRC
+                                | TextureUsages::COPY_DST,
+                            view_formats: &[],
+                        },
+                        wgpu::util::TextureDataOrder::LayerMajor,
+                        &texels,
+                    )
+                });
+                let sampler = Self::is_filterable(format).then(|| {
+                    device.create_sampler(&wgpu::SamplerDescriptor {
+                        label: Some("Field sampler"),
+                        mag_filter: wgpu::FilterMode::Linear,
+                        min_filter: wgpu::FilterMode::Linear,
+                        ..Default::default()
+                    })
+                });
+                Self::Texture {
+                    views: [0, 1].map(|copy| textures[copy].create_view(&Default::default())),
+                    textures,
+                    species: values.len(),
+                    sampler,
+                }
+            }
+        }
+    }
+
+    /// Return `layout` if the adapter supports it for `species` species, otherwise fall back to
+    /// storage buffers
+    pub(crate) fn supported_layout(
+        layout: StorageLayout,
+        species: usize,
+        adapter: &wgpu::Adapter,
+    ) -> StorageLayout {
+        let format = Self::texture_format(layout, species);
+        if layout == StorageLayout::Buffers || Self::supports_storage_texture(format, adapter) {
+            return layout;
+        }
+        log(&format!(
+            "{:?} storage textures are not supported, falling back to buffers",
+            format
+        ));
+        StorageLayout::Buffers
+    }
+
+    /// Whether textures of `format` can be written by the compute shaders on `adapter`
+    fn supports_storage_texture(format: wgpu::TextureFormat, adapter: &wgpu::Adapter) -> bool {
+        // the usages beyond those of WebGPU need a device feature, see `device::request_device`
+        let features = if adapter
+            .features()
+            .contains(wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES)
+        {
+            adapter.get_texture_format_features(format)
+        } else {
+            format.guaranteed_format_features(adapter.features())
+        };
+        features
+            .allowed_usages
+            .contains(TextureUsages::STORAGE_BINDING)
+    }
+
+    /// Format of the textures of `layout`, with one channel per species
+    fn texture_format(layout: StorageLayout, species: usize) -> wgpu::TextureFormat {
+        match (layout, species <= 2) {
+            (StorageLayout::HalfTexture, true) => wgpu::TextureFormat::Rg16Float,
+            (StorageLayout::HalfTexture, false) => wgpu::TextureFormat::Rgba16Float,
+            (_, true) => wgpu::TextureFormat::Rg32Float,
+            (_, false) => wgpu::TextureFormat::Rgba32Float,
+        }
+    }
+
+    /// Whether the texels of `format` are filtered when sampled, which is not the case of the
+    /// 32-bit floats without a device feature
+    fn is_filterable(format: wgpu::TextureFormat) -> bool {
+        matches!(
+            format,
+            wgpu::TextureFormat::Rg16Float | wgpu::TextureFormat::Rgba16Float
+        )
+    }
+
+    fn species(&self) -> usize {
+        match self {
+            Self::Buffers { species, .. } | Self::Texture { species, .. } => *species,
+        }
+    }
+
+    /// Bytes of the texels of `format`, with the species packed in their channels and the unused
+    /// channels being zero
+    fn interleave(format: wgpu::TextureFormat, values: &[Vec<f32>]) -> Vec<u8> {
+        let channels: usize = format.components().into();
+        let size = values[0].len();
+        let mut texels = vec![0.0; size * channels];
+        for (channel, values) in values.iter().enumerate() {
+            for (i, &value) in values.iter().enumerate() {
+                texels[i * channels + channel] = value;
+            }
+        }
+        match format {
+            wgpu::TextureFormat::Rg16Float | wgpu::TextureFormat::Rgba16Float => {
+                let texels: Vec<u16> = texels.into_iter().map(f16_bits).collect();
+                bytemuck::cast_slice(&texels).to_vec()
+            }
+            _ => bytemuck::cast_slice(&texels).to_vec(),
+        }
+    }
+
+    /// Prepend to `shader` the WGSL declarations of the storage bindings, together with the
+    /// `Cell` type holding the concentrations of a cell and the `load` and `store` helpers used
+    /// to access them
+    pub(crate) fn shader_source(&self, shader: &str) -> String {
+        let species = self.species();
+        let components = &["x", "y", "z", "w"][..species];
+        let mut prelude = format!(
+            "// Number of species, and their concentrations in a cell, see `system.rs`\n\
+             const SPECIES: u32 = {species};\n\
+             alias Cell = vec{species}<f32>;\n\
+             \n\
+             // Concentrations of the first `SPECIES` species in `value`\n\
+             fn concentrations(value: vec4<f32>) -> Cell {{\n    return value.{};\n}}\n\n",
+            components.concat()
+        );
+
+        match self {
+            Self::Buffers { cells, .. } => {
+                // index of the concentration of the species `s` in the cell `i`
+                let indices: Vec<_> = (0..species)
+                    .map(|s| match s {
+                        0 => "i".to_string(),
+                        1 => "i + CELLS".to_string(),
+                        s => format!("i + {}u * CELLS", s),
+                    })
+                    .collect();
+                prelude += &format!(
+                    "// The species one after the other, in the order of `System::NAMES`\n\
+                     const CELLS: u32 = {cells}u;\n\
+                     @group(0) @binding(1) var<storage, read> field: array<f32>;\n\
+                     @group(0) @binding(2) var<storage, read_write> field_out: array<f32>;\n"
+                );
+                let loads: Vec<_> = indices.iter().map(|i| format!("field[{}]", i)).collect();
+                prelude += &format!(
+                    "\nfn load(x: u32, y: u32) -> Cell {{\n    \
+                     let i = x + y * config.width;\n    \
+                     return Cell({});\n}}\n",
+                    loads.join(", ")
+                );
+                prelude += "\nfn store(x: u32, y: u32, value: Cell) {\n    \
+                            let i = x + y * config.width;\n";
+                for (index, component) in indices.iter().zip(components) {
+                    prelude += &format!("    field_out[{}] = value.{};\n", index, component);
+                }
+                prelude += "}\n";
+                prelude += Self::INTERPOLATED_LOAD;
+            }
+            Self::Texture {
+                textures, sampler, ..
+            } => {
+                let format = match textures[0].format() {
+                    wgpu::TextureFormat::Rg32Float => "rg32float",
+                    wgpu::TextureFormat::Rgba32Float => "rgba32float",
+                    wgpu::TextureFormat::Rg16Float => "rg16float",
+                    _ => "rgba16float",
+                };
+                let padding = ", 0.0".repeat(4 - species);
+                prelude += &format!(
+                    "// One species per channel, in the order of `System::NAMES`\n\
+                     @group(0) @binding(1) var field: texture_2d<f32>;\n\
+                     @group(0) @binding(2) var field_out: texture_storage_2d<{format}, write>;\n\
+                     \n\
+                     fn load(x: u32, y: u32) -> Cell {{\n    \
+                     return concentrations(textureLoad(field, vec2<u32>(x, y), 0));\n}}\n\
+                     \n\
+                     fn store(x: u32, y: u32, value: Cell) {{\n    \
+                     textureStore(field_out, vec2<u32>(x, y), vec4<f32>(value{padding}));\n}}\n"
+                );
+                match sampler {
+                    Some(_) => {
+                        prelude += "\n@group(0) @binding(3) var field_sampler: sampler;\n\n\
+                                    // Concentrations at `p`, in cells, filtered by the hardware\n\
+                                    fn load_interpolated(p: vec2<f32>) -> Cell {\n    \
+                                    let uv = p / vec2<f32>(textureDimensions(field));\n    \
+                                    return concentrations(\
+                                    textureSampleLevel(field, field_sampler, uv, 0.0));\n}\n";
+                    }
+                    None => prelude += Self::INTERPOLATED_LOAD,
+                }
+            }
+        }
+        format!("{}\n{}", prelude, shader)
+    }
+
+    /// Bind group layout entries of the storage, starting from binding 1
+    pub(crate) fn layout_entries(&self) -> Vec<wgpu::BindGroupLayoutEntry> {
+        let storage_buffer = |binding, read_only| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: if read_only {
+                wgpu::ShaderStages::all()
+            } else {
+                wgpu::ShaderStages::COMPUTE
+            },
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+
+        match self {
+            // the input, then the output
+            Self::Buffers { .. } => vec![storage_buffer(1, true), storage_buffer(2, false)],
+            Self::Texture {
+                textures, sampler, ..
+            } => {
+                let format = textures[0].format();
+                let mut entries = vec![
+                    // input
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::all(),
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float {
+                                filterable: sampler.is_some(),
+                            },
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    // output
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::StorageTexture {
+                            access: wgpu::StorageTextureAccess::WriteOnly,
+                            format,
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                        },
+                        count: None,
+                    },
+                ];
+                if sampler.is_some() {
+                    entries.push(wgpu::BindGroupLayoutEntry {
+                        binding: 3,
+                        visibility: wgpu::ShaderStages::all(),
+                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                        count: None,
+                    });
+                }
+                entries
+            }
+        }
+    }
+
+    /// First binding of group 0 after those of the storage
+    pub(crate) fn next_binding(&self) -> u32 {
+        1 + self.layout_entries().len() as u32
+    }
+
+    /// Bind group entries of the storage, starting from binding 1.
+    ///
+    /// If `swapped` is false the first copy of the field is the input and the second one is the
+    /// output, otherwise it is the other way around.
+    pub(crate) fn bind_group_entries(&self, swapped: bool) -> Vec<wgpu::BindGroupEntry<'_>> {
+        let (input, output) = if swapped { (1, 0) } else { (0, 1) };
+        match self {
+            Self::Buffers { buffers, .. } => vec![
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: buffers[input].as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: buffers[output].as_entire_binding(),
+                },
+            ],
+            Self::Texture { views, sampler, .. } => {
+                let mut entries = vec![
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::TextureView(&views[input]),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: wgpu::BindingResource::TextureView(&views[output]),
+                    },
+                ];
+                if let Some(sampler) = sampler {
+                    entries.push(wgpu::BindGroupEntry {
+                        binding: 3,
+                        resource: wgpu::BindingResource::Sampler(sampler),
+                    });
+                }
+                entries
+            }
+        }
+    }
+
+    /// Overwrite both copies of the field with the concentrations of each species in `values`
+    pub(crate) fn write(&self, queue: &wgpu::Queue, values: &[Vec<f32>]) {
+        match self {
+            Self::Buffers { buffers, .. } => {
+                let contents = values.concat();
+                for buffer in buffers {
+                    queue.write_buffer(buffer, 0, bytemuck::cast_slice(&contents));
+                }
+            }
+            Self::Texture { textures, .. } => {
+                let texels = Self::interleave(textures[0].format(), values);
+                for texture in textures {
+                    let texel_size = texture.format().block_copy_size(None).unwrap();
+                    queue.write_texture(
+                        texture.as_image_copy(),
+                        &texels,
+                        wgpu::TexelCopyBufferLayout {
+                            offset: 0,
+                            bytes_per_row: Some(texture.width() * texel_size),
+                            rows_per_image: None,
+                        },
+                        texture.size(),
+                    );
+                }
+            }
+        }
+    }
+
+    /// Record the copy of one copy of the field to one copy of `destination`, which must have
+    /// the same layout and size. The second copy is selected when `source_copy` or
+    /// `destination_copy` is true.
+    pub(crate) fn copy_field(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        source_copy: bool,
+        destination: &Storage,
+        destination_copy: bool,
+    ) {
+        let source_copy = source_copy as usize;
+        let destination_copy = destination_copy as usize;
+        match (self, destination) {
+            (
+                Self::Buffers { buffers, .. },
+                Self::Buffers {
+                    buffers: destination_buffers,
+                    ..
+                },
+            ) => {
+                let buffer = &buffers[source_copy];
+                encoder.copy_buffer_to_buffer(
+                    buffer,
+                    0,
+                    &destination_buffers[destination_copy],
+                    0,
+                    buffer.size(),
+                );
+            }
+            (
+                Self::Texture { textures, .. },
+                Self::Texture {
+                    textures: destination_textures,
+                    ..
+                },
+            ) => {
+                let texture = &textures[source_copy];
+                encoder.copy_texture_to_texture(
+                    texture.as_image_copy(),
+                    destination_textures[destination_copy].as_image_copy(),
+                    texture.size(),
+                );
+            }
+            _ => unreachable!("copy between storages of different layouts"),
+        }
+    }
+}
+
+/// Bits of the half precision float nearest to `value`, rounding ties to even
+fn f16_bits(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x7f_ffff;
+    if exponent == 0xff {
+        // infinity or NaN
+        return sign | 0x7c00 | if mantissa != 0 { 0x200 } else { 0 };
+    }
+
+    // round the mantissa, with its implicit leading bit, shifted right by `shift` bits
+    let round = |mantissa: u32, shift: u32| {
+        let half = mantissa >> shift;
+        let remainder = mantissa & ((1 << shift) - 1);
+        let halfway = 1 << (shift - 1);
+        half + (remainder > halfway || (remainder == halfway && half & 1 == 1)) as u32
+    };
+    let exponent = exponent - 127 + 15;
+    if exponent >= 0x1f {
+        // too large, rounded to infinity
+        sign | 0x7c00
+    } else if exponent <= 0 {
+        // subnormal, or too small and rounded to zero
+        if exponent < -10 {
+            return sign;
+        }
+        sign | round(mantissa | 0x80_0000, (14 - exponent) as u32) as u16
+    } else {
+        // a carry out of the mantissa increments the exponent, up to infinity
+        sign | round(((exponent as u32) << 23) | mantissa, 13) as u16
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::f16_bits;
+
+    #[test]
+    fn f16_bits_rounds_to_nearest_even() {
+        assert_eq!(f16_bits(0.0), 0x0000);
+        assert_eq!(f16_bits(-0.0), 0x8000);
+        assert_eq!(f16_bits(1.0), 0x3c00);
+        assert_eq!(f16_bits(-2.0), 0xc000);
+        assert_eq!(f16_bits(0.25), 0x3400);
+        // halfway between two halves, rounded to the even one
+        assert_eq!(f16_bits(1.0 + 2f32.powi(-11)), 0x3c00);
+        assert_eq!(f16_bits(1.0 + 3.0 * 2f32.powi(-11)), 0x3c02);
+        // largest half, and the carry of the rounding up to infinity
+        assert_eq!(f16_bits(65504.0), 0x7bff);
+        assert_eq!(f16_bits(65520.0), 0x7c00);
+        assert_eq!(f16_bits(f32::INFINITY), 0x7c00);
+        assert_eq!(f16_bits(f32::NEG_INFINITY), 0xfc00);
+        assert_eq!(f16_bits(f32::NAN) & 0x7e00, 0x7e00);
+        // subnormals
+        assert_eq!(f16_bits(2f32.powi(-24)), 0x0001);
+        assert_eq!(f16_bits(2f32.powi(-25)), 0x0000);
+        assert_eq!(f16_bits(1.5 * 2f32.powi(-25)), 0x0001);
+        assert_eq!(f16_bits(2f32.powi(-15)), 0x0200);
+        assert_eq!(f16_bits(1e-10), 0x0000);
+    }
+}
@@ -0,0 +1,259 @@
+use crate::config::{Config, Model};
+use crate::expression::{Expression, Function, Variables};
+use crate::log::log;
+
+/// Chemical species of a reaction system
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Species {
+    pub(crate) diffusion: f32,
+    /// Initial concentration outside the seeded square
+    pub(crate) background: f32,
+    /// Initial concentration inside the seeded square
+    pub(crate) seeded: f32,
+}
+
+/// Reaction system simulated on the grid: its species, in the order of the components of `Cell`
+/// in the shaders, and the kinetics of `model` found in `reactions.wgsl` or given by `reactions`.
+pub(crate) struct System {
+    pub(crate) model: Model,
+    pub(crate) species: Vec<Species>,
+    /// Reaction terms given as expressions, replacing the kinetics of `model`
+    pub(crate) reactions: Option<Reactions>,
+}
+
+impl System {
+    /// Names of the species, as used in the parameters and in the shaders. Systems have at most
+    /// four species, the components of the `Cell` vector on which the shaders do their arithmetic
+    /// and the channels of the storage textures.
+    pub(crate) const NAMES: [&'static str; 4] = ["A", "B", "C", "D"];
+    const REACTIONS: &'static str = include_str!("reactions.wgsl");
+
+    pub(crate) fn new(config: &Config) -> Self {
+        let diffusion: Vec<_> = [config.diffusion_a, config.diffusion_b]
+            .into_iter()
+            .chain(config.diffusion_rates.iter().copied())
+            .collect();
+        let reactions = (!config.reactions.is_empty())
+            .then(|| Reactions::parse(&config.reactions, &config.parameters))
+            .and_then(|reactions| {
+                reactions
+                    .inspect_err(|e| {
+                        log(&format!(
+                            "invalid reactions, falling back to the {:?} model: {}",
+                            config.model, e
+                        ))
+                    })
+                    .ok()
+            });
+        let species = reactions
+            .as_ref()
+            .map_or_else(|| config.model.species(), Reactions::species)
+            .into_iter()
+            .enumerate()
+            .map(|(i, species)| Species {
+                diffusion: diffusion.get(i).copied().unwrap_or(species.diffusion),
+                ..species
+            })
+            .collect();
+        Self {
+            model: config.model,
+            species,
+            reactions,
+        }
+    }
+
+    /// Number of species simulated with `config`, without logging invalid reactions
+    pub(crate) fn species_count(config: &Config) -> usize {
+        Reactions::parse(&config.reactions, &config.parameters).map_or_else(
+            |_| config.model.species().len(),
+            |reactions| reactions.terms.len(),
+        )
+    }
+
+    /// Named parameters of the reactions simulated with `config` and their initial values, none
+    /// for the models, without logging invalid reactions
+    pub(crate) fn parameters(config: &Config) -> Vec<(String, f32)> {
+        Reactions::parse(&config.reactions, &config.parameters)
+            .map_or_else(|_| Vec::new(), |reactions| reactions.parameters)
+    }
+
+    /// Name of the model, or "Custom" for reactions given as expressions
+    pub(crate) fn name(&self) -> String {
+        match self.reactions {
+            Some(_) => "Custom".to_string(),
+            None => format!("{:?}", self.model),
+        }
+    }
+
+    /// Source of the reactions, defining the `rate` of change of the concentrations used by
+    /// `diffusion.wgsl`
+    pub(crate) fn shader_source(&self) -> String {
+        if let Some(reactions) = &self.reactions {
+            return format!("{}\n{}", reactions.shader_source(), Self::REACTIONS);
+        }
+        let function = match self.model {
+            Model::GrayScott => "gray_scott",
+            Model::GrayScottInhibitor => "gray_scott_inhibitor",
+            Model::LotkaVolterra => "lotka_volterra",
+        };
+        format!(
+            "// Rate of change of the concentrations `c`, given their diffusion\n\
+             fn rate(c: Cell, diffusion: Cell) -> Cell {{\n    return {}(c, diffusion);\n}}\n\n{}",
+            function,
+            Self::REACTIONS
+        )
+    }
+}
+
+impl Model {
+    /// Species of the model, with their default diffusion rates and initial concentrations
+    pub(crate) fn species(self) -> Vec<Species> {
+        fn species(diffusion: f32, background: f32, seeded: f32) -> Species {
+            Species {
+                diffusion,
+                background,
+                seeded,
+            }
+        }
+        match self {
+            Model::GrayScott => vec![species(0.5, 1.0, 0.0), species(0.25, 0.0, 1.0)],
+            Model::GrayScottInhibitor => vec![
+                species(0.5, 1.0, 0.0),
+                species(0.25, 0.0, 1.0),
+                species(1.0, 0.0, 0.0),
+            ],
+            // close to the coexistence state, which the seeded square perturbs
+            Model::LotkaVolterra => vec![
+                species(0.5, 0.25, 0.0),
+                species(0.25, 0.25, 1.0),
+                species(0.5, 0.25, 0.0),
+                species(0.25, 0.25, 0.0),
+            ],
+        }
+    }
+}
+
+/// Reaction terms of each species, written as `da = -a*b*b + f*(1 - a)` for the species A, with
+/// the concentrations named after the species in lower case and named parameters
+pub(crate) struct Reactions {
+    /// The reactions as written, one per species
+    pub(crate) sources: Vec<String>,
+    terms: Vec<Expression>,
+    /// Names of the parameters and their initial values
+    pub(crate) parameters: Vec<(String, f32)>,
+}
+
+impl Reactions {
+    /// Number of parameters that fit in `config.parameters` in the shaders
+    pub(crate) const MAX_PARAMETERS: usize = 16;
+
+    /// Parse one reaction per species, in the order of `System::NAMES`
+    pub(crate) fn parse(
+        reactions: &[String],
+        parameters: &[(String, f32)],
+    ) -> Result<Self, String> {
+        if !(2..=System::NAMES.len()).contains(&reactions.len()) {
+            return Err(format!(
+                "between 2 and {} reactions are needed, found {}",
+                System::NAMES.len(),
+                reactions.len()
+            ));
+        }
+        if parameters.len() > Self::MAX_PARAMETERS {
+            return Err(format!(
+                "at most {} parameters are supported, found {}",
+                Self::MAX_PARAMETERS,
+                parameters.len()
+            ));
+        }
+
+        let species: Vec<_> = System::NAMES[..reactions.len()]
+            .iter()
+            .map(|name| name.to_lowercase())
+            .collect();
+        let names: Vec<_> = parameters.iter().map(|(name, _)| name.clone()).collect();
+        for (i, name) in names.iter().enumerate() {
+            let is_identifier = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+                && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+            if !is_identifier {
+                return Err(format!("invalid parameter name \"{}\"", name));
+            }
+            if species.contains(name) || Function::from_name(name).is_some() {
+                return Err(format!(
+                    "the parameter \"{}\" has the name of a species or a function",
+                    name
+                ));
+            }
+            if names[..i].contains(name) {
+                return Err(format!("the parameter \"{}\" is given twice", name));
+            }
+        }
+
+        let variables = Variables {
+            species: &species,
+            parameters: &names,
+        };
+        let terms = reactions
+            .iter()
+            .zip(&species)
+            .map(|(reaction, species)| {
+                let expected = format!("d{}", species);
+                let term = match reaction.split_once('=') {
+                    Some((derivative, term)) if derivative.trim() == expected => term,
+                    _ => {
+                        return Err(format!(
+                            "expected \"{} = ...\", found \"{}\"",
+                            expected, reaction
+                        ))
+                    }
+                };
+                Expression::parse(term, &variables).map_err(|e| format!("{}: {}", reaction, e))
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self {
+            sources: reactions.to_vec(),
+            terms,
+            parameters: parameters.to_vec(),
+        })
+    }
+
+    /// Index of the parameter called `name`
+    pub(crate) fn parameter_index(&self, name: &str) -> Option<usize> {
+        self.parameters.iter().position(|(n, _)| n == name)
+    }
+
+    /// Species of the reactions, starting like the Gray-Scott model with A everywhere except
+    /// in the seeded square, filled with B
+    fn species(&self) -> Vec<Species> {
+        (0..self.terms.len())
+            .map(|i| Species {
+                diffusion: 0.5,
+                background: if i == 0 { 1.0 } else { 0.0 },
+                seeded: if i == 1 { 1.0 } else { 0.0 },
+            })
+            .collect()
+    }
+
+    /// Source of the `rate` function computing the reaction terms
+    fn shader_source(&self) -> String {
+        let terms: Vec<_> = self
+            .sources
+            .iter()
+            .zip(&self.terms)
+            .map(|(source, term)| {
+                format!(
+                    "        // {}\n        {},\n",
+                    source.replace('\n', " "),
+                    term.to_wgsl()
+                )
+            })
+            .collect();
+        format!(
+            "// Rate of change of the concentrations `c`, given their diffusion\n\
+             fn rate(c: Cell, diffusion: Cell) -> Cell {{\n    \
+             return diffusion + Cell(\n{}    );\n}}\n",
+            terms.concat()
+        )
+    }
+}
@@ -0,0 +1,58 @@
+use crate::config::Config;
+use crate::diffusion::Diffusion;
+
+/// Point at which the simulation should stop, either a number of steps or a simulated time
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Target {
+    max_steps: Option<u64>,
+    max_time: Option<f64>,
+}
+
+impl Target {
+    pub(crate) fn new(config: &Config) -> Self {
+        Self {
+            max_steps: config.max_steps,
+            max_time: config.max_time,
+        }
+    }
+
+    pub(crate) fn is_set(&self) -> bool {
+        self.max_steps.is_some() || self.max_time.is_some()
+    }
+
+    pub(crate) fn set_max_steps(&mut self, max_steps: Option<u64>) {
+        self.max_steps = max_steps;
+    }
+
+    pub(crate) fn set_max_time(&mut self, max_time: Option<f64>) {
+        self.max_time = max_time;
+    }
+
+    /// Number of steps left before reaching the target, `None` if it will never be reached
+    pub(crate) fn remaining_steps(&self, diffusion: &Diffusion) -> Option<u64> {
+        let by_steps = self
+            .max_steps
+            .map(|max_steps| max_steps.saturating_sub(diffusion.step_number()));
+        let timestep = diffusion.timestep() as f64;
+        let by_time = self
+            .max_time
+            .filter(|_| timestep > 0.0)
+            .map(|max_time| ((max_time - diffusion.time()) / timestep).ceil().max(0.0) as u64);
+        match (by_steps, by_time) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        }
+    }
+
+    /// Limit `steps` so that the target is not overshot
+    pub(crate) fn clamp_steps(&self, diffusion: &Diffusion, steps: u32) -> u32 {
+        match self.remaining_steps(diffusion) {
+            Some(remaining) => steps.min(remaining.try_into().unwrap_or(u32::MAX)),
+            None => steps,
+        }
+    }
+
+    pub(crate) fn is_reached(&self, diffusion: &Diffusion) -> bool {
+        self.remaining_steps(diffusion) == Some(0)
+    }
+}
@@ -0,0 +1,295 @@
+use crate::config::{Config, Stencil, VolumeStencil};
+
+/// Weights of the neighbours of a cell in the discrete diffusion operator, see `diffusion.wgsl`
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Weights {
+    /// Neighbours at `(±1, 0)`
+    pub(crate) x: f32,
+    /// Neighbours at `(0, ±1)`
+    pub(crate) y: f32,
+    /// Neighbours at `(1, 1)` and `(-1, -1)`
+    pub(crate) diagonal: f32,
+    /// Neighbours at `(1, -1)` and `(-1, 1)`
+    pub(crate) antidiagonal: f32,
+    /// Neighbours at `(±2, 0)`
+    pub(crate) x2: f32,
+    /// Neighbours at `(0, ±2)`
+    pub(crate) y2: f32,
+}
+
+impl Weights {
+    /// Number of samples of the wave numbers along each axis in `spectral_radius`
+    const SAMPLES: u32 = 32;
+
+    /// Decay rate of the Fourier mode of wave vector `(kx, ky)` under the discrete operator.
+    ///
+    /// The mode is an eigenvector of the operator with the eigenvalue
+    /// `sum(w * (cos(k . offset) - 1))` over the neighbours, whose opposite is returned.
+    pub(crate) fn decay_rate(&self, kx: f32, ky: f32) -> f32 {
+        -2.0 * (self.x * (kx.cos() - 1.0)
+            + self.y * (ky.cos() - 1.0)
+            + self.diagonal * ((kx + ky).cos() - 1.0)
+            + self.antidiagonal * ((kx - ky).cos() - 1.0)
+            + self.x2 * ((2.0 * kx).cos() - 1.0)
+            + self.y2 * ((2.0 * ky).cos() - 1.0))
+    }
+
+    /// Weights of the 7-point operator of the hexagonal lattice with neighbours `dx` apart, with
+    /// the scale of `stencil`.
+    ///
+    /// In the axial coordinates of the lattice, whose axes are 60 degrees apart, the neighbours
+    /// are at `(±1, 0)`, `(0, ±1)`, `(1, -1)` and `(-1, 1)`, so that the operator has the same
+    /// weights as a square stencil without the diagonal. The Laplacian is `2 / (3 dx^2)` times the
+    /// sum of the differences with the neighbours.
+    pub(crate) fn hexagonal(stencil: Stencil, dx: f32) -> Self {
+        let weight = stencil.scale() * 2.0 / (3.0 * dx * dx);
+        Self {
+            x: weight,
+            y: weight,
+            diagonal: 0.0,
+            antidiagonal: weight,
+            x2: 0.0,
+            y2: 0.0,
+        }
+    }
+
+    /// Largest decay rate of the Fourier modes under the discrete operator, sampled over the
+    /// Brillouin zone
+    pub(crate) fn spectral_radius(&self) -> f32 {
+        let pi = std::f32::consts::PI;
+        let mut radius = 0.0f32;
+        for i in 0..=Self::SAMPLES {
+            let kx = pi * i as f32 / Self::SAMPLES as f32;
+            for j in 0..=2 * Self::SAMPLES {
+                let ky = pi * (j as f32 / Self::SAMPLES as f32 - 1.0);
+                radius = radius.max(self.decay_rate(kx, ky));
+            }
+        }
+        radius
+    }
+}
+
+/// Weights of the neighbours of a cell in the discrete diffusion operator of 3D volumes, by
+/// distance to the cell
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct VolumeWeights {
+    /// The 6 neighbours sharing a face with the cell
+    pub(crate) face: f32,
+    /// The 12 neighbours sharing an edge with the cell
+    pub(crate) edge: f32,
+    /// The 8 neighbours sharing a corner with the cell
+    pub(crate) corner: f32,
+}
+
+impl VolumeWeights {
+    /// Number of samples of the wave numbers along each axis in `spectral_radius`
+    const SAMPLES: u32 = 16;
+
+    /// Weights of `stencil` with the scale of the 2D `scale_stencil`, on a grid of spacing `dx`
+    pub(crate) fn new(stencil: VolumeStencil, scale_stencil: Stencil, dx: f32) -> Self {
+        let (face, edge, corner) = match stencil {
+            VolumeStencil::SevenPoint => (1.0, 0.0, 0.0),
+            VolumeStencil::NineteenPoint => (1.0 / 3.0, 1.0 / 6.0, 0.0),
+            VolumeStencil::TwentySevenPoint => (14.0 / 30.0, 3.0 / 30.0, 1.0 / 30.0),
+        };
+        let scale = scale_stencil.scale() / (dx * dx);
+        Self {
+            face: scale * face,
+            edge: scale * edge,
+            corner: scale * corner,
+        }
+    }
+
+    /// Largest decay rate of the Fourier modes under the discrete operator, see
+    /// `Weights::spectral_radius`. The operator being symmetric along each axis, only the wave
+    /// vectors with non negative components are sampled.
+    pub(crate) fn spectral_radius(&self) -> f32 {
+        let pi = std::f32::consts::PI;
+        let mut radius = 0.0f32;
+        for i in 0..=Self::SAMPLES {
+            let cx = (pi * i as f32 / Self::SAMPLES as f32).cos();
+            for j in 0..=Self::SAMPLES {
+                let cy = (pi * j as f32 / Self::SAMPLES as f32).cos();
+                for k in 0..=Self::SAMPLES {
+                    let cz = (pi * k as f32 / Self::SAMPLES as f32).cos();
+                    // sums of cos(k . offset) over the neighbours, the sines cancelling out
+                    let faces = 2.0 * (cx + cy + cz);
+                    let edges = 4.0 * (cx * cy + cy * cz + cz * cx);
+                    let corners = 8.0 * cx * cy * cz;
+                    let decay_rate = self.face * (6.0 - faces)
+                        + self.edge * (12.0 - edges)
+                        + self.corner * (8.0 - corners);
+                    radius = radius.max(decay_rate);
+                }
+            }
+        }
+        radius
+    }
+}
+
+impl Stencil {
+    /// Largest distance of the neighbours used by the stencil, along each axis
+    pub(crate) fn radius(self) -> u32 {
+        match self {
+            Stencil::ThirteenPoint => 2,
+            _ => 1,
+        }
+    }
+
+    /// Coefficients of the second derivative along an axis, `(near, far, corners)`.
+    ///
+    /// The derivative along x is the sum of `near` times `u(1, 0) + u(-1, 0) - 2 u`, `far` times
+    /// `u(2, 0) + u(-2, 0) - 2 u`, and `corners` times the second differences along x of the
+    /// rows above and below, and the one along y is symmetric.
+    fn second_derivative(self) -> (f32, f32, f32) {
+        match self {
+            Stencil::FivePoint => (1.0, 0.0, 0.0),
+            // edges 2/3 and corners 1/6 for the Laplacian
+            Stencil::Sims | Stencil::Isotropic => (5.0 / 6.0, 0.0, 1.0 / 12.0),
+            // edges 1/2 and corners 1/4 for the Laplacian
+            Stencil::OonoPuri => (3.0 / 4.0, 0.0, 1.0 / 8.0),
+            // edges 4/3 and cells two apart along the axes -1/12 for the Laplacian, fourth order
+            // accurate, which requires the corners of the neighbourhood to have no weight
+            Stencil::ThirteenPoint => (4.0 / 3.0, -1.0 / 12.0, 0.0),
+        }
+    }
+
+    /// Factor of the stencil relative to the Laplacian
+    pub(crate) fn scale(self) -> f32 {
+        match self {
+            Stencil::Sims => 0.3,
+            _ => 1.0,
+        }
+    }
+}
+
+/// Anisotropic diffusion tensor, scaling `diffusion_a` and `diffusion_b`.
+///
+/// The tensor has the coefficients `x` and `y` along its principal axes, the first of which is
+/// rotated by `angle` degrees from the x axis of the grid towards its y axis.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct DiffusionTensor {
+    pub(crate) x: f32,
+    pub(crate) y: f32,
+    pub(crate) angle: f32,
+}
+
+impl DiffusionTensor {
+    pub(crate) fn new(config: &Config) -> Self {
+        Self {
+            x: config.diffusion_x,
+            y: config.diffusion_y,
+            angle: config.diffusion_angle,
+        }
+    }
+
+    /// Weights of the discrete operator `div(D grad u)` with the given stencil, on a grid with
+    /// the spacing `(dx, dy)`.
+    ///
+    /// With the identity tensor and a unit spacing, these are the weights of the usual isotropic
+    /// stencil.
+    pub(crate) fn weights(&self, stencil: Stencil, spacing: (f32, f32)) -> Weights {
+        let (dxx, dyy, dxy) = self.coefficients(spacing);
+
+        // the mixed derivative is (u(1, 1) + u(-1, -1) - u(1, -1) - u(-1, 1)) / 4
+        let (near, far, corners) = stencil.second_derivative();
+        let scale = stencil.scale();
+        Weights {
+            x: scale * (near * dxx - 2.0 * corners * dyy),
+            y: scale * (near * dyy - 2.0 * corners * dxx),
+            diagonal: scale * (corners * (dxx + dyy) + dxy / 2.0),
+            antidiagonal: scale * (corners * (dxx + dyy) - dxy / 2.0),
+            x2: scale * far * dxx,
+            y2: scale * far * dyy,
+        }
+    }
+
+    /// Weights of the 5-point operator with the scale of `stencil`, which only keeps the second
+    /// derivatives along the axes, as used by the ADI solver
+    pub(crate) fn axis_weights(&self, stencil: Stencil, spacing: (f32, f32)) -> Weights {
+        let (dxx, dyy, _) = self.coefficients(spacing);
+        let scale = stencil.scale();
+        Weights {
+            x: scale * dxx,
+            y: scale * dyy,
+            diagonal: 0.0,
+            antidiagonal: 0.0,
+            x2: 0.0,
+            y2: 0.0,
+        }
+    }
+
+    /// Whether the tensor has a mixed derivative term, when it is anisotropic and rotated
+    pub(crate) fn has_mixed_derivative(&self) -> bool {
+        self.coefficients((1.0, 1.0)).2.abs() > 1e-6
+    }
+
+    /// Coefficients `(dxx, dyy, dxy)` of the derivatives in `div(D grad u)`, divided by the
+    /// spacing
+    fn coefficients(&self, (dx, dy): (f32, f32)) -> (f32, f32, f32) {
+        let (sin, cos) = self.angle.to_radians().sin_cos();
+        let dxx = (self.x * cos * cos + self.y * sin * sin) / (dx * dx);
+        let dyy = (self.x * sin * sin + self.y * cos * cos) / (dy * dy);
+        let dxy = (self.x - self.y) * sin * cos / (dx * dy);
+        (dxx, dyy, dxy)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DiffusionTensor;
+    use crate::config::Stencil;
+
+    /// Sum of `w x^i y^j` over the neighbours (x, y) of the isotropic stencil, with weights `w`
+    /// divided by its scale
+    fn moment(stencil: Stencil, i: i32, j: i32) -> f32 {
+        let tensor = DiffusionTensor {
+            x: 1.0,
+            y: 1.0,
+            angle: 0.0,
+        };
+        let w = tensor.weights(stencil, (1.0, 1.0));
+        let neighbours = [
+            ((1, 0), w.x),
+            ((0, 1), w.y),
+            ((1, 1), w.diagonal),
+            ((1, -1), w.antidiagonal),
+            ((2, 0), w.x2),
+            ((0, 2), w.y2),
+        ];
+        // each neighbour and its opposite
+        let sum: f32 = neighbours
+            .iter()
+            .flat_map(|&((x, y), w)| [((x, y), w), ((-x, -y), w)])
+            .map(|((x, y), w)| w * (x as f32).powi(i) * (y as f32).powi(j))
+            .sum();
+        sum / stencil.scale()
+    }
+
+    #[test]
+    fn stencils_are_consistent_with_the_laplacian() {
+        for stencil in [
+            Stencil::FivePoint,
+            Stencil::Sims,
+            Stencil::Isotropic,
+            Stencil::OonoPuri,
+            Stencil::ThirteenPoint,
+        ] {
+            // the second order terms of the Taylor expansion give the Laplacian
+            assert!((moment(stencil, 2, 0) - 2.0).abs() < 1e-6, "{:?}", stencil);
+            assert!((moment(stencil, 0, 2) - 2.0).abs() < 1e-6, "{:?}", stencil);
+            assert!(moment(stencil, 1, 1).abs() < 1e-6, "{:?}", stencil);
+        }
+    }
+
+    #[test]
+    fn thirteen_point_stencil_is_fourth_order() {
+        // the fourth order terms of the Taylor expansion vanish, leaving an error in h^4
+        for (i, j) in [(4, 0), (0, 4), (2, 2), (3, 1), (1, 3)] {
+            let moment = moment(Stencil::ThirteenPoint, i, j);
+            assert!(moment.abs() < 1e-6, "moment ({}, {}) is {}", i, j, moment);
+        }
+        // unlike the 9-point stencils, second order only
+        assert!(moment(Stencil::Isotropic, 4, 0).abs() > 0.1);
+    }
+}
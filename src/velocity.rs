@@ -0,0 +1,192 @@
+use crate::config::VelocityField;
+
+/// Velocity in each cell of the built-in `field`, row by row, with a largest speed of 1.
+///
+/// The uniform flow goes along `angle` degrees from the x axis, the vortex turns around the centre
+/// of the domain, vanishing at the edges so that it stays continuous across the periodic
+/// boundaries, and the shear flow goes along x with a sinusoidal profile along y.
+pub(crate) fn field(field: VelocityField, width: u32, height: u32, angle: f32) -> Vec<[f32; 2]> {
+    let (sin, cos) = angle.to_radians().sin_cos();
+    let centre = (width as f32 / 2.0, height as f32 / 2.0);
+    let radius = width.min(height) as f32 / 2.0;
+    let mut velocity = Vec::with_capacity((width * height) as usize);
+    for y in 0..height {
+        for x in 0..width {
+            velocity.push(match field {
+                VelocityField::None => [0.0, 0.0],
+                VelocityField::Uniform => [cos, sin],
+                VelocityField::Vortex => {
+                    let (dx, dy) = (x as f32 + 0.5 - centre.0, y as f32 + 0.5 - centre.1);
+                    let r = dx.hypot(dy);
+                    if r > 0.0 && r < radius {
+                        let speed = (std::f32::consts::PI * r / radius).sin();
+                        [-dy / r * speed, dx / r * speed]
+                    } else {
+                        [0.0, 0.0]
+                    }
+                }
+                VelocityField::Shear => {
+                    let phase = std::f32::consts::TAU * (y as f32 + 0.5) / height as f32;
+                    [phase.sin(), 0.0]
+                }
+            });
+        }
+    }
+    velocity
+}
+
+/// Load a velocity field from the bytes of a PNG image or a `.npy` array, told apart by their
+/// magic strings, resampled to the size of the grid.
+///
+/// The red and green channels of the image hold the velocity along x and y, from -1 at 0 to 1 at
+/// the largest value. The array must have a shape of (rows, columns, 2) and hold `f32` or `f64`
+/// values, used as they are.
+pub(crate) fn load(bytes: &[u8], width: u32, height: u32) -> Result<Vec<[f32; 2]>, String> {
+    let (columns, rows, values) = if bytes.starts_with(NPY_MAGIC) {
+        load_npy(bytes)?
+    } else {
+        load_png(bytes)?
+    };
+    if columns == 0 || rows == 0 {
+        return Err("empty velocity field".to_string());
+    }
+
+    // nearest neighbour resampling
+    let mut velocity = Vec::with_capacity((width * height) as usize);
+    for y in 0..height {
+        let row = (y as u64 * rows as u64 / height as u64) as usize;
+        for x in 0..width {
+            let column = (x as u64 * columns as u64 / width as u64) as usize;
+            velocity.push(values[column + row * columns]);
+        }
+    }
+    Ok(velocity)
+}
+
+/// Width, height and velocities of a PNG image, see `load`
+fn load_png(bytes: &[u8]) -> Result<(usize, usize, Vec<[f32; 2]>), String> {
+    let mut decoder = png::Decoder::new(bytes);
+    decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+    let mut reader = decoder.read_info().map_err(|e| e.to_string())?;
+    let mut pixels = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut pixels).map_err(|e| e.to_string())?;
+
+    let channels = info.color_type.samples();
+    if channels < 3 {
+        return Err(
+            "expected a color image, with the velocity in the red and green channels".into(),
+        );
+    }
+    let component = |value: u8| value as f32 / 127.5 - 1.0;
+    let values = pixels
+        .chunks(info.line_size)
+        .take(info.height as usize)
+        .flat_map(|line| {
+            line.chunks(channels)
+                .take(info.width as usize)
+                .map(|pixel| [component(pixel[0]), component(pixel[1])])
+        })
+        .collect();
+    Ok((info.width as usize, info.height as usize, values))
+}
+
+/// Width, height and velocities of a `.npy` array, see `load`
+fn load_npy(bytes: &[u8]) -> Result<(usize, usize, Vec<[f32; 2]>), String> {
+    let (header, data) = npy_header(bytes).ok_or("not a valid .npy file")?;
+
+    let descr = header_value(header, "descr").ok_or("missing descr in the .npy header")?;
+    let fortran_order = header_value(header, "fortran_order");
+    if fortran_order != Some("False") {
+        return Err("only arrays in C order are supported".to_string());
+    }
+    let shape: Vec<usize> = header_value(header, "shape")
+        .ok_or("missing shape in the .npy header")?
+        .trim_matches(|c| c == '(' || c == ')')
+        .split(',')
+        .map(str::trim)
+        .filter(|dimension| !dimension.is_empty())
+        .map(|dimension| {
+            dimension
+                .parse()
+                .map_err(|_| "invalid shape in the .npy header")
+        })
+        .collect::<Result<_, _>>()?;
+    let [rows, columns, 2] = shape[..] else {
+        return Err(format!(
+            "expected an array of shape (rows, columns, 2), found {:?}",
+            shape
+        ));
+    };
+
+    let descr = descr.trim_matches('\'');
+    let item_size = match descr {
+        "<f4" => 4,
+        "<f8" => 8,
+        descr => {
+            return Err(format!(
+                "expected an array of float32 or float64, found {}",
+                descr
+            ))
+        }
+    };
+    // the shape is checked against the data before anything is allocated
+    let length = rows
+        .checked_mul(columns)
+        .and_then(|cells| cells.checked_mul(2 * item_size))
+        .ok_or_else(|| format!("the shape {:?} of the .npy array is too large", shape))?;
+    if data.len() != length {
+        return Err(format!(
+            "the .npy array of shape {:?} needs {} bytes of data, found {}",
+            shape,
+            length,
+            data.len()
+        ));
+    }
+    let components: Vec<f32> = if descr == "<f4" {
+        data.chunks_exact(4)
+            .map(|bytes| f32::from_le_bytes(bytes.try_into().unwrap()))
+            .collect()
+    } else {
+        data.chunks_exact(8)
+            .map(|bytes| f64::from_le_bytes(bytes.try_into().unwrap()) as f32)
+            .collect()
+    };
+    let values = components
+        .chunks_exact(2)
+        .map(|velocity| [velocity[0], velocity[1]])
+        .collect();
+    Ok((columns, rows, values))
+}
+
+/// Start of the `.npy` files
+const NPY_MAGIC: &[u8] = b"\x93NUMPY";
+
+/// Header and data of a `.npy` file
+fn npy_header(bytes: &[u8]) -> Option<(&str, &[u8])> {
+    let version = *bytes.strip_prefix(NPY_MAGIC)?.first()?;
+    let (length, start) = match version {
+        1 => (
+            u16::from_le_bytes(bytes.get(8..10)?.try_into().ok()?) as usize,
+            10,
+        ),
+        2 | 3 => (
+            u32::from_le_bytes(bytes.get(8..12)?.try_into().ok()?) as usize,
+            12,
+        ),
+        _ => return None,
+    };
+    let header = std::str::from_utf8(bytes.get(start..start + length)?).ok()?;
+    Some((header, &bytes[start + length..]))
+}
+
+/// Value of `key` in the dictionary of a `.npy` header, as written by numpy
+fn header_value<'a>(header: &'a str, key: &str) -> Option<&'a str> {
+    let start = header.find(&format!("'{}':", key))? + key.len() + 3;
+    let value = header[start..].trim_start();
+    let end = if value.starts_with('(') {
+        value.find(')')? + 1
+    } else {
+        value.find([',', '}'])?
+    };
+    Some(value[..end].trim())
+}